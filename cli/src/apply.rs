@@ -0,0 +1,551 @@
+use std::collections::BTreeSet;
+use std::path::Path;
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use serde_json::Value;
+use common::manifest::{parse_manifests, ManifestDocument};
+use common::{CronJob, CronJobSpec, DryRun, Job, JobSpec, Manifest, ManifestFormat, OrchError, Service, ServiceSpec, Task, TaskSpec, TaskStatus, Workflow, WorkflowSpec};
+use crate::MANAGER_URL;
+
+/// What `apply` or `delete` did to a resource.
+enum Outcome {
+    Created,
+    Configured,
+    /// The spec was immutable, so the resource was deleted and created again
+    Replaced,
+    Unchanged,
+    Deleted,
+    NotFound,
+}
+
+impl std::fmt::Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let outcome = match self {
+            Outcome::Created => "created",
+            Outcome::Configured => "configured",
+            Outcome::Replaced => "replaced",
+            Outcome::Unchanged => "unchanged",
+            Outcome::Deleted => "deleted",
+            Outcome::NotFound => "not found",
+        };
+        write!(f, "{}", outcome)
+    }
+}
+
+/// Creates or updates every resource of the manifest file, matched by name.
+///
+/// With `dry_run` the manager only validates the resources and tells where they would be scheduled.
+pub fn apply(client: &Client, path: &Path, dry_run: bool) -> anyhow::Result<()> {
+    for_each_resource(path, |document| match &document.manifest {
+        Manifest::Task(spec) => apply_task(client, spec, dry_run),
+        Manifest::Service(spec) => apply_service(client, spec, dry_run),
+        Manifest::Job(spec) => apply_job(client, spec, dry_run),
+        Manifest::CronJob(spec) => apply_cronjob(client, spec, dry_run),
+        Manifest::Workflow(spec) => apply_workflow(client, spec, dry_run),
+    })
+}
+
+/// Deletes every resource of the manifest file, matched by name.
+pub fn delete(client: &Client, path: &Path) -> anyhow::Result<()> {
+    for_each_resource(path, |document| match &document.manifest {
+        Manifest::Task(spec) => delete_task(client, &spec.name).map(|outcome| (outcome, None)),
+        Manifest::Service(spec) => delete_service(client, &spec.name).map(|outcome| (outcome, None)),
+        Manifest::Job(spec) => delete_job(client, &spec.name).map(|outcome| (outcome, None)),
+        Manifest::CronJob(spec) => delete_cronjob(client, &spec.name).map(|outcome| (outcome, None)),
+        Manifest::Workflow(spec) => delete_workflow(client, &spec.name).map(|outcome| (outcome, None)),
+    })
+}
+
+/// Prints the fields of each resource that differ between the cluster and the manifest file.
+pub fn diff(client: &Client, path: &Path) -> anyhow::Result<()> {
+    let (_, documents) = load(path)?;
+
+    for document in &documents {
+        let resource = format!("{}/{}", document.manifest.kind(), document.manifest.name());
+
+        // the live spec, the desired one, and the status of a task, job or workflow that would have to be replaced
+        let (live, desired, replaced) = match &document.manifest {
+            Manifest::Task(spec) => {
+                let live = find_live_task(client, &spec.name)?;
+                let replaced = live
+                    .as_ref()
+                    .map(|t| t.status.clone())
+                    .filter(|s| *s != TaskStatus::Pending)
+                    .map(|s| format!("{:?}", s));
+                let live = live.map(|t| serde_json::to_value(TaskSpec::from_task(&t))).transpose()?;
+                (live, serde_json::to_value(spec)?, replaced)
+            }
+            Manifest::Service(spec) => {
+                let live = find_live_service(client, &spec.name)?;
+                let live = live.map(|s| serde_json::to_value(ServiceSpec::from_service(&s))).transpose()?;
+                (live, serde_json::to_value(spec)?, None)
+            }
+            Manifest::Job(spec) => {
+                let live = find_live_job(client, &spec.name)?;
+                let replaced = live
+                    .as_ref()
+                    .filter(|j| !spec.immutable_changes(j).is_empty())
+                    .map(|j| format!("{:?}", j.status.state));
+                let live = live.map(|j| serde_json::to_value(JobSpec::from_job(&j))).transpose()?;
+                (live, serde_json::to_value(spec)?, replaced)
+            }
+            Manifest::CronJob(spec) => {
+                let live = find_live_cronjob(client, &spec.name)?;
+                let live = live.map(|c| serde_json::to_value(CronJobSpec::from_cronjob(&c))).transpose()?;
+                (live, serde_json::to_value(spec)?, None)
+            }
+            Manifest::Workflow(spec) => {
+                let live = find_live_workflow(client, &spec.name)?;
+                let replaced = live
+                    .as_ref()
+                    .filter(|w| !spec.immutable_changes(w).is_empty())
+                    .map(|w| format!("{:?}", w.status.state));
+                let live = live.map(|w| serde_json::to_value(WorkflowSpec::from_workflow(&w))).transpose()?;
+                (live, serde_json::to_value(spec)?, replaced)
+            }
+        };
+
+        let Some(live) = live else {
+            println!("+ {} (will be created)", resource);
+            continue;
+        };
+
+        let mut changes = Vec::new();
+        diff_values("", &live, &desired, &mut changes);
+        if changes.is_empty() {
+            println!("  {} (unchanged)", resource);
+            continue;
+        }
+
+        match replaced {
+            None => println!("~ {}", resource),
+            Some(status) => println!("~ {} (is {}, will be replaced)", resource, status),
+        }
+        for (field, from, to) in changes {
+            println!("    {}: {} -> {}", field, from, to);
+        }
+    }
+
+    Ok(())
+}
+
+fn load(path: &Path) -> anyhow::Result<(String, Vec<ManifestDocument>)> {
+    let source = std::fs::read_to_string(path)?;
+    let format = ManifestFormat::from_path(path)?;
+    let documents = parse_manifests(&source, format).map_err(|e| match e.line {
+        Some(line) => anyhow::anyhow!("{}:{}: {}", path.display(), line, e.message),
+        None => anyhow::anyhow!("{}: {}", path.display(), e.message),
+    })?;
+
+    Ok((source, documents))
+}
+
+/// Runs `action` on each resource of the file and prints the outcome, followed by the note it returned.
+///
+/// Errors are reported as `file:line` and don't stop the remaining resources,
+/// validation errors point at the offending field.
+fn for_each_resource(
+    path: &Path,
+    mut action: impl FnMut(&ManifestDocument) -> Result<(Outcome, Option<String>), OrchError>,
+) -> anyhow::Result<()> {
+    let (source, documents) = load(path)?;
+
+    let mut failed = 0;
+    for document in &documents {
+        let resource = format!("{}/{}", document.manifest.kind(), document.manifest.name());
+
+        if let Manifest::Task(spec) = &document.manifest {
+            for warning in spec.warnings() {
+                let line = document.locate(&source, &warning.field);
+                eprintln!("{}:{}: {}: warning: {}", path.display(), line, resource, warning);
+            }
+        }
+
+        match action(document) {
+            Ok((outcome, None)) => println!("{} {}", resource, outcome),
+            Ok((outcome, Some(note))) => println!("{} {} ({})", resource, outcome, note),
+            Err(OrchError::ValidationFailed(errors)) => {
+                failed += 1;
+                for error in errors {
+                    let line = document.locate(&source, &error.field);
+                    eprintln!("{}:{}: {}: {}", path.display(), line, resource, error);
+                }
+            }
+            Err(e) => {
+                failed += 1;
+                eprintln!("{}:{}: {}: {}", path.display(), document.lines.start + 1, resource, e);
+            }
+        }
+    }
+
+    if failed > 0 {
+        anyhow::bail!("{} of {} resources failed", failed, documents.len());
+    }
+    Ok(())
+}
+
+/// Collects `(field, live, desired)` for every leaf that differs, objects are compared key by key
+/// and lists of objects of the same length, like the steps of a workflow, item by item.
+fn diff_values(path: &str, live: &Value, desired: &Value, changes: &mut Vec<(String, String, String)>) {
+    if let (Value::Array(live), Value::Array(desired)) = (live, desired)
+        && live.len() == desired.len()
+        && live.iter().chain(desired).all(Value::is_object)
+    {
+        for (i, (live, desired)) in live.iter().zip(desired).enumerate() {
+            diff_values(&format!("{}[{}]", path, i), live, desired, changes);
+        }
+    } else if let (Value::Object(live), Value::Object(desired)) = (live, desired) {
+        let keys: BTreeSet<&String> = live.keys().chain(desired.keys()).collect();
+        for key in keys {
+            let field = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+            diff_values(
+                &field,
+                live.get(key).unwrap_or(&Value::Null),
+                desired.get(key).unwrap_or(&Value::Null),
+                changes,
+            );
+        }
+    } else if live != desired {
+        changes.push((path.to_string(), show(live), show(desired)));
+    }
+}
+
+fn show(value: &Value) -> String {
+    match value {
+        Value::Null => "<none>".to_string(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn apply_task(client: &Client, spec: &TaskSpec, dry_run: bool) -> Result<(Outcome, Option<String>), OrchError> {
+    let query: &[(&str, &str)] = if dry_run { &[("dryRun", "true")] } else { &[] };
+    let create = || client.post(format!("{}/tasks", MANAGER_URL)).query(query).json(spec);
+
+    let (outcome, response) = match find_live_task(client, &spec.name)? {
+        None => (Outcome::Created, send(create())?),
+        Some(task) if TaskSpec::from_task(&task) == *spec => return Ok((Outcome::Unchanged, None)),
+        Some(task) if task.status == TaskStatus::Pending => {
+            let response = send(
+                client
+                    .put(format!("{}/tasks/{}", MANAGER_URL, task.id))
+                    .query(query)
+                    .header("If-Match", format!("\"{}\"", task.resource_version))
+                    .json(spec),
+            )?;
+            (Outcome::Configured, response)
+        }
+        Some(task) => {
+            // checking the new spec is all a dry run can do, the old task stays
+            if !dry_run {
+                send(
+                    client
+                        .delete(format!("{}/tasks/{}", MANAGER_URL, task.id))
+                        .header("If-Match", format!("\"{}\"", task.resource_version)),
+                )?;
+            }
+            (Outcome::Replaced, send(create())?)
+        }
+    };
+
+    if !dry_run {
+        return Ok((outcome, None));
+    }
+
+    let result: DryRun = response
+        .json()
+        .map_err(|e| OrchError::NetworkError(format!("Failed to decode dry run: {}", e)))?;
+    let note = match (result.node_id, result.unschedulable) {
+        (Some(node_id), _) => format!("dry run, would run on {}", node_id),
+        (None, Some(reason)) => format!("dry run, unschedulable: {}", reason),
+        (None, None) => "dry run".to_string(),
+    };
+    Ok((outcome, Some(note)))
+}
+
+fn delete_task(client: &Client, name: &str) -> Result<Outcome, OrchError> {
+    let Some(task) = find_live_task(client, name)? else {
+        return Ok(Outcome::NotFound);
+    };
+
+    send(client.delete(format!("{}/tasks/{}", MANAGER_URL, task.id)))?;
+    Ok(Outcome::Deleted)
+}
+
+/// Creates the service or updates its spec, the manager's controller does the rest.
+///
+/// The manager has no dry run for services, so a dry run only validates the spec here.
+fn apply_service(client: &Client, spec: &ServiceSpec, dry_run: bool) -> Result<(Outcome, Option<String>), OrchError> {
+    let live = find_live_service(client, &spec.name)?;
+    let outcome = match &live {
+        None => Outcome::Created,
+        Some(service) if ServiceSpec::from_service(service) == *spec => return Ok((Outcome::Unchanged, None)),
+        Some(_) => Outcome::Configured,
+    };
+
+    if dry_run {
+        let errors = spec.validate();
+        if !errors.is_empty() {
+            return Err(OrchError::ValidationFailed(errors));
+        }
+        return Ok((outcome, Some("dry run".to_string())));
+    }
+
+    match live {
+        None => send(client.post(format!("{}/services", MANAGER_URL)).json(spec))?,
+        Some(service) => send(
+            client
+                .put(format!("{}/services/{}", MANAGER_URL, service.name))
+                .header("If-Match", format!("\"{}\"", service.resource_version))
+                .json(spec),
+        )?,
+    };
+    Ok((outcome, None))
+}
+
+fn delete_service(client: &Client, name: &str) -> Result<Outcome, OrchError> {
+    match send(client.delete(format!("{}/services/{}", MANAGER_URL, name))) {
+        Ok(_) => Ok(Outcome::Deleted),
+        Err(OrchError::ServiceNotFound(_)) => Ok(Outcome::NotFound),
+        Err(e) => Err(e),
+    }
+}
+
+/// Creates the job, updates what may change while it runs, or replaces it when the rest changed.
+///
+/// Like for services, a dry run only validates the spec here.
+fn apply_job(client: &Client, spec: &JobSpec, dry_run: bool) -> Result<(Outcome, Option<String>), OrchError> {
+    let live = find_live_job(client, &spec.name)?;
+    let outcome = match &live {
+        None => Outcome::Created,
+        Some(job) if JobSpec::from_job(job) == *spec => return Ok((Outcome::Unchanged, None)),
+        Some(job) if spec.immutable_changes(job).is_empty() => Outcome::Configured,
+        Some(_) => Outcome::Replaced,
+    };
+
+    if dry_run {
+        let errors = spec.validate();
+        if !errors.is_empty() {
+            return Err(OrchError::ValidationFailed(errors));
+        }
+        return Ok((outcome, Some("dry run".to_string())));
+    }
+
+    let create = || client.post(format!("{}/jobs", MANAGER_URL)).json(spec);
+    match (&outcome, live) {
+        (Outcome::Configured, Some(job)) => send(
+            client
+                .put(format!("{}/jobs/{}", MANAGER_URL, job.name))
+                .header("If-Match", format!("\"{}\"", job.resource_version))
+                .json(spec),
+        )?,
+        (Outcome::Replaced, Some(job)) => {
+            send(
+                client
+                    .delete(format!("{}/jobs/{}", MANAGER_URL, job.name))
+                    .header("If-Match", format!("\"{}\"", job.resource_version)),
+            )?;
+            send(create())?
+        }
+        _ => send(create())?,
+    };
+    Ok((outcome, None))
+}
+
+fn delete_job(client: &Client, name: &str) -> Result<Outcome, OrchError> {
+    match send(client.delete(format!("{}/jobs/{}", MANAGER_URL, name))) {
+        Ok(_) => Ok(Outcome::Deleted),
+        Err(OrchError::JobNotFound(_)) => Ok(Outcome::NotFound),
+        Err(e) => Err(e),
+    }
+}
+
+/// The job a manifest manages.
+fn find_live_job(client: &Client, name: &str) -> Result<Option<Job>, OrchError> {
+    match send(client.get(format!("{}/jobs/{}", MANAGER_URL, name))) {
+        Ok(response) => response
+            .json()
+            .map(Some)
+            .map_err(|e| OrchError::NetworkError(format!("Failed to decode job: {}", e))),
+        Err(OrchError::JobNotFound(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Creates the cron job or updates its spec, jobs already started keep their template.
+///
+/// Like for services, a dry run only validates the spec here.
+fn apply_cronjob(client: &Client, spec: &CronJobSpec, dry_run: bool) -> Result<(Outcome, Option<String>), OrchError> {
+    let live = find_live_cronjob(client, &spec.name)?;
+    let outcome = match &live {
+        None => Outcome::Created,
+        Some(cronjob) if CronJobSpec::from_cronjob(cronjob) == *spec => return Ok((Outcome::Unchanged, None)),
+        Some(_) => Outcome::Configured,
+    };
+
+    if dry_run {
+        let errors = spec.validate();
+        if !errors.is_empty() {
+            return Err(OrchError::ValidationFailed(errors));
+        }
+        return Ok((outcome, Some("dry run".to_string())));
+    }
+
+    match live {
+        None => send(client.post(format!("{}/cronjobs", MANAGER_URL)).json(spec))?,
+        Some(cronjob) => send(
+            client
+                .put(format!("{}/cronjobs/{}", MANAGER_URL, cronjob.name))
+                .header("If-Match", format!("\"{}\"", cronjob.resource_version))
+                .json(spec),
+        )?,
+    };
+    Ok((outcome, None))
+}
+
+fn delete_cronjob(client: &Client, name: &str) -> Result<Outcome, OrchError> {
+    match send(client.delete(format!("{}/cronjobs/{}", MANAGER_URL, name))) {
+        Ok(_) => Ok(Outcome::Deleted),
+        Err(OrchError::CronJobNotFound(_)) => Ok(Outcome::NotFound),
+        Err(e) => Err(e),
+    }
+}
+
+/// The cron job a manifest manages.
+fn find_live_cronjob(client: &Client, name: &str) -> Result<Option<CronJob>, OrchError> {
+    match send(client.get(format!("{}/cronjobs/{}", MANAGER_URL, name))) {
+        Ok(response) => response
+            .json()
+            .map(Some)
+            .map_err(|e| OrchError::NetworkError(format!("Failed to decode cron job: {}", e))),
+        Err(OrchError::CronJobNotFound(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// The service a manifest manages.
+fn find_live_service(client: &Client, name: &str) -> Result<Option<Service>, OrchError> {
+    match send(client.get(format!("{}/services/{}", MANAGER_URL, name))) {
+        Ok(response) => response
+            .json()
+            .map(Some)
+            .map_err(|e| OrchError::NetworkError(format!("Failed to decode service: {}", e))),
+        Err(OrchError::ServiceNotFound(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// The task a manifest manages, names are unique so there's at most one.
+fn find_live_task(client: &Client, name: &str) -> Result<Option<Task>, OrchError> {
+    match send(client.get(format!("{}/tasks/by-name/{}", MANAGER_URL, name))) {
+        Ok(response) => response
+            .json()
+            .map(Some)
+            .map_err(|e| OrchError::NetworkError(format!("Failed to decode task: {}", e))),
+        Err(OrchError::TaskNotFound(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Sends the request, turning error responses back into the manager's `OrchError`.
+fn send(request: RequestBuilder) -> Result<Response, OrchError> {
+    let response = request
+        .send()
+        .map_err(|e| OrchError::NetworkError(format!("Failed to reach the manager: {}", e)))?;
+
+    if response.status().is_success() {
+        return Ok(response);
+    }
+
+    let status = response.status();
+    Err(response
+        .json::<OrchError>()
+        .unwrap_or_else(|_| OrchError::NetworkError(format!("Manager responded with {}", status))))
+}
+
+/// Creates the workflow, updates its labels, or replaces it when the steps changed.
+///
+/// Like for services, a dry run only validates the spec here.
+fn apply_workflow(client: &Client, spec: &WorkflowSpec, dry_run: bool) -> Result<(Outcome, Option<String>), OrchError> {
+    let live = find_live_workflow(client, &spec.name)?;
+    let outcome = match &live {
+        None => Outcome::Created,
+        Some(workflow) if WorkflowSpec::from_workflow(workflow) == *spec => return Ok((Outcome::Unchanged, None)),
+        Some(workflow) if spec.immutable_changes(workflow).is_empty() => Outcome::Configured,
+        Some(_) => Outcome::Replaced,
+    };
+
+    if dry_run {
+        let errors = spec.validate();
+        if !errors.is_empty() {
+            return Err(OrchError::ValidationFailed(errors));
+        }
+        return Ok((outcome, Some("dry run".to_string())));
+    }
+
+    let create = || client.post(format!("{}/workflows", MANAGER_URL)).json(spec);
+    match (&outcome, live) {
+        (Outcome::Configured, Some(workflow)) => send(
+            client
+                .put(format!("{}/workflows/{}", MANAGER_URL, workflow.name))
+                .header("If-Match", format!("\"{}\"", workflow.resource_version))
+                .json(spec),
+        )?,
+        (Outcome::Replaced, Some(workflow)) => {
+            send(
+                client
+                    .delete(format!("{}/workflows/{}", MANAGER_URL, workflow.name))
+                    .header("If-Match", format!("\"{}\"", workflow.resource_version)),
+            )?;
+            send(create())?
+        }
+        _ => send(create())?,
+    };
+    Ok((outcome, None))
+}
+
+fn delete_workflow(client: &Client, name: &str) -> Result<Outcome, OrchError> {
+    match send(client.delete(format!("{}/workflows/{}", MANAGER_URL, name))) {
+        Ok(_) => Ok(Outcome::Deleted),
+        Err(OrchError::WorkflowNotFound(_)) => Ok(Outcome::NotFound),
+        Err(e) => Err(e),
+    }
+}
+
+/// The workflow a manifest manages.
+fn find_live_workflow(client: &Client, name: &str) -> Result<Option<Workflow>, OrchError> {
+    match send(client.get(format!("{}/workflows/{}", MANAGER_URL, name))) {
+        Ok(response) => response
+            .json()
+            .map(Some)
+            .map_err(|e| OrchError::NetworkError(format!("Failed to decode workflow: {}", e))),
+        Err(OrchError::WorkflowNotFound(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_diff_values() {
+        let live = json!({"image": "nginx", "memory": 256, "env": {"A": "1"}, "liveness_probe": null});
+        let desired = json!({"image": "nginx:1.27", "memory": 256, "env": {"B": "2"}, "liveness_probe": null});
+
+        let mut changes = Vec::new();
+        diff_values("", &live, &desired, &mut changes);
+
+        let expected = [("env.A", "1", "<none>"), ("env.B", "<none>", "2"), ("image", "nginx", "nginx:1.27")];
+        assert_eq!(
+            changes,
+            expected.map(|(f, a, b)| (f.to_string(), a.to_string(), b.to_string())).to_vec()
+        );
+
+        let live = json!({"steps": [{"name": "a", "depends_on": []}, {"name": "b", "depends_on": ["a"]}]});
+        let desired = json!({"steps": [{"name": "a", "depends_on": []}, {"name": "b", "depends_on": ["a", "c"]}]});
+        let mut changes = Vec::new();
+        diff_values("", &live, &desired, &mut changes);
+        assert_eq!(changes, [("steps[1].depends_on".to_string(), "[\"a\"]".to_string(), "[\"a\",\"c\"]".to_string())]);
+    }
+}
@@ -0,0 +1,35 @@
+use reqwest::blocking::Client;
+use serde_json::json;
+use common::{CronJob, Job};
+use crate::node::send;
+use crate::output::{print_list, OutputFormat};
+use crate::MANAGER_URL;
+
+/// Prints the cron jobs with their schedule and when they last and next run.
+pub fn list(client: &Client, selector: Option<&str>, output: &OutputFormat) -> anyhow::Result<()> {
+    let mut request = client.get(format!("{}/cronjobs", MANAGER_URL));
+    if let Some(selector) = selector {
+        request = request.query(&[("labelSelector", selector)]);
+    }
+
+    let cronjobs: Vec<CronJob> = send(request)?;
+    print_list(&cronjobs.iter().collect::<Vec<_>>(), output, "No cron jobs found in the cluster.")
+}
+
+/// Starts a job of the cron job right away, whatever its schedule and concurrency policy.
+pub fn trigger(client: &Client, cronjob: &str) -> anyhow::Result<()> {
+    let job: Job = send(client.post(format!("{}/cronjobs/{}/trigger", MANAGER_URL, cronjob)))?;
+    println!("job/{} created", job.name);
+    Ok(())
+}
+
+/// Stops or resumes starting jobs on schedule.
+pub fn suspend(client: &Client, cronjob: &str, suspend: bool) -> anyhow::Result<()> {
+    let cronjob: CronJob = send(
+        client
+            .put(format!("{}/cronjobs/{}/suspend", MANAGER_URL, cronjob))
+            .json(&json!({ "suspend": suspend })),
+    )?;
+    println!("cronjob/{} {}", cronjob.name, if suspend { "suspended" } else { "resumed" });
+    Ok(())
+}
@@ -0,0 +1,74 @@
+use reqwest::blocking::Client;
+use prettytable::{format, row, Table};
+use common::{Job, JobState};
+use crate::node::send;
+use crate::output::{format_labels, print_list, print_one, OutputFormat};
+use crate::MANAGER_URL;
+
+/// Prints the jobs with how many of their completions succeeded.
+pub fn list(client: &Client, selector: Option<&str>, output: &OutputFormat) -> anyhow::Result<()> {
+    let mut request = client.get(format!("{}/jobs", MANAGER_URL));
+    if let Some(selector) = selector {
+        request = request.query(&[("labelSelector", selector)]);
+    }
+
+    let jobs: Vec<Job> = send(request)?;
+    print_list(&jobs.iter().collect::<Vec<_>>(), output, "No jobs found in the cluster.")
+}
+
+/// Prints a job's details and the result of each completion index.
+pub fn describe(client: &Client, job: &str, output: &OutputFormat) -> anyhow::Result<()> {
+    let job: Job = send(client.get(format!("{}/jobs/{}", MANAGER_URL, job)))?;
+    print_one(&job, output, describe_job)
+}
+
+fn describe_job(job: &Job) {
+    let time_format = "%Y-%m-%d %H:%M:%S";
+    let status = &job.status;
+
+    println!("Name:           {}", job.name);
+    println!("Image:          {}", job.template.image);
+    println!("Labels:         {}", format_labels(&job.labels));
+    println!("Completions:    {}/{}", status.succeeded, job.completions);
+    println!("Parallelism:    {}", job.parallelism);
+    println!("Failed:         {} (backoff limit {})", status.failed, job.backoff_limit);
+    match job.ttl_secs_after_finished {
+        Some(ttl) if status.cleaned_up => println!("TTL:            {}s, tasks deleted", ttl),
+        Some(ttl) => println!("TTL:            {}s after finishing", ttl),
+        None => println!("TTL:            -"),
+    }
+    println!("Status:         {:?}", status.state);
+    if let Some(message) = &status.message {
+        println!("Message:        {}", message);
+    }
+    println!("Created:        {}", job.created_at.format(time_format));
+    if let Some(finished_at) = status.finished_at {
+        println!("Finished:       {}", finished_at.format(time_format));
+    }
+
+    if status.results.is_empty() {
+        return;
+    }
+    println!();
+    println!("Indexes:");
+
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.set_titles(row!["INDEX", "STATE", "FAILURES", "TASK", "FINISHED", "REASON"]);
+    for result in &status.results {
+        table.add_row(row![
+            result.index,
+            format!("{:?}", result.state),
+            result.failures,
+            result.task.as_deref().unwrap_or("-"),
+            result.finished_at.map(|t| t.format(time_format).to_string()).unwrap_or_else(|| "-".to_string()),
+            result.reason.as_deref().unwrap_or("-"),
+        ]);
+    }
+    table.printstd();
+
+    if status.state == JobState::Running {
+        println!();
+        println!("{} tasks running", status.active);
+    }
+}
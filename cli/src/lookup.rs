@@ -0,0 +1,41 @@
+use reqwest::blocking::Client;
+use reqwest::StatusCode;
+use common::Task;
+use crate::MANAGER_URL;
+
+/// Finds the task a user referred to, either by its name or by a prefix of its id.
+///
+/// Names win over id prefixes. Fails if nothing matches, or if the prefix matches
+/// more than one task, listing the candidates so the user can pick a longer prefix.
+pub fn resolve_task(client: &Client, reference: &str) -> anyhow::Result<Task> {
+    let response = client.get(format!("{}/tasks/by-name/{}", MANAGER_URL, reference)).send()?;
+    match response.status() {
+        StatusCode::OK => return Ok(response.json()?),
+        StatusCode::NOT_FOUND => {}
+        status => anyhow::bail!("Error looking up task '{}': {}", reference, status),
+    }
+
+    let response = client.get(format!("{}/tasks", MANAGER_URL)).send()?;
+    if !response.status().is_success() {
+        anyhow::bail!("Error looking up task '{}': {}", reference, response.status());
+    }
+    let tasks: Vec<Task> = response.json()?;
+
+    let prefix = reference.to_lowercase();
+    let mut matches: Vec<Task> = tasks.into_iter().filter(|t| t.id.to_string().starts_with(&prefix)).collect();
+
+    match matches.len() {
+        0 => anyhow::bail!("No task named '{}' or with an id starting with it", reference),
+        1 => Ok(matches.remove(0)),
+        _ => {
+            matches.sort_by_key(|t| t.created_at);
+            let candidates: Vec<String> = matches.iter().map(|t| format!("  {} ({})", t.id, t.name)).collect();
+            anyhow::bail!(
+                "'{}' is ambiguous, it is the start of {} task ids:\n{}",
+                reference,
+                matches.len(),
+                candidates.join("\n")
+            )
+        }
+    }
+}
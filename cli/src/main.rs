@@ -1,15 +1,36 @@
-use std::fs::read;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, IsTerminal, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use clap::{Parser, Subcommand};
 use prettytable::{format, row, Table};
 use serde_json::json;
-use common::Task;
+use common::{EventType, OrchError, PriorityClass, Probe, ProbeAction, Selector, Task, TaskSpec, TaskStatus, WatchEvent, WatchObject};
+use crate::lookup::resolve_task;
+use crate::output::{OutputFormat, build_table, format_labels, print_list, print_one};
+
+mod apply;
+mod cronjob;
+mod job;
+mod lookup;
+mod node;
+mod output;
+mod rollout;
+mod service;
+mod top;
+mod workflow;
 
 #[derive(Parser)]
 #[command(name = "orch")]
 #[command(about = "Rust-Orch: a simple container orchestrator CLI", long_about = None)]
 struct Cli {
     #[command(subcommand)]
-    command: Commands
+    command: Commands,
+
+    /// output format: table, wide, json, yaml or custom-columns=HEADER:.path,...
+    #[arg(short, long, global = true, default_value = "table")]
+    output: OutputFormat,
 }
 
 #[derive(Subcommand)]
@@ -20,8 +41,269 @@ enum Commands {
         name: String,
         /// docker image to use
         image: String,
+        /// `low`, `normal`, `high` or `critical`, higher ones are scheduled first and may preempt lower ones
+        #[arg(long, default_value = "normal")]
+        priority: PriorityClass,
+    },
+    /// create or update the resources of a manifest file, matched by name
+    Apply {
+        /// YAML (`---` between resources) or TOML (`[[resources]]` tables) manifest
+        #[arg(short, long)]
+        file: PathBuf,
+        /// only validate and show where tasks would be scheduled, nothing is changed
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// show how the resources of a manifest file differ from the cluster
+    Diff {
+        /// YAML or TOML manifest
+        #[arg(short, long)]
+        file: PathBuf,
+    },
+    /// delete the resources of a manifest file
+    Delete {
+        /// YAML or TOML manifest
+        #[arg(short, long)]
+        file: PathBuf,
+    },
+    /// list the tasks in the cluster
+    List {
+        /// keep running and redraw the table whenever tasks change
+        #[arg(short, long)]
+        watch: bool,
+        /// only show tasks with this status, e.g. `running`
+        #[arg(long)]
+        status: Option<TaskStatus>,
+        /// only show tasks assigned to this node
+        #[arg(long)]
+        node: Option<String>,
+        /// only show tasks whose labels match, e.g. `app=web,env in (prod,staging)`
+        #[arg(short = 'l', long)]
+        selector: Option<String>,
+    },
+    /// inspect nodes and take them out of service
+    Node {
+        #[command(subcommand)]
+        command: NodeCommand,
+    },
+    /// add or remove taints of a node
+    Taint {
+        /// id of the node
+        node: String,
+        /// `key=value:Effect` or `key:Effect` to add, `key-` to remove every taint with that key
+        #[arg(required = true)]
+        taints: Vec<String>,
+    },
+    /// inspect the replicated services
+    Service {
+        #[command(subcommand)]
+        command: ServiceCommand,
+    },
+    /// inspect the batch jobs
+    Job {
+        #[command(subcommand)]
+        command: JobCommand,
     },
+    /// inspect, trigger or suspend the cron jobs
+    Cronjob {
+        #[command(subcommand)]
+        command: CronJobCommand,
+    },
+    /// inspect the workflows and the state of their steps
+    Workflow {
+        #[command(subcommand)]
+        command: WorkflowCommand,
+    },
+    /// change how many tasks of a service run
+    Scale {
+        /// name of the service
+        service: String,
+        /// the new replica count
+        #[arg(long)]
+        replicas: u32,
+    },
+    /// follow, inspect or undo the rollout of a service's template
+    Rollout {
+        #[command(subcommand)]
+        command: RolloutCommand,
+    },
+    /// show the CPU and memory nodes or tasks actually use
+    Top {
+        #[command(subcommand)]
+        command: TopCommand,
+    },
+    /// show the details and status timeline of a task
+    Describe {
+        /// name of the task or a prefix of its id
+        task: String,
+    },
+    /// print the logs of a task's container
+    Logs {
+        /// name of the task or a prefix of its id
+        task: String,
+        /// keep streaming new output
+        #[arg(short, long)]
+        follow: bool,
+        /// number of lines to show from the end of the logs
+        #[arg(long)]
+        tail: Option<String>,
+        /// only show logs newer than a relative duration like 30s, 5m or 2h
+        #[arg(long)]
+        since: Option<String>,
+    },
+    /// run a command inside a task's container
+    Exec {
+        /// pass stdin to the command
+        #[arg(short, long)]
+        interactive: bool,
+        /// allocate a pseudo-TTY
+        #[arg(short, long)]
+        tty: bool,
+        /// name of the task or a prefix of its id
+        task: String,
+        /// command and its arguments, e.g. `-- sh -c "ls /"`
+        #[arg(last = true, required = true)]
+        command: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum NodeCommand {
+    /// list the registered nodes and their resources
     List,
+    /// show the details and tasks of a node
+    Describe {
+        /// id of the node
+        node: String,
+    },
+    /// stop scheduling new tasks on the node, running tasks stay
+    Cordon {
+        /// id of the node
+        node: String,
+    },
+    /// allow scheduling tasks on the node again
+    Uncordon {
+        /// id of the node
+        node: String,
+    },
+    /// cordon the node and move its tasks to other nodes
+    Drain {
+        /// id of the node
+        node: String,
+        /// how long the tasks' containers get to stop before they're rescheduled anyway, e.g. 30s, 2m
+        #[arg(long)]
+        grace_period: Option<String>,
+        /// give up waiting for the tasks to run elsewhere after e.g. 30s, 5m
+        #[arg(long, default_value = "5m")]
+        timeout: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum ServiceCommand {
+    /// list the services and how many of their replicas are ready
+    List {
+        /// only show services whose labels match, e.g. `tier=web`
+        #[arg(short = 'l', long)]
+        selector: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum JobCommand {
+    /// list the jobs and how many of their completions succeeded
+    List {
+        /// only show jobs whose labels match, e.g. `team=data`
+        #[arg(short = 'l', long)]
+        selector: Option<String>,
+    },
+    /// show the status and the result of each index of a job
+    Describe {
+        /// name of the job
+        job: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum CronJobCommand {
+    /// list the cron jobs with their schedule and last and next run
+    List {
+        /// only show cron jobs whose labels match, e.g. `team=data`
+        #[arg(short = 'l', long)]
+        selector: Option<String>,
+    },
+    /// start a job of the cron job now, outside its schedule
+    Trigger {
+        /// name of the cron job
+        cronjob: String,
+    },
+    /// stop starting jobs on schedule, running jobs carry on
+    Suspend {
+        /// name of the cron job
+        cronjob: String,
+        /// start jobs on schedule again
+        #[arg(long)]
+        resume: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum WorkflowCommand {
+    /// list the workflows and how many of their steps succeeded
+    List {
+        /// only show workflows whose labels match, e.g. `team=data`
+        #[arg(short = 'l', long)]
+        selector: Option<String>,
+    },
+    /// draw the DAG of a workflow with the state of each step
+    Describe {
+        /// name of the workflow
+        workflow: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum RolloutCommand {
+    /// wait until every replica runs the current template and is ready
+    Status {
+        /// name of the service
+        service: String,
+        /// give up waiting after e.g. 30s, 5m
+        #[arg(long, default_value = "5m")]
+        timeout: String,
+    },
+    /// list the remembered revisions of the template
+    History {
+        /// name of the service
+        service: String,
+    },
+    /// roll out an earlier revision again, by default the previous one
+    Undo {
+        /// name of the service
+        service: String,
+        /// the revision to go back to
+        #[arg(long)]
+        to_revision: Option<u64>,
+    },
+}
+
+#[derive(Subcommand)]
+enum TopCommand {
+    /// usage of each node
+    Nodes {
+        /// only show nodes whose labels match, e.g. `disk=ssd`
+        #[arg(short = 'l', long)]
+        selector: Option<String>,
+    },
+    /// usage of each running task, the busiest first
+    Tasks {
+        /// only show tasks running on this node
+        #[arg(long)]
+        node: Option<String>,
+        /// only show tasks whose labels match, e.g. `app=web`
+        #[arg(short = 'l', long)]
+        selector: Option<String>,
+    },
 }
 
 const MANAGER_URL: &str = "http://127.0.0.1:3000";
@@ -31,58 +313,436 @@ fn main() -> anyhow::Result<()> {
     let client = reqwest::blocking::Client::new();
 
     match &cli.command {
-        Commands::Run { name, image } => {
-            println!("Submitting task '{}' with image '{}'...", name, image);
+        Commands::Run { name, image, priority } => {
+            if matches!(cli.output, OutputFormat::Table | OutputFormat::Wide) {
+                println!("Submitting task '{}' with image '{}'...", name, image);
+            }
 
             let payload = json!({
                 "name": name,
                 "image": image,
+                "priority_class": priority,
             });
+            for warning in TaskSpec::from_task(&Task::new(name.clone(), image.clone())).warnings() {
+                eprintln!("Warning: {}", warning);
+            }
 
             let response = client.post(format!("{}/tasks", MANAGER_URL)).json(&payload).send()?;
 
             if response.status().is_success() {
                 let task: Task = response.json()?;
-                println!("Task '{}' successfully submitted.", task.name);
+                match cli.output {
+                    OutputFormat::Table | OutputFormat::Wide => println!("Task '{}' successfully submitted.", task.name),
+                    _ => print_one(&task, &cli.output, |_| {})?,
+                }
             } else {
-                eprintln!("Error submitting task: {}", response.status());
+                let status = response.status();
+                match response.json::<OrchError>() {
+                    Ok(err) => eprintln!("Error submitting task: {}", err),
+                    Err(_) => eprintln!("Error submitting task: {}", status),
+                }
             }
         }
-        Commands::List => {
-            let response = client.get(format!("{}/tasks", MANAGER_URL)).send()?;
+        Commands::Apply { file, dry_run } => apply::apply(&client, file, *dry_run)?,
+        Commands::Diff { file } => apply::diff(&client, file)?,
+        Commands::Delete { file } => apply::delete(&client, file)?,
+        Commands::List { watch, status, node, selector } => {
+            let parsed = selector
+                .as_deref()
+                .map(str::parse::<Selector>)
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("Invalid label selector: {}", e))?;
+            if *watch {
+                return watch_tasks(status.as_ref(), node.as_deref(), parsed.as_ref(), &cli.output);
+            }
 
-            if response.status().is_success() {
-                let tasks: Vec<Task> = response.json()?;
+            let mut query = Vec::new();
+            if let Some(status) = status {
+                query.push(("status", format!("{:?}", status)));
+            }
+            if let Some(node) = node {
+                query.push(("node", node.clone()));
+            }
+            if let Some(selector) = selector {
+                query.push(("labelSelector", selector.clone()));
+            }
 
-                if tasks.is_empty() {
-                    println!("No tasks found in the cluster.");
-                    return Ok(());
-                }
+            let response = client.get(format!("{}/tasks", MANAGER_URL)).query(&query).send()?;
 
-                let mut table = Table::new();
-                table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
-                table.set_titles(row!["NAME", "ID", "IMAGE", "STATUS", "NODE", "CONTAINER"]);
-
-                for t in tasks {
-                    let node = t.node_id.unwrap_or_else(|| "-".to_string());
-                    let container = t.container_id.map(|id| id[..8].to_string()).unwrap_or_else(|| "-".to_string());
-
-                    table.add_row(row![
-                        t.name,
-                        t.id.to_string()[..8],
-                        t.image,
-                        format!("{:?}", t.status),
-                        node,
-                        container,
-                    ]);
-                }
+            if response.status().is_success() {
+                let mut tasks: Vec<Task> = response.json()?;
+                tasks.sort_by_key(|t| t.created_at);
 
-                table.printstd();
+                print_list(&tasks.iter().collect::<Vec<_>>(), &cli.output, "No tasks found in the cluster.")?;
             } else {
                 eprintln!("Error listing tasks: {}", response.status());
             }
         }
+        Commands::Node { command } => match command {
+            NodeCommand::List => node::list(&client, &cli.output)?,
+            NodeCommand::Describe { node } => node::describe(&client, node, &cli.output)?,
+            NodeCommand::Cordon { node } => node::cordon(&client, node, true)?,
+            NodeCommand::Uncordon { node } => node::cordon(&client, node, false)?,
+            NodeCommand::Drain { node, grace_period, timeout } => {
+                let grace_period = grace_period.as_deref().map(parse_duration).transpose()?;
+                node::drain(&client, node, grace_period, parse_duration(timeout)?)?
+            }
+        },
+        Commands::Taint { node, taints } => node::taint(&client, node, taints)?,
+        Commands::Service { command } => match command {
+            ServiceCommand::List { selector } => service::list(&client, selector.as_deref(), &cli.output)?,
+        },
+        Commands::Job { command } => match command {
+            JobCommand::List { selector } => job::list(&client, selector.as_deref(), &cli.output)?,
+            JobCommand::Describe { job } => job::describe(&client, job, &cli.output)?,
+        },
+        Commands::Cronjob { command } => match command {
+            CronJobCommand::List { selector } => cronjob::list(&client, selector.as_deref(), &cli.output)?,
+            CronJobCommand::Trigger { cronjob } => cronjob::trigger(&client, cronjob)?,
+            CronJobCommand::Suspend { cronjob, resume } => cronjob::suspend(&client, cronjob, !*resume)?,
+        },
+        Commands::Workflow { command } => match command {
+            WorkflowCommand::List { selector } => workflow::list(&client, selector.as_deref(), &cli.output)?,
+            WorkflowCommand::Describe { workflow } => workflow::describe(&client, workflow, &cli.output)?,
+        },
+        Commands::Scale { service, replicas } => service::scale(&client, service, *replicas)?,
+        Commands::Rollout { command } => match command {
+            RolloutCommand::Status { service, timeout } => rollout::status(&client, service, parse_duration(timeout)?)?,
+            RolloutCommand::History { service } => rollout::history(&client, service)?,
+            RolloutCommand::Undo { service, to_revision } => rollout::undo(&client, service, *to_revision)?,
+        },
+        Commands::Top { command } => match command {
+            TopCommand::Nodes { selector } => top::nodes(&client, selector.as_deref(), &cli.output)?,
+            TopCommand::Tasks { node, selector } => top::tasks(&client, node.as_deref(), selector.as_deref(), &cli.output)?,
+        },
+        Commands::Describe { task } => {
+            let task = resolve_task(&client, task)?;
+            print_one(&task, &cli.output, describe_task)?;
+        }
+        Commands::Logs { task, follow, tail, since } => {
+            let task = resolve_task(&client, task)?;
+            let mut query = vec![format!("follow={}", follow)];
+            if let Some(tail) = tail {
+                query.push(format!("tail={}", tail));
+            }
+            if let Some(since) = since {
+                let since = SystemTime::now()
+                    .checked_sub(parse_duration(since)?)
+                    .ok_or_else(|| anyhow::anyhow!("--since {} reaches back too far", since))?;
+                query.push(format!("since={}", since.duration_since(UNIX_EPOCH)?.as_secs()));
+            }
+
+            // no timeout, `--follow` streams until the user hits Ctrl-C
+            let client = reqwest::blocking::Client::builder().timeout(None).build()?;
+            let mut response = client
+                .get(format!("{}/tasks/{}/logs?{}", MANAGER_URL, task.id, query.join("&")))
+                .send()?;
+
+            if response.status().is_success() {
+                response.copy_to(&mut std::io::stdout())?;
+            } else {
+                eprintln!("Error fetching logs: {} {}", response.status(), response.text()?);
+            }
+        }
+        Commands::Exec { interactive, tty, task, command } => {
+            let task = resolve_task(&client, task)?;
+            exec(&task.id.to_string(), command, *interactive, *tty)?;
+        }
     }
 
     Ok(())
 }
+
+/// How long a status change stays highlighted in `list --watch`.
+const HIGHLIGHT_FOR: Duration = Duration::from_secs(5);
+
+/// Follows the manager's change feed and redraws the task table on every change.
+///
+/// Reconnects after the last seen version when the stream breaks, and starts
+/// over from a fresh list if the manager no longer has that version.
+fn watch_tasks(
+    status: Option<&TaskStatus>,
+    node: Option<&str>,
+    selector: Option<&Selector>,
+    output: &OutputFormat,
+) -> anyhow::Result<()> {
+    let client = reqwest::blocking::Client::builder().timeout(None).build()?;
+    let mut tasks: HashMap<String, Task> = HashMap::new();
+    let mut changed_at: HashMap<String, Instant> = HashMap::new();
+    let mut last_version: Option<u64> = None;
+
+    loop {
+        let url = match last_version {
+            Some(version) => format!("{}/watch?resourceVersion={}", MANAGER_URL, version),
+            None => format!("{}/watch", MANAGER_URL),
+        };
+
+        let response = match client.get(url).send() {
+            Ok(response) => response,
+            Err(e) => {
+                eprintln!("Error watching tasks: {}", e);
+                std::thread::sleep(Duration::from_secs(2));
+                continue;
+            }
+        };
+
+        if response.status() == reqwest::StatusCode::GONE {
+            last_version = None;
+            tasks.clear();
+            continue;
+        }
+        if !response.status().is_success() {
+            eprintln!("Error watching tasks: {}", response.status());
+            return Ok(());
+        }
+
+        for line in BufReader::new(response).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let event: WatchEvent = serde_json::from_str(&line)?;
+            last_version = Some(event.resource_version);
+
+            let WatchObject::Task(task) = event.object else {
+                continue;
+            };
+            let visible = |t: &Task| {
+                status.is_none_or(|s| &t.status == s)
+                    && node.is_none_or(|n| t.node_id.as_deref() == Some(n))
+                    && selector.is_none_or(|s| s.matches(&t.labels))
+            };
+
+            // machine readable output is a stream of the changed tasks instead of a table
+            match output {
+                OutputFormat::Json if visible(&task) => println!("{}", serde_json::to_string(&task)?),
+                OutputFormat::Yaml if visible(&task) => print!("---\n{}", serde_yaml::to_string(&task)?),
+                OutputFormat::Json | OutputFormat::Yaml => {}
+                _ => {
+                    let id = task.id.to_string();
+                    if event.event_type == EventType::Deleted {
+                        tasks.remove(&id);
+                    } else if let Some(previous) = tasks.insert(id.clone(), *task)
+                        && previous.status != tasks[&id].status
+                    {
+                        changed_at.insert(id, Instant::now());
+                    }
+
+                    changed_at.retain(|_, at| at.elapsed() < HIGHLIGHT_FOR);
+
+                    let mut shown: Vec<&Task> = tasks.values().filter(|t| visible(t)).collect();
+                    shown.sort_by_key(|t| t.created_at);
+
+                    // clear the screen and redraw from the top
+                    print!("\x1B[2J\x1B[H");
+                    println!("Watching tasks, press Ctrl-C to stop.\n");
+                    build_table(&shown, output, |t| changed_at.contains_key(&t.id.to_string()))?.printstd();
+                }
+            }
+        }
+
+        std::thread::sleep(Duration::from_secs(1));
+    }
+}
+
+fn describe_task(task: &Task) {
+    let time_format = "%Y-%m-%d %H:%M:%S";
+    let format_time = |time: Option<chrono::DateTime<chrono::Utc>>| {
+        time.map(|t| t.format(time_format).to_string()).unwrap_or_else(|| "-".to_string())
+    };
+
+    println!("Name:           {}", task.name);
+    println!("ID:             {}", task.id);
+    println!("Image:          {}", task.image);
+    println!("Status:         {:?}", task.status);
+    println!("Node:           {}", task.node_id.as_deref().unwrap_or("-"));
+    println!("Container:      {}", task.container_id.as_deref().unwrap_or("-"));
+    if let Some(rescheduling) = &task.rescheduling {
+        println!("Rescheduling:   {} (by {})", rescheduling.reason, format_time(Some(rescheduling.deadline)));
+    }
+    if let Some(node_id) = &task.nominated_node {
+        println!("Nominated:      {}", node_id);
+    }
+    if let Some(owner) = &task.owner {
+        println!("Owner:          {}", owner);
+    }
+    println!("Resources:      cpu {}, memory {}MB", task.cpu, task.memory);
+    println!("Priority:       {:?}", task.priority_class);
+    println!("Labels:         {}", format_labels(&task.labels));
+    println!("Annotations:    {}", format_labels(&task.annotations));
+    println!("Node Selector:  {}", format_labels(&task.node_selector));
+    if !task.tolerations.is_empty() {
+        let tolerations: Vec<String> = task.tolerations.iter().map(ToString::to_string).collect();
+        println!("Tolerations:    {}", tolerations.join(", "));
+    }
+    println!("Created:        {}", format_time(Some(task.created_at)));
+    println!("Started:        {}", format_time(task.started_at));
+    println!("Finished:       {}", format_time(task.finished_at));
+    println!("Restart Policy: {:?}", task.restart_policy);
+    println!("Liveness:       {}", describe_probe(task.liveness_probe.as_ref()));
+    println!("Readiness:      {}", describe_probe(task.readiness_probe.as_ref()));
+    println!(
+        "Health:         live={:?} ready={:?} restarts={}",
+        task.health.live, task.health.ready, task.health.restart_count
+    );
+    if let Some(message) = &task.health.message {
+        println!("Health Message: {}", message);
+    }
+
+    println!();
+    println!("Timeline:");
+
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.set_titles(row!["TIME", "FROM", "TO", "NODE", "REASON"]);
+    for transition in &task.history {
+        table.add_row(row![
+            transition.at.format(time_format),
+            transition.from.as_ref().map(|s| format!("{:?}", s)).unwrap_or_else(|| "-".to_string()),
+            format!("{:?}", transition.to),
+            transition.node_id.as_deref().unwrap_or("-"),
+            transition.reason.as_deref().unwrap_or("-"),
+        ]);
+    }
+    table.printstd();
+}
+
+fn describe_probe(probe: Option<&Probe>) -> String {
+    let Some(probe) = probe else {
+        return "-".to_string();
+    };
+
+    let action = match &probe.action {
+        ProbeAction::Http { path, port } => format!("http-get :{}{}", port, path),
+        ProbeAction::Tcp { port } => format!("tcp-socket :{}", port),
+        ProbeAction::Exec { command } => format!("exec {:?}", command),
+    };
+
+    format!(
+        "{} delay={}s period={}s timeout={}s #success={} #failure={}",
+        action, probe.initial_delay_secs, probe.period_secs, probe.timeout_secs, probe.success_threshold, probe.failure_threshold
+    )
+}
+
+/// Opens an exec session through the manager.
+///
+/// The manager answers `101 Switching Protocols` and from then on the socket is a raw
+/// pipe to the command: we copy stdin into it and its output to stdout.
+fn exec(task: &str, command: &[String], interactive: bool, tty: bool) -> anyhow::Result<()> {
+    let body = json!({
+        "command": command,
+        "interactive": interactive,
+        "tty": tty,
+    })
+    .to_string();
+
+    let host = MANAGER_URL.trim_start_matches("http://");
+    let mut socket = TcpStream::connect(host)?;
+    write!(
+        socket,
+        "POST /tasks/{}/exec HTTP/1.1\r\nHost: {}\r\nConnection: Upgrade\r\nUpgrade: tcp\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+        task,
+        host,
+        body.len(),
+        body
+    )?;
+
+    // read the response head byte by byte so we don't swallow any session output
+    let mut head = Vec::new();
+    let mut byte = [0u8; 1];
+    while !head.ends_with(b"\r\n\r\n") {
+        if socket.read(&mut byte)? == 0 {
+            anyhow::bail!("connection closed by manager");
+        }
+        head.push(byte[0]);
+    }
+    let head = String::from_utf8_lossy(&head).to_string();
+
+    if !head.starts_with("HTTP/1.1 101") {
+        let mut rest = String::new();
+        socket.read_to_string(&mut rest)?;
+        eprintln!("Error starting exec: {} {}", head.lines().next().unwrap_or(""), rest);
+        return Ok(());
+    }
+
+    // with a TTY the remote side echoes and handles line editing, so the local terminal must not
+    let raw_mode = interactive && tty && std::io::stdin().is_terminal();
+    if raw_mode {
+        crossterm::terminal::enable_raw_mode()?;
+    }
+
+    if interactive {
+        let mut writer = socket.try_clone()?;
+        std::thread::spawn(move || {
+            let _ = std::io::copy(&mut std::io::stdin(), &mut writer);
+            let _ = writer.shutdown(Shutdown::Write);
+        });
+    }
+
+    let mut stdout = std::io::stdout();
+    let mut buffer = [0u8; 4096];
+    let result = loop {
+        match socket.read(&mut buffer) {
+            Ok(0) => break Ok(()),
+            Ok(n) => {
+                // flush right away, prompts usually don't end with a newline
+                if let Err(e) = stdout.write_all(&buffer[..n]).and_then(|_| stdout.flush()) {
+                    break Err(e);
+                }
+            }
+            Err(e) => break Err(e),
+        }
+    };
+
+    if raw_mode {
+        crossterm::terminal::disable_raw_mode()?;
+    }
+
+    Ok(result?)
+}
+
+/// Parses durations like `30s`, `5m`, `2h` or `1d`. A bare number is taken as seconds.
+fn parse_duration(input: &str) -> anyhow::Result<Duration> {
+    let (value, unit) = match input.find(|c: char| !c.is_ascii_digit()) {
+        Some(idx) => input.split_at(idx),
+        None => (input, "s"),
+    };
+    let value: u64 = value.parse().map_err(|_| anyhow::anyhow!("invalid duration '{}'", input))?;
+
+    let unit_seconds: u64 = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 60 * 60 * 24,
+        _ => anyhow::bail!("invalid duration unit in '{}', expected s, m, h or d", input),
+    };
+    let seconds = value.checked_mul(unit_seconds).ok_or_else(|| anyhow::anyhow!("duration '{}' is too long", input))?;
+
+    Ok(Duration::from_secs(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("45s").unwrap(), Duration::from_secs(45));
+        assert_eq!(parse_duration("5m").unwrap(), Duration::from_secs(300));
+        assert_eq!(parse_duration("2h").unwrap(), Duration::from_secs(7200));
+        assert_eq!(parse_duration("1d").unwrap(), Duration::from_secs(86400));
+        // a bare number is seconds
+        assert_eq!(parse_duration("90").unwrap(), Duration::from_secs(90));
+
+        assert!(parse_duration("m").unwrap_err().to_string().contains("invalid duration 'm'"));
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("10w").unwrap_err().to_string().contains("invalid duration unit"));
+        assert!(parse_duration("1.5h").is_err());
+
+        assert!(parse_duration(&format!("{}d", u64::MAX / 2)).unwrap_err().to_string().contains("too long"));
+        assert!(parse_duration("99999999999999999999s").is_err());
+    }
+}
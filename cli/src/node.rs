@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader};
+use std::time::{Duration, Instant};
+use reqwest::blocking::{Client, RequestBuilder};
+use reqwest::StatusCode;
+use prettytable::{format, row, Table};
+use common::{EventType, NodeDetails, NodeUpdate, OrchError, Resources, Taint, Task, TaskStatus, WatchEvent, WatchObject};
+use crate::output::{format_age, format_labels, node_status, print_list, print_one, OutputFormat};
+use crate::MANAGER_URL;
+
+/// Prints the registered nodes with their allocated resources.
+pub fn list(client: &Client, output: &OutputFormat) -> anyhow::Result<()> {
+    let nodes: Vec<NodeDetails> = send(client.get(format!("{}/nodes", MANAGER_URL)))?;
+    print_list(&nodes.iter().collect::<Vec<_>>(), output, "No nodes registered.")
+}
+
+/// Prints a node's details and the tasks assigned to it.
+pub fn describe(client: &Client, node: &str, output: &OutputFormat) -> anyhow::Result<()> {
+    let details: NodeDetails = send(client.get(format!("{}/nodes/{}", MANAGER_URL, node)))?;
+    let mut tasks: Vec<Task> = send(client.get(format!("{}/tasks", MANAGER_URL)).query(&[("node", node)]))?;
+    tasks.retain(|t| !t.status.is_terminal());
+    tasks.sort_by_key(|t| t.created_at);
+
+    print_one(&details, output, |details| describe_node(details, &tasks))
+}
+
+fn describe_node(details: &NodeDetails, tasks: &[Task]) {
+    let node = &details.node;
+    let taints: Vec<String> = node.taints.iter().map(ToString::to_string).collect();
+    let percent = |used: f64, total: f64| if total > 0.0 { (used / total * 100.0).round() } else { 0.0 };
+
+    println!("Name:           {}", node.id);
+    println!("Status:         {}", node_status(details));
+    println!("Address:        {}", node.api_address());
+    println!("Last Heartbeat: {}", format_age(node.last_heartbeat));
+    println!("Labels:         {}", format_labels(&node.labels));
+    println!("Annotations:    {}", format_labels(&node.annotations));
+    println!("Taints:         {}", if taints.is_empty() { "<none>".to_string() } else { taints.join(", ") });
+    println!("Capacity:       {}", format_resources(&node.capacity));
+    println!("Reserved:       {}", format_resources(&node.reserved));
+    println!();
+    println!("Resources:      ALLOCATABLE ALLOCATED        AVAILABLE");
+    println!(
+        "  cpu           {:<11} {:<16} {}",
+        node.total_cpu,
+        format!("{} ({}%)", details.allocated_cpu, percent(details.allocated_cpu as f64, node.total_cpu as f64)),
+        node.available_cpu
+    );
+    println!(
+        "  memory        {:<11} {:<16} {}MB",
+        format!("{}MB", node.total_memory),
+        format!(
+            "{}MB ({}%)",
+            details.allocated_memory,
+            percent(details.allocated_memory as f64, node.total_memory as f64)
+        ),
+        node.available_memory
+    );
+
+    println!();
+    println!("Tasks ({}):", details.task_count);
+    if tasks.is_empty() {
+        return;
+    }
+
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.set_titles(row!["NAME", "STATUS", "CPU", "MEMORY", "AGE"]);
+    for task in tasks {
+        table.add_row(row![
+            task.name,
+            format!("{:?}", task.status),
+            task.cpu,
+            format!("{}MB", task.memory),
+            format_age(Some(task.created_at)),
+        ]);
+    }
+    table.printstd();
+}
+
+fn format_resources(resources: &Resources) -> String {
+    format!("cpu {}, memory {}MB, disk {}MB", resources.cpu, resources.memory, resources.disk)
+}
+
+/// Stops (`true`) or resumes (`false`) scheduling new tasks on the node.
+pub fn cordon(client: &Client, node: &str, unschedulable: bool) -> anyhow::Result<()> {
+    let action = if unschedulable { "cordon" } else { "uncordon" };
+    send::<NodeUpdate>(client.post(format!("{}/nodes/{}/{}", MANAGER_URL, node, action)))?;
+    println!("node/{} {}ed", node, action);
+    Ok(())
+}
+
+/// Cordons the node and moves its tasks to other nodes.
+///
+/// The worker stops the old containers, each gets `grace_period` (the manager's default
+/// if not given) before the scheduler places its task elsewhere anyway. Follows the change
+/// feed and reports each task once it runs again (or finished), failing if that takes
+/// longer than `timeout`.
+pub fn drain(client: &Client, node: &str, grace_period: Option<Duration>, timeout: Duration) -> anyhow::Result<()> {
+    let mut url = format!("{}/nodes/{}/drain", MANAGER_URL, node);
+    if let Some(grace_period) = grace_period {
+        url.push_str(&format!("?gracePeriodSecs={}", grace_period.as_secs()));
+    }
+    let update: NodeUpdate = send(client.post(url))?;
+    println!("node/{} cordoned", node);
+
+    let total = update.evicted.len();
+    let mut remaining = HashMap::new();
+    for task in &update.evicted {
+        println!("task/{} stopping", task.name);
+        remaining.insert(task.id, task.name.clone());
+    }
+
+    let deadline = Instant::now() + timeout;
+    let mut last_version = update.evicted.iter().map(|t| t.resource_version).max();
+
+    while !remaining.is_empty() {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            let mut names: Vec<&String> = remaining.values().collect();
+            names.sort();
+            anyhow::bail!(
+                "timed out after {:?}, {} of {} tasks don't run elsewhere yet: {}",
+                timeout,
+                remaining.len(),
+                total,
+                names.iter().map(|n| n.as_str()).collect::<Vec<_>>().join(", ")
+            );
+        }
+
+        let url = match last_version {
+            Some(version) => format!("{}/watch?resourceVersion={}", MANAGER_URL, version),
+            None => format!("{}/watch", MANAGER_URL),
+        };
+        // the client timeout ends the stream at the deadline
+        let response = match Client::builder().timeout(left).build()?.get(url).send() {
+            Ok(response) => response,
+            Err(e) if e.is_timeout() => continue,
+            Err(e) => {
+                eprintln!("Error watching tasks: {}", e);
+                std::thread::sleep(Duration::from_secs(1));
+                continue;
+            }
+        };
+        // too far behind, start over from the current state of every task
+        if response.status() == StatusCode::GONE {
+            last_version = None;
+            continue;
+        }
+        if !response.status().is_success() {
+            anyhow::bail!("Error watching tasks: {}", response.status());
+        }
+
+        for line in BufReader::new(response).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let event: WatchEvent = serde_json::from_str(&line)?;
+            last_version = Some(event.resource_version);
+            let WatchObject::Task(task) = event.object else {
+                continue;
+            };
+            if !remaining.contains_key(&task.id) {
+                continue;
+            }
+
+            let progress = match (&event.event_type, &task.status) {
+                (EventType::Deleted, _) => "deleted".to_string(),
+                (_, TaskStatus::Running) if task.rescheduling.is_none() => format!("running on {}", task.node_id.as_deref().unwrap_or("-")),
+                (_, status) if status.is_terminal() => format!("{:?}", status),
+                _ => continue,
+            };
+            remaining.remove(&task.id);
+            println!("task/{} {} ({}/{})", task.name, progress, total - remaining.len(), total);
+
+            if remaining.is_empty() {
+                break;
+            }
+        }
+    }
+
+    println!("node/{} drained", node);
+    Ok(())
+}
+
+/// Adds (`key=value:Effect`) or removes (`key-`) taints of a node, in the given order.
+///
+/// Prints the tasks a `NoExecute` taint evicted from the node.
+pub fn taint(client: &Client, node: &str, changes: &[String]) -> anyhow::Result<()> {
+    for change in changes {
+        let request = match change.strip_suffix('-') {
+            Some(key) => client.delete(format!("{}/nodes/{}/taints/{}", MANAGER_URL, node, key)),
+            None => {
+                let taint: Taint = change.parse().map_err(|e| anyhow::anyhow!("{}", e))?;
+                client.post(format!("{}/nodes/{}/taints", MANAGER_URL, node)).json(&taint)
+            }
+        };
+
+        let update: NodeUpdate = send(request)?;
+        if change.ends_with('-') {
+            println!("node/{} untainted ({})", node, change.trim_end_matches('-'));
+        } else {
+            println!("node/{} tainted ({})", node, change);
+        }
+        for task in update.evicted {
+            println!("task/{} evicted", task.name);
+        }
+    }
+
+    Ok(())
+}
+
+/// Sends the request and decodes the response, turning error responses into the manager's error.
+pub fn send<T: serde::de::DeserializeOwned>(request: RequestBuilder) -> anyhow::Result<T> {
+    let response = request.send()?;
+    if response.status().is_success() {
+        return Ok(response.json()?);
+    }
+
+    let status = response.status();
+    match response.json::<OrchError>() {
+        Ok(err) => anyhow::bail!("{}", err),
+        Err(_) => anyhow::bail!("Manager responded with {}", status),
+    }
+}
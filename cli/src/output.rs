@@ -0,0 +1,541 @@
+use std::str::FromStr;
+use prettytable::{format, Cell, Row, Table};
+use serde::Serialize;
+use serde_json::Value;
+use chrono::{DateTime, Utc};
+use common::{
+    CronJob, Job, Labels, NodeDetails, NodeMetrics, ServiceDetails, StepState, Task, TaskMetrics, UsageSample, Workflow,
+};
+
+/// How resources are printed, selected with the global `-o/--output` flag.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum OutputFormat {
+    /// The default human readable table
+    #[default]
+    Table,
+    /// The table with additional columns
+    Wide,
+    Json,
+    Yaml,
+    /// `custom-columns=NAME:.name,NODE:.node_id`, one column per `HEADER:.json.path`
+    Columns(Vec<Column>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Column {
+    pub header: String,
+    /// Field names (or array indices) leading to the value in the resource's JSON form
+    pub path: Vec<String>,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(OutputFormat::Table),
+            "wide" => Ok(OutputFormat::Wide),
+            "json" => Ok(OutputFormat::Json),
+            "yaml" => Ok(OutputFormat::Yaml),
+            _ => match s.strip_prefix("custom-columns=") {
+                Some(spec) => parse_columns(spec).map(OutputFormat::Columns),
+                None => Err(format!(
+                    "unknown output format '{}', expected json, yaml, wide or custom-columns=HEADER:.path,...",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+fn parse_columns(spec: &str) -> Result<Vec<Column>, String> {
+    spec.split(',')
+        .map(|column| {
+            let (header, path) = column
+                .split_once(':')
+                .ok_or_else(|| format!("invalid column '{}', expected HEADER:.path", column))?;
+            let path = path
+                .strip_prefix('.')
+                .ok_or_else(|| format!("invalid path '{}' in column '{}', it must start with '.'", path, header))?;
+
+            Ok(Column {
+                header: header.to_string(),
+                path: path
+                    .split(['.', '[', ']'])
+                    .filter(|segment| !segment.is_empty())
+                    .map(|segment| segment.to_string())
+                    .collect(),
+            })
+        })
+        .collect()
+}
+
+impl Column {
+    /// Looks up the column's value in the JSON form of a resource.
+    fn value(&self, resource: &Value) -> String {
+        let found = self.path.iter().try_fold(resource, |value, segment| match value {
+            Value::Array(items) => segment.parse::<usize>().ok().and_then(|i| items.get(i)),
+            _ => value.get(segment),
+        });
+
+        match found {
+            None | Some(Value::Null) => "<none>".to_string(),
+            Some(Value::String(s)) => s.clone(),
+            Some(other) => other.to_string(),
+        }
+    }
+}
+
+/// A resource the CLI prints in every output format.
+pub trait Printable: Serialize {
+    /// Column titles of the table, `wide` adds the extra columns.
+    fn headers(wide: bool) -> Vec<&'static str>;
+
+    /// The resource's cells, in the order of `headers`.
+    fn row(&self, wide: bool) -> Vec<String>;
+}
+
+/// Builds the table for `table`, `wide` and `custom-columns` output.
+/// Rows matching `highlight` are printed in bold yellow.
+pub fn build_table<T: Printable>(items: &[&T], output: &OutputFormat, highlight: impl Fn(&T) -> bool) -> anyhow::Result<Table> {
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+
+    let (headers, rows): (Vec<String>, Vec<Vec<String>>) = match output {
+        OutputFormat::Columns(columns) => {
+            let mut rows = Vec::new();
+            for item in items {
+                let value = serde_json::to_value(item)?;
+                rows.push(columns.iter().map(|c| c.value(&value)).collect());
+            }
+            (columns.iter().map(|c| c.header.clone()).collect(), rows)
+        }
+        _ => {
+            let wide = *output == OutputFormat::Wide;
+            (
+                T::headers(wide).into_iter().map(String::from).collect(),
+                items.iter().map(|item| item.row(wide)).collect(),
+            )
+        }
+    };
+
+    table.set_titles(Row::new(headers.iter().map(|h| Cell::new(h)).collect()));
+    for (item, row) in items.iter().zip(rows) {
+        let style = if highlight(item) { "Fyb" } else { "" };
+        table.add_row(Row::new(row.iter().map(|value| Cell::new(value).style_spec(style)).collect()));
+    }
+
+    Ok(table)
+}
+
+/// Prints a list of resources. `empty` is shown instead of an empty table.
+pub fn print_list<T: Printable>(items: &[&T], output: &OutputFormat, empty: &str) -> anyhow::Result<()> {
+    match output {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(items)?),
+        OutputFormat::Yaml => print!("{}", serde_yaml::to_string(items)?),
+        _ if items.is_empty() => println!("{}", empty),
+        _ => {
+            build_table(items, output, |_| false)?.printstd();
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints a single resource. For `table` and `wide` output, `describe` prints the detail view.
+pub fn print_one<T: Printable>(item: &T, output: &OutputFormat, describe: impl FnOnce(&T)) -> anyhow::Result<()> {
+    match output {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(item)?),
+        OutputFormat::Yaml => print!("{}", serde_yaml::to_string(item)?),
+        OutputFormat::Columns(_) => {
+            build_table(&[item], output, |_| false)?.printstd();
+        }
+        OutputFormat::Table | OutputFormat::Wide => describe(item),
+    }
+
+    Ok(())
+}
+
+impl Printable for Task {
+    fn headers(wide: bool) -> Vec<&'static str> {
+        let mut headers = vec!["NAME", "ID", "IMAGE", "STATUS", "NODE", "CONTAINER"];
+        if wide {
+            headers.extend(["PRIORITY", "CPU", "MEMORY", "RESTARTS", "CREATED", "LABELS"]);
+        }
+        headers
+    }
+
+    fn row(&self, wide: bool) -> Vec<String> {
+        let id = self.id.to_string();
+        let mut row = vec![
+            self.name.clone(),
+            if wide { id } else { id[..8].to_string() },
+            self.image.clone(),
+            format!("{:?}", self.status),
+            self.node_id.clone().unwrap_or_else(|| "-".to_string()),
+            self.container_id
+                .as_ref()
+                .map(|id| if wide { id.clone() } else { id[..8].to_string() })
+                .unwrap_or_else(|| "-".to_string()),
+        ];
+
+        if wide {
+            row.extend([
+                format!("{:?}", self.priority_class),
+                self.cpu.to_string(),
+                format!("{}MB", self.memory),
+                self.health.restart_count.to_string(),
+                self.created_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+                format_labels(&self.labels),
+            ]);
+        }
+
+        row
+    }
+}
+
+impl Printable for NodeDetails {
+    fn headers(wide: bool) -> Vec<&'static str> {
+        let mut headers = vec!["NAME", "STATUS", "CPU", "MEMORY", "TASKS", "HEARTBEAT"];
+        if wide {
+            headers.extend(["ADDRESS", "LABELS", "TAINTS"]);
+        }
+        headers
+    }
+
+    fn row(&self, wide: bool) -> Vec<String> {
+        let node = &self.node;
+        let mut row = vec![
+            node.id.clone(),
+            node_status(self),
+            format!("{}/{}", self.allocated_cpu, node.total_cpu),
+            format!("{}/{}MB", self.allocated_memory, node.total_memory),
+            self.task_count.to_string(),
+            format_age(node.last_heartbeat),
+        ];
+
+        if wide {
+            let taints: Vec<String> = node.taints.iter().map(ToString::to_string).collect();
+            row.extend([
+                node.api_address(),
+                format_labels(&node.labels),
+                if taints.is_empty() { "<none>".to_string() } else { taints.join(",") },
+            ]);
+        }
+
+        row
+    }
+}
+
+impl Printable for ServiceDetails {
+    fn headers(wide: bool) -> Vec<&'static str> {
+        let mut headers = vec!["NAME", "READY", "UP-TO-DATE", "RUNNING", "CURRENT", "IMAGE", "AGE"];
+        if wide {
+            headers.extend(["REVISION", "ROLLOUT", "CPU", "MEMORY", "LABELS"]);
+        }
+        headers
+    }
+
+    fn row(&self, wide: bool) -> Vec<String> {
+        let service = &self.service;
+        let mut row = vec![
+            service.name.clone(),
+            format!("{}/{}", self.ready_replicas, service.replicas),
+            self.updated_replicas.to_string(),
+            self.running_replicas.to_string(),
+            self.current_replicas.to_string(),
+            service.template.image.clone(),
+            format_age(Some(service.created_at)).trim_end_matches(" ago").to_string(),
+        ];
+
+        if wide {
+            row.extend([
+                service.revision.to_string(),
+                format!("{:?}", service.rollout.state),
+                service.template.cpu.to_string(),
+                format!("{}MB", service.template.memory),
+                format_labels(&service.labels),
+            ]);
+        }
+
+        row
+    }
+}
+
+impl Printable for Job {
+    fn headers(wide: bool) -> Vec<&'static str> {
+        let mut headers = vec!["NAME", "COMPLETIONS", "ACTIVE", "FAILED", "STATUS", "AGE"];
+        if wide {
+            headers.extend(["PARALLELISM", "BACKOFF LIMIT", "IMAGE", "LABELS"]);
+        }
+        headers
+    }
+
+    fn row(&self, wide: bool) -> Vec<String> {
+        let status = &self.status;
+        let mut row = vec![
+            self.name.clone(),
+            format!("{}/{}", status.succeeded, self.completions),
+            status.active.to_string(),
+            status.failed.to_string(),
+            format!("{:?}", status.state),
+            format_age(Some(self.created_at)).trim_end_matches(" ago").to_string(),
+        ];
+
+        if wide {
+            row.extend([
+                self.parallelism.to_string(),
+                self.backoff_limit.to_string(),
+                self.template.image.clone(),
+                format_labels(&self.labels),
+            ]);
+        }
+
+        row
+    }
+}
+
+impl Printable for CronJob {
+    fn headers(wide: bool) -> Vec<&'static str> {
+        let mut headers = vec!["NAME", "SCHEDULE", "TIMEZONE", "SUSPEND", "ACTIVE", "LAST SCHEDULE", "NEXT SCHEDULE", "AGE"];
+        if wide {
+            headers.extend(["POLICY", "MISSED", "IMAGE", "LABELS"]);
+        }
+        headers
+    }
+
+    fn row(&self, wide: bool) -> Vec<String> {
+        let status = &self.status;
+        let mut row = vec![
+            self.name.clone(),
+            self.schedule.clone(),
+            self.timezone.clone(),
+            self.suspend.to_string(),
+            status.active.len().to_string(),
+            format_age(status.last_schedule_time),
+            if self.suspend { "-".to_string() } else { format_until(status.next_schedule_time) },
+            format_age(Some(self.created_at)).trim_end_matches(" ago").to_string(),
+        ];
+
+        if wide {
+            row.extend([
+                format!("{:?}", self.concurrency_policy),
+                status.missed_runs.to_string(),
+                self.job_template.template.image.clone(),
+                format_labels(&self.labels),
+            ]);
+        }
+
+        row
+    }
+}
+
+impl Printable for Workflow {
+    fn headers(wide: bool) -> Vec<&'static str> {
+        let mut headers = vec!["NAME", "STEPS", "RUNNING", "FAILED", "STATUS", "AGE"];
+        if wide {
+            headers.extend(["SKIPPED", "LABELS"]);
+        }
+        headers
+    }
+
+    fn row(&self, wide: bool) -> Vec<String> {
+        let count = |state: StepState| self.status.steps.iter().filter(|s| s.state == state).count();
+        let mut row = vec![
+            self.name.clone(),
+            format!("{}/{}", count(StepState::Succeeded), self.steps.len()),
+            count(StepState::Running).to_string(),
+            count(StepState::Failed).to_string(),
+            format!("{:?}", self.status.state),
+            format_age(Some(self.created_at)).trim_end_matches(" ago").to_string(),
+        ];
+
+        if wide {
+            row.extend([count(StepState::Skipped).to_string(), format_labels(&self.labels)]);
+        }
+
+        row
+    }
+}
+
+impl Printable for NodeMetrics {
+    fn headers(wide: bool) -> Vec<&'static str> {
+        let mut headers = vec!["NAME", "CPU", "CPU%", "MEMORY", "MEMORY%"];
+        if wide {
+            headers.extend(["AVG CPU", "AVG MEMORY", "SAMPLES"]);
+        }
+        headers
+    }
+
+    fn row(&self, wide: bool) -> Vec<String> {
+        let mut row = vec![self.node_id.clone()];
+        row.extend(usage_cells(&self.samples, self.total_cpu, self.total_memory));
+
+        if wide {
+            row.extend(average_cells(&self.samples));
+            row.push(self.samples.len().to_string());
+        }
+
+        row
+    }
+}
+
+impl Printable for TaskMetrics {
+    fn headers(wide: bool) -> Vec<&'static str> {
+        let mut headers = vec!["NAME", "NODE", "CPU", "CPU%", "MEMORY", "MEMORY%"];
+        if wide {
+            headers.extend(["AVG CPU", "AVG MEMORY", "SAMPLES"]);
+        }
+        headers
+    }
+
+    /// The percentages are of what the task requested, so they can go above 100.
+    fn row(&self, wide: bool) -> Vec<String> {
+        let mut row = vec![self.name.clone(), self.node_id.clone()];
+        row.extend(usage_cells(&self.samples, self.cpu, self.memory));
+
+        if wide {
+            row.extend(average_cells(&self.samples));
+            row.push(self.samples.len().to_string());
+        }
+
+        row
+    }
+}
+
+/// The latest CPU and memory usage and their share of `cpu` and `memory`, `-` without samples.
+fn usage_cells(samples: &[UsageSample], cpu: f32, memory: i32) -> [String; 4] {
+    let Some(latest) = samples.last().map(|s| &s.usage) else {
+        return ["-".to_string(), "-".to_string(), "-".to_string(), "-".to_string()];
+    };
+    let percent = |used: f64, total: f64| {
+        if total > 0.0 { format!("{}%", (used / total * 100.0).round()) } else { "-".to_string() }
+    };
+
+    [
+        format!("{:.2}", latest.cpu),
+        percent(latest.cpu as f64, cpu as f64),
+        format!("{}MB", latest.memory),
+        percent(latest.memory as f64, memory as f64),
+    ]
+}
+
+/// The average CPU and memory usage over all samples.
+fn average_cells(samples: &[UsageSample]) -> [String; 2] {
+    if samples.is_empty() {
+        return ["-".to_string(), "-".to_string()];
+    }
+    let count = samples.len() as f64;
+    let cpu = samples.iter().map(|s| s.usage.cpu as f64).sum::<f64>() / count;
+    let memory = samples.iter().map(|s| s.usage.memory as f64).sum::<f64>() / count;
+
+    [format!("{:.2}", cpu), format!("{}MB", memory.round())]
+}
+
+/// `Ready`, `NotReady`, with `,SchedulingDisabled` once the node is cordoned.
+pub fn node_status(details: &NodeDetails) -> String {
+    let status = format!("{:?}", details.node.status);
+    if details.node.unschedulable {
+        format!("{},SchedulingDisabled", status)
+    } else {
+        status
+    }
+}
+
+/// How long ago `time` was, e.g. `42s ago` or `3m ago`, `-` if it's unknown.
+pub fn format_age(time: Option<DateTime<Utc>>) -> String {
+    let Some(time) = time else {
+        return "-".to_string();
+    };
+
+    let seconds = (Utc::now() - time).num_seconds().max(0);
+    match seconds {
+        0..60 => format!("{}s ago", seconds),
+        60..3600 => format!("{}m ago", seconds / 60),
+        3600..86400 => format!("{}h ago", seconds / 3600),
+        _ => format!("{}d ago", seconds / 86400),
+    }
+}
+
+/// Formats the time left until a future timestamp, e.g. `in 5m`, or `-` if there is none.
+pub fn format_until(time: Option<DateTime<Utc>>) -> String {
+    let Some(time) = time else {
+        return "-".to_string();
+    };
+
+    let seconds = (time - Utc::now()).num_seconds().max(0);
+    match seconds {
+        0..60 => format!("in {}s", seconds),
+        60..3600 => format!("in {}m", seconds / 60),
+        3600..86400 => format!("in {}h", seconds / 3600),
+        _ => format!("in {}d", seconds / 86400),
+    }
+}
+
+/// Formats labels as `key=value,...`, or `<none>` if there aren't any.
+pub fn format_labels(labels: &Labels) -> String {
+    if labels.is_empty() {
+        return "<none>".to_string();
+    }
+    labels.iter().map(|(k, v)| format!("{}={}", k, v)).collect::<Vec<_>>().join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_formats() {
+        assert_eq!("json".parse::<OutputFormat>(), Ok(OutputFormat::Json));
+        assert_eq!("wide".parse::<OutputFormat>(), Ok(OutputFormat::Wide));
+        assert!("xml".parse::<OutputFormat>().is_err());
+        assert!("custom-columns=NAME".parse::<OutputFormat>().is_err());
+    }
+
+    #[test]
+    fn test_custom_columns() {
+        let Ok(OutputFormat::Columns(columns)) =
+            "custom-columns=NAME:.name,FIRST:.history[0].to,NODE:.node_id".parse::<OutputFormat>()
+        else {
+            panic!("expected custom columns");
+        };
+
+        let task = Task::new("web".to_string(), "nginx".to_string());
+        let value = serde_json::to_value(&task).unwrap();
+
+        let values: Vec<String> = columns.iter().map(|c| c.value(&value)).collect();
+        assert_eq!(values, vec!["web", "Pending", "<none>"]);
+    }
+
+    #[test]
+    fn test_node_row() {
+        let mut node = common::Node::new("worker-1".to_string(), 4096, 4.0);
+        node.unschedulable = true;
+        node.last_heartbeat = Some(Utc::now() - chrono::Duration::seconds(90));
+        let details = NodeDetails { node, allocated_memory: 512, allocated_cpu: 1.5, task_count: 2 };
+
+        assert_eq!(details.row(false), ["worker-1", "NotReady,SchedulingDisabled", "1.5/4", "512/4096MB", "2", "1m ago"]);
+        assert_eq!(format_age(None), "-");
+    }
+
+    #[test]
+    fn test_task_metrics_row() {
+        let sample = |cpu, memory| UsageSample { timestamp: Utc::now(), usage: common::Usage { cpu, memory } };
+        let mut metrics = TaskMetrics {
+            task_id: Task::new("web".to_string(), "nginx".to_string()).id,
+            name: "web".to_string(),
+            node_id: "worker-1".to_string(),
+            cpu: 0.5,
+            memory: 256,
+            samples: vec![sample(0.25, 128), sample(0.75, 384)],
+        };
+
+        assert_eq!(
+            metrics.row(true),
+            ["web", "worker-1", "0.75", "150%", "384MB", "150%", "0.50", "256MB", "2"]
+        );
+
+        metrics.samples.clear();
+        assert_eq!(metrics.row(false), ["web", "worker-1", "-", "-", "-", "-"]);
+    }
+}
@@ -0,0 +1,133 @@
+use std::time::{Duration, Instant};
+use reqwest::blocking::Client;
+use serde_json::json;
+use common::{RolloutState, Service, ServiceDetails};
+use crate::node::send;
+use crate::output::format_age;
+use crate::MANAGER_URL;
+
+/// How often `rollout status` asks the manager for the service again.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Follows the rollout of the current revision until every replica runs it and is ready.
+///
+/// Fails when the rollout pauses, or when it's still going after `timeout`.
+pub fn status(client: &Client, service: &str, timeout: Duration) -> anyhow::Result<()> {
+    let deadline = Instant::now() + timeout;
+    let mut last_message = String::new();
+
+    loop {
+        let details: ServiceDetails = send(client.get(format!("{}/services/{}", MANAGER_URL, service)))?;
+        let service = &details.service;
+
+        if service.rollout.state == RolloutState::Paused {
+            let reason = service.rollout.message.as_deref().unwrap_or("no reason given");
+            anyhow::bail!(
+                "rollout of service/{} revision {} paused: {}, fix the template or run `orch rollout undo {}`",
+                service.name,
+                service.revision,
+                reason,
+                service.name
+            );
+        }
+
+        let message = progress(&details);
+        if message != last_message {
+            println!("{}", message);
+            last_message = message;
+        }
+        if service.rollout.state == RolloutState::Complete {
+            return Ok(());
+        }
+
+        if Instant::now() >= deadline {
+            anyhow::bail!("timed out after {:?} waiting for service/{} to roll out", timeout, service.name);
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// One line describing how far the rollout got, like `2 of 3 updated replicas are ready`.
+fn progress(details: &ServiceDetails) -> String {
+    let service = &details.service;
+    if service.rollout.state == RolloutState::Complete {
+        return format!("service/{} successfully rolled out revision {}", service.name, service.revision);
+    }
+
+    let old = details.current_replicas.saturating_sub(details.updated_replicas);
+    let mut message = format!(
+        "Waiting for service/{} revision {}: {} of {} updated replicas are ready",
+        service.name, service.revision, details.updated_ready_replicas, service.replicas
+    );
+    if old > 0 {
+        message.push_str(&format!(", {} old replicas are pending termination", old));
+    }
+    message
+}
+
+/// Prints the remembered revisions of the service's template, the current one marked.
+pub fn history(client: &Client, service: &str) -> anyhow::Result<()> {
+    let details: ServiceDetails = send(client.get(format!("{}/services/{}", MANAGER_URL, service)))?;
+    let service = &details.service;
+
+    println!("service/{}", service.name);
+    println!("{:<10}{:<28}{:<10}CHANGE-CAUSE", "REVISION", "IMAGE", "AGE");
+    for revision in &service.history {
+        let marker = if revision.revision == service.revision { " (current)" } else { "" };
+        println!(
+            "{:<10}{:<28}{:<10}{}{}",
+            revision.revision,
+            revision.template.image,
+            format_age(Some(revision.created_at)).trim_end_matches(" ago"),
+            revision.change_cause,
+            marker
+        );
+    }
+    Ok(())
+}
+
+/// Rolls the service back to an earlier revision, by default the one before the current.
+pub fn undo(client: &Client, service: &str, to_revision: Option<u64>) -> anyhow::Result<()> {
+    let service: Service = send(
+        client
+            .post(format!("{}/services/{}/rollback", MANAGER_URL, service))
+            .json(&json!({ "revision": to_revision })),
+    )?;
+    let cause = service.history.last().map(|r| r.change_cause.as_str()).unwrap_or("rolled back");
+    println!("service/{} rolled back, revision {} ({})", service.name, service.revision, cause);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::{ServiceSpec, Task, TaskSpec};
+
+    #[test]
+    fn test_progress() {
+        let template = TaskSpec::from_task(&Task::new(String::new(), "nginx".to_string()));
+        let spec = ServiceSpec {
+            name: "web".to_string(),
+            labels: Default::default(),
+            replicas: 3,
+            template,
+            strategy: Default::default(),
+        };
+        let mut details = ServiceDetails {
+            service: spec.into_service(),
+            current_replicas: 4,
+            running_replicas: 3,
+            ready_replicas: 3,
+            updated_replicas: 2,
+            updated_ready_replicas: 1,
+        };
+        details.service.rollout.state = RolloutState::Progressing;
+
+        assert_eq!(
+            progress(&details),
+            "Waiting for service/web revision 1: 1 of 3 updated replicas are ready, 2 old replicas are pending termination"
+        );
+        details.service.rollout.state = RolloutState::Complete;
+        assert_eq!(progress(&details), "service/web successfully rolled out revision 1");
+    }
+}
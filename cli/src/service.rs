@@ -0,0 +1,28 @@
+use reqwest::blocking::Client;
+use serde_json::json;
+use common::{Service, ServiceDetails};
+use crate::node::send;
+use crate::output::{print_list, OutputFormat};
+use crate::MANAGER_URL;
+
+/// Prints the services with their ready and desired replicas.
+pub fn list(client: &Client, selector: Option<&str>, output: &OutputFormat) -> anyhow::Result<()> {
+    let mut request = client.get(format!("{}/services", MANAGER_URL));
+    if let Some(selector) = selector {
+        request = request.query(&[("labelSelector", selector)]);
+    }
+
+    let services: Vec<ServiceDetails> = send(request)?;
+    print_list(&services.iter().collect::<Vec<_>>(), output, "No services found in the cluster.")
+}
+
+/// Sets the replica count, the manager's controller starts or stops tasks to match it.
+pub fn scale(client: &Client, service: &str, replicas: u32) -> anyhow::Result<()> {
+    let service: Service = send(
+        client
+            .put(format!("{}/services/{}/scale", MANAGER_URL, service))
+            .json(&json!({ "replicas": replicas })),
+    )?;
+    println!("service/{} scaled to {} replicas", service.name, service.replicas);
+    Ok(())
+}
@@ -0,0 +1,34 @@
+use reqwest::blocking::Client;
+use common::{NodeMetrics, TaskMetrics};
+use crate::node::send;
+use crate::output::{print_list, OutputFormat};
+use crate::MANAGER_URL;
+
+/// Prints the CPU and memory the nodes use, as measured by their workers.
+pub fn nodes(client: &Client, selector: Option<&str>, output: &OutputFormat) -> anyhow::Result<()> {
+    let mut request = client.get(format!("{}/metrics/nodes", MANAGER_URL));
+    if let Some(selector) = selector {
+        request = request.query(&[("labelSelector", selector)]);
+    }
+
+    let metrics: Vec<NodeMetrics> = send(request)?;
+    print_list(&metrics.iter().collect::<Vec<_>>(), output, "No nodes registered.")
+}
+
+/// Prints the CPU and memory the running tasks use, the busiest first.
+pub fn tasks(client: &Client, node: Option<&str>, selector: Option<&str>, output: &OutputFormat) -> anyhow::Result<()> {
+    let mut query = Vec::new();
+    if let Some(node) = node {
+        query.push(("node", node));
+    }
+    if let Some(selector) = selector {
+        query.push(("labelSelector", selector));
+    }
+
+    let mut metrics: Vec<TaskMetrics> = send(client.get(format!("{}/metrics/tasks", MANAGER_URL)).query(&query))?;
+    metrics.sort_by(|a, b| {
+        let cpu = |m: &TaskMetrics| m.latest().map_or(0.0, |u| u.cpu);
+        cpu(b).total_cmp(&cpu(a)).then_with(|| a.name.cmp(&b.name))
+    });
+    print_list(&metrics.iter().collect::<Vec<_>>(), output, "No task usage reported yet.")
+}
@@ -0,0 +1,164 @@
+use reqwest::blocking::Client;
+use prettytable::{format, row, Table};
+use common::{StepState, StepStatus, Workflow};
+use crate::node::send;
+use crate::output::{format_labels, print_list, print_one, OutputFormat};
+use crate::MANAGER_URL;
+
+/// Prints the workflows with how many of their steps succeeded.
+pub fn list(client: &Client, selector: Option<&str>, output: &OutputFormat) -> anyhow::Result<()> {
+    let mut request = client.get(format!("{}/workflows", MANAGER_URL));
+    if let Some(selector) = selector {
+        request = request.query(&[("labelSelector", selector)]);
+    }
+
+    let workflows: Vec<Workflow> = send(request)?;
+    print_list(&workflows.iter().collect::<Vec<_>>(), output, "No workflows found in the cluster.")
+}
+
+/// Prints a workflow's details, its DAG with the state of every step, and the steps' tasks.
+pub fn describe(client: &Client, workflow: &str, output: &OutputFormat) -> anyhow::Result<()> {
+    let workflow: Workflow = send(client.get(format!("{}/workflows/{}", MANAGER_URL, workflow)))?;
+    print_one(&workflow, output, describe_workflow)
+}
+
+fn describe_workflow(workflow: &Workflow) {
+    let time_format = "%Y-%m-%d %H:%M:%S";
+    let status = &workflow.status;
+
+    println!("Name:           {}", workflow.name);
+    println!("Labels:         {}", format_labels(&workflow.labels));
+    println!("Status:         {:?}", status.state);
+    if let Some(message) = &status.message {
+        println!("Message:        {}", message);
+    }
+    println!("Created:        {}", workflow.created_at.format(time_format));
+    if let Some(finished_at) = status.finished_at {
+        println!("Finished:       {}", finished_at.format(time_format));
+    }
+
+    println!();
+    println!("Graph:");
+    for line in render_graph(workflow) {
+        println!("  {}", line);
+    }
+
+    println!();
+    println!("Steps:");
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.set_titles(row!["STEP", "STATE", "DEPENDS ON", "TASK", "FINISHED", "REASON"]);
+    for i in workflow.levels().concat() {
+        let step = &workflow.steps[i];
+        let state = step_status(workflow, i);
+        table.add_row(row![
+            step.name,
+            format!("{:?}", state.state),
+            if step.depends_on.is_empty() { "-".to_string() } else { step.depends_on.join(",") },
+            state.task.as_deref().unwrap_or("-"),
+            state.finished_at.map(|t| t.format(time_format).to_string()).unwrap_or_else(|| "-".to_string()),
+            state.reason.as_deref().unwrap_or("-"),
+        ]);
+    }
+    table.printstd();
+}
+
+/// The status of the step at `index`, `Waiting` before the controller first looked at the workflow.
+fn step_status(workflow: &Workflow, index: usize) -> StepStatus {
+    workflow.status.steps.get(index).cloned().unwrap_or_else(|| StepStatus {
+        name: workflow.steps[index].name.clone(),
+        ..Default::default()
+    })
+}
+
+/// Draws the DAG one level per line, each step after the ones it depends on:
+///
+/// ```text
+/// [✓] extract
+///  └─▶ [●] transform  [✓] enrich
+///       └─▶ [ ] load
+///            └─▶ [ ] report (after extract, load)
+/// ```
+///
+/// Steps name their dependencies unless those are exactly the previous level.
+fn render_graph(workflow: &Workflow) -> Vec<String> {
+    let levels = workflow.levels();
+    let dependencies = workflow.dependencies();
+
+    levels
+        .iter()
+        .enumerate()
+        .map(|(depth, level)| {
+            let steps: Vec<String> = level
+                .iter()
+                .map(|&i| {
+                    let name = &workflow.steps[i].name;
+                    let after_previous = depth > 0
+                        && dependencies[i].iter().all(|d| levels[depth - 1].contains(d))
+                        && levels[depth - 1].iter().all(|d| dependencies[i].contains(d));
+                    if depth == 0 || after_previous {
+                        format!("[{}] {}", marker(&step_status(workflow, i).state), name)
+                    } else {
+                        let names: Vec<&str> = dependencies[i].iter().map(|&d| workflow.steps[d].name.as_str()).collect();
+                        format!("[{}] {} (after {})", marker(&step_status(workflow, i).state), name, names.join(", "))
+                    }
+                })
+                .collect();
+
+            let indent = if depth == 0 { String::new() } else { format!("{}└─▶ ", " ".repeat(5 * (depth - 1) + 1)) };
+            format!("{}{}", indent, steps.join("  "))
+        })
+        .collect()
+}
+
+fn marker(state: &StepState) -> &'static str {
+    match state {
+        StepState::Waiting => " ",
+        StepState::Running => "●",
+        StepState::Succeeded => "✓",
+        StepState::Failed => "✗",
+        StepState::Skipped => "-",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::{Step, Task, TaskSpec, WorkflowSpec};
+
+    #[test]
+    fn test_render_graph() {
+        let step = |name: &str, depends_on: &[&str]| Step {
+            name: name.to_string(),
+            depends_on: depends_on.iter().map(|d| d.to_string()).collect(),
+            template: TaskSpec::from_task(&Task::new(String::new(), "busybox".to_string())),
+        };
+        let spec = WorkflowSpec {
+            name: "etl".to_string(),
+            labels: Default::default(),
+            steps: vec![
+                step("extract", &[]),
+                step("transform", &["extract"]),
+                step("enrich", &["extract"]),
+                step("load", &["transform", "enrich"]),
+                step("report", &["extract", "load"]),
+            ],
+        };
+        let mut workflow = spec.into_workflow();
+        workflow.status.steps = ["extract", "transform", "enrich"]
+            .iter()
+            .zip([StepState::Succeeded, StepState::Running, StepState::Failed])
+            .map(|(name, state)| StepStatus { name: name.to_string(), state, ..Default::default() })
+            .collect();
+
+        assert_eq!(
+            render_graph(&workflow),
+            [
+                "[✓] extract",
+                " └─▶ [●] transform  [✗] enrich",
+                "      └─▶ [ ] load",
+                "           └─▶ [ ] report (after extract, load)",
+            ]
+        );
+    }
+}
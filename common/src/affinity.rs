@@ -0,0 +1,53 @@
+use serde::{Deserialize, Serialize};
+
+use crate::labels::Selector;
+
+/// Placement rules of a task, on top of its `node_selector`.
+///
+/// `required` rules filter the nodes a task may run on, `preferred` rules only score them.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Affinity {
+    /// Rules on the labels of the node
+    pub node_affinity: NodeAffinity,
+
+    /// Run on the same node as the tasks matching these rules
+    pub task_affinity: TaskAffinity,
+
+    /// Stay away from the nodes running tasks matching these rules, e.g. to spread replicas
+    pub task_anti_affinity: TaskAffinity,
+}
+
+impl Affinity {
+    pub fn is_empty(&self) -> bool {
+        *self == Affinity::default()
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct NodeAffinity {
+    /// The node's labels must match at least one of these selectors
+    pub required: Vec<Selector>,
+
+    /// Each matching selector adds its weight to the node's score
+    pub preferred: Vec<Preference>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TaskAffinity {
+    /// Every selector must (affinity) or must not (anti-affinity) match a task on the node
+    pub required: Vec<Selector>,
+
+    /// Each task on the node matching a selector adds (affinity) or subtracts (anti-affinity) its weight
+    pub preferred: Vec<Preference>,
+}
+
+/// A weighted soft rule, weights go from 1 to 100.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Preference {
+    pub weight: u32,
+    pub selector: Selector,
+}
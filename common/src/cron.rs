@@ -0,0 +1,264 @@
+use chrono::{DateTime, Datelike, Duration, LocalResult, NaiveDate, NaiveDateTime, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+
+/// How far ahead `next_after` looks before giving up, enough for `0 0 29 2 *` to hit a leap year.
+const SEARCH_YEARS: i32 = 8;
+
+/// More than any daylight saving shift, a wall-clock time this much later is always a later instant.
+const MAX_SHIFT: Duration = Duration::hours(3);
+
+const MONTH_NAMES: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+const WEEKDAY_NAMES: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// A parsed cron expression: `minute hour day-of-month month day-of-week`.
+///
+/// Each field takes `*`, values, ranges `a-b`, steps `*/n` or `a-b/n` and lists of those,
+/// months and weekdays also take names like `jan` or `mon`, and 7 is Sunday too.
+/// `@yearly`, `@monthly`, `@weekly`, `@daily` and `@hourly` are shorthands.
+/// Like in cron, a time matches if either day field does when both are restricted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether the day-of-month field doesn't start with `*`
+    days_restricted: bool,
+    /// Whether the day-of-week field doesn't start with `*`
+    weekdays_restricted: bool,
+    /// Whether the hour field has a `*` or a step, such schedules also run in the hour repeated
+    /// when daylight saving ends
+    hours_repeat: bool,
+}
+
+impl std::str::FromStr for Schedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let expression = match s.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            other if other.starts_with('@') => return Err(format!("unknown shorthand '{}'", other)),
+            other => other,
+        };
+
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(format!("expected 5 fields (minute hour day-of-month month day-of-week), got {}", fields.len()));
+        };
+
+        let mut weekdays = parse_field("day-of-week", weekday, 0, 7, &WEEKDAY_NAMES)?;
+        // 7 is another Sunday
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+
+        Ok(Schedule {
+            minutes: parse_field("minute", minute, 0, 59, &[])?,
+            hours: parse_field("hour", hour, 0, 23, &[])?,
+            days: parse_field("day-of-month", day, 1, 31, &[])?,
+            months: parse_field("month", month, 1, 12, &MONTH_NAMES)?,
+            weekdays,
+            days_restricted: !day.starts_with('*'),
+            weekdays_restricted: !weekday.starts_with('*'),
+            hours_repeat: hour.contains('*') || hour.contains('/'),
+        })
+    }
+}
+
+/// Parses one field into a bit set of the values it matches.
+///
+/// `names` are matched case-insensitively and stand for `min`, `min + 1`, and so on.
+fn parse_field(field: &str, source: &str, min: u32, max: u32, names: &[&str]) -> Result<u64, String> {
+    let value = |s: &str| -> Result<u32, String> {
+        let lower = s.to_ascii_lowercase();
+        let number = match names.iter().position(|n| *n == lower) {
+            Some(i) => min + i as u32,
+            None => s.parse().map_err(|_| format!("{}: '{}' is not a number", field, s))?,
+        };
+        if !(min..=max).contains(&number) {
+            return Err(format!("{}: {} is out of range {}-{}", field, number, min, max));
+        }
+        Ok(number)
+    };
+
+    let mut bits = 0;
+    for item in source.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step.parse().map_err(|_| format!("{}: '{}' is not a step", field, step))?;
+                if step == 0 {
+                    return Err(format!("{}: the step must be at least 1", field));
+                }
+                (range, step)
+            }
+            None => (item, 1),
+        };
+
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (value(start)?, value(end)?),
+                // `5/15` runs from 5 to the end of the range
+                None if step > 1 => (value(range)?, max),
+                None => (value(range)?, value(range)?),
+            },
+        };
+        if start > end {
+            return Err(format!("{}: the range {} is backwards", field, range));
+        }
+
+        for number in (start..=end).step_by(step as usize) {
+            bits |= 1 << number;
+        }
+    }
+
+    Ok(bits)
+}
+
+impl Schedule {
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        let day = self.days & (1 << date.day()) != 0;
+        let weekday = self.weekdays & (1 << date.weekday().num_days_from_sunday()) != 0;
+
+        match (self.days_restricted, self.weekdays_restricted) {
+            (true, true) => day || weekday,
+            (true, false) => day,
+            (false, true) => weekday,
+            (false, false) => true,
+        }
+    }
+
+    /// The first matching wall-clock minute after `after`, skipping whole months, days and hours that can't match.
+    fn next_local(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        let start = after.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let limit = start.with_year(start.year() + SEARCH_YEARS).unwrap_or(NaiveDateTime::MAX);
+
+        let mut t = start;
+        while t <= limit {
+            let midnight = t.date().and_hms_opt(0, 0, 0)?;
+
+            if self.months & (1 << t.month()) == 0 {
+                let (year, month) = if t.month() == 12 { (t.year() + 1, 1) } else { (t.year(), t.month() + 1) };
+                t = NaiveDate::from_ymd_opt(year, month, 1)?.and_hms_opt(0, 0, 0)?;
+            } else if !self.matches_day(t.date()) {
+                t = midnight + Duration::days(1);
+            } else if self.hours & (1 << t.hour()) == 0 {
+                t = midnight + Duration::hours(t.hour() as i64 + 1);
+            } else if self.minutes & (1 << t.minute()) == 0 {
+                t += Duration::minutes(1);
+            } else {
+                return Some(t);
+            }
+        }
+
+        None
+    }
+
+    /// The first scheduled time after `after`, with the fields read as wall-clock time in `timezone`.
+    ///
+    /// Times that don't exist on the day daylight saving starts are skipped. Times that happen
+    /// twice when it ends run the first time for a fixed hour like `30 2 * * *`, and both times
+    /// when the hour field has a `*` or a step, so `0 * * * *` fires every elapsed hour.
+    /// `None` if nothing matches for years, e.g. for `0 0 30 2 *`.
+    pub fn next_after(&self, after: DateTime<Utc>, timezone: &Tz) -> Option<DateTime<Utc>> {
+        // the second run of a repeated time lies behind `after` on the wall clock
+        let mut local = after.with_timezone(timezone).naive_local() - MAX_SHIFT;
+        let mut next: Option<(DateTime<Utc>, NaiveDateTime)> = None;
+        loop {
+            local = match self.next_local(local) {
+                Some(local) if next.is_none_or(|(_, found)| local <= found + MAX_SHIFT) => local,
+                _ => return next.map(|(time, _)| time),
+            };
+            let times = match timezone.from_local_datetime(&local) {
+                LocalResult::Single(time) => [Some(time), None],
+                LocalResult::Ambiguous(first, second) => [Some(first), Some(second).filter(|_| self.hours_repeat)],
+                LocalResult::None => [None, None],
+            };
+            for time in times.into_iter().flatten().map(|t| t.with_timezone(&Utc)) {
+                if time > after && next.is_none_or(|(earliest, _)| time < earliest) {
+                    next = Some((time, local));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_schedule() {
+        assert!("*/15 2,4-6 * jan-mar mon-fri".parse::<Schedule>().is_ok());
+        assert_eq!("@daily".parse::<Schedule>(), "0 0 * * *".parse::<Schedule>());
+        assert_eq!("0 0 * * 7".parse::<Schedule>(), "0 0 * * sun".parse::<Schedule>());
+
+        for (expression, error) in [
+            ("* * * *", "expected 5 fields"),
+            ("60 * * * *", "minute: 60 is out of range 0-59"),
+            ("* * * foo *", "month: 'foo' is not a number"),
+            ("*/0 * * * *", "the step must be at least 1"),
+            ("* 5-2 * * *", "backwards"),
+            ("@often", "unknown shorthand"),
+        ] {
+            let err = expression.parse::<Schedule>().unwrap_err();
+            assert!(err.contains(error), "{}: {}", expression, err);
+        }
+    }
+
+    #[test]
+    fn test_next_after() {
+        let next = |expression: &str, after: &str| {
+            expression.parse::<Schedule>().unwrap().next_after(utc(after), &Tz::UTC).map(|t| t.to_rfc3339())
+        };
+
+        assert_eq!(next("30 2 * * *", "2026-03-01T02:30:00Z").unwrap(), "2026-03-02T02:30:00+00:00");
+        assert_eq!(next("*/20 * * * *", "2026-03-01T10:41:10Z").unwrap(), "2026-03-01T11:00:00+00:00");
+        // either day field matches when both are restricted: the 13th, or any Friday
+        assert_eq!(next("0 0 13 * fri", "2026-03-01T00:00:00Z").unwrap(), "2026-03-06T00:00:00+00:00");
+        assert_eq!(next("0 0 29 2 *", "2026-03-01T00:00:00Z").unwrap(), "2028-02-29T00:00:00+00:00");
+        assert_eq!(next("0 0 30 2 *", "2026-03-01T00:00:00Z"), None);
+    }
+
+    #[test]
+    fn test_next_after_across_daylight_saving() {
+        let berlin = Tz::Europe__Berlin;
+        let schedule: Schedule = "30 2 * * *".parse().unwrap();
+
+        // 02:30 doesn't exist on 2026-03-29, the clocks jump from 02:00 to 03:00
+        let next = schedule.next_after(utc("2026-03-28T02:00:00Z"), &berlin).unwrap();
+        assert_eq!(next, utc("2026-03-30T00:30:00Z"));
+
+        // 02:30 happens twice on 2026-10-25, only the first one runs
+        let next = schedule.next_after(utc("2026-10-24T01:00:00Z"), &berlin).unwrap();
+        assert_eq!(next, utc("2026-10-25T00:30:00Z"));
+        let next = schedule.next_after(next, &berlin).unwrap();
+        assert_eq!(next, utc("2026-10-26T01:30:00Z"));
+    }
+
+    #[test]
+    fn test_hourly_runs_through_the_repeated_hour() {
+        let berlin = Tz::Europe__Berlin;
+        let mut runs = Vec::new();
+        let mut time = utc("2026-10-24T23:30:00Z");
+        for _ in 0..4 {
+            time = "0 * * * *".parse::<Schedule>().unwrap().next_after(time, &berlin).unwrap();
+            runs.push(time);
+        }
+        // 02:00 CEST, 02:00 CET, then 03:00 CET, one hour apart each
+        assert_eq!(runs, ["2026-10-25T00:00:00Z", "2026-10-25T01:00:00Z", "2026-10-25T02:00:00Z", "2026-10-25T03:00:00Z"].map(utc));
+
+        let steps: Schedule = "*/30 */2 * * *".parse().unwrap();
+        let next = steps.next_after(utc("2026-10-25T00:30:00Z"), &berlin).unwrap();
+        assert_eq!(next, utc("2026-10-25T01:00:00Z"));
+    }
+}
@@ -0,0 +1,319 @@
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::cron::Schedule;
+use crate::error::FieldError;
+use crate::job::{Job, JobSpec};
+use crate::labels::{self, Labels};
+use crate::manifest::validate_name;
+use crate::task::{Owner, OwnerKind};
+
+/// Longest cron job name, leaving room for the `-manual-xxxxx` suffix of triggered job names.
+const MAX_NAME_LENGTH: usize = 38;
+
+/// What to do when a run is due while a job of an earlier run is still going.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub enum ConcurrencyPolicy {
+    /// Start the new job next to the running ones
+    #[default]
+    Allow,
+    /// Skip the run
+    Forbid,
+    /// Delete the running jobs and start the new one
+    Replace,
+}
+
+/// What the cron job controller last did.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct CronJobStatus {
+    /// The latest scheduled time that was handled, by starting a job or by skipping it
+    pub last_schedule_time: Option<DateTime<Utc>>,
+    /// When the next run is due
+    pub next_schedule_time: Option<DateTime<Utc>>,
+    /// When the latest job that completed finished
+    pub last_successful_time: Option<DateTime<Utc>>,
+    /// The cron job's unfinished jobs
+    pub active: Vec<String>,
+    /// Runs that weren't started because they were too late, e.g. while the manager was stalled
+    pub missed_runs: u64,
+    /// Why the latest run was skipped, or why the schedule never fires
+    pub message: Option<String>,
+}
+
+/// Starts a job from its template at the times of a cron schedule.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CronJob {
+    pub name: String,
+
+    /// Unique per cron job, so one created again under the same name doesn't take over the old jobs
+    pub uid: Uuid,
+
+    /// Bumped by the manager on every change, used for optimistic concurrency (`If-Match`)
+    #[serde(default)]
+    pub resource_version: u64,
+
+    #[serde(default)]
+    pub labels: Labels,
+
+    /// A cron expression like `*/15 * * * *`, see [`Schedule`]
+    pub schedule: String,
+
+    /// The IANA time zone the schedule is read in, e.g. `Europe/Berlin`
+    pub timezone: String,
+
+    pub concurrency_policy: ConcurrencyPolicy,
+
+    /// Stops starting new jobs, running ones carry on
+    pub suspend: bool,
+
+    /// A run that is more than this many seconds late is counted as missed instead of started.
+    /// When unset, only the latest of several missed runs is started, however late.
+    pub starting_deadline_secs: Option<u64>,
+
+    /// How many completed jobs to keep
+    pub successful_jobs_history_limit: u32,
+
+    /// How many failed jobs to keep
+    pub failed_jobs_history_limit: u32,
+
+    /// What each run starts, its name is left out
+    pub job_template: JobSpec,
+
+    #[serde(default)]
+    pub status: CronJobStatus,
+
+    pub created_at: DateTime<Utc>,
+}
+
+impl CronJob {
+    /// Whether the job was started by this cron job.
+    pub fn owns(&self, job: &Job) -> bool {
+        job.owner
+            .as_ref()
+            .is_some_and(|owner| owner.kind == OwnerKind::CronJob && owner.uid == Some(self.uid))
+    }
+
+    /// The parsed schedule and time zone, both were checked when the cron job was stored.
+    pub fn schedule(&self) -> Result<(Schedule, Tz), String> {
+        Ok((self.schedule.parse()?, parse_timezone(&self.timezone)?))
+    }
+
+    /// The job for the run scheduled at `scheduled`, named `{cronjob}-{minutes since the epoch}`.
+    pub fn new_job(&self, scheduled: DateTime<Utc>) -> Job {
+        self.job_named(format!("{}-{}", self.name, scheduled.timestamp() / 60))
+    }
+
+    /// A job started by hand, outside the schedule, named `{cronjob}-manual-xxxxx`.
+    pub fn new_manual_job(&self) -> Job {
+        let suffix = Uuid::new_v4().simple().to_string();
+        self.job_named(format!("{}-manual-{}", self.name, &suffix[..5]))
+    }
+
+    fn job_named(&self, name: String) -> Job {
+        let mut spec = self.job_template.clone();
+        spec.name = name;
+
+        let mut job = spec.into_job();
+        job.owner = Some(Owner {
+            kind: OwnerKind::CronJob,
+            name: self.name.clone(),
+            revision: 0,
+            index: None,
+            uid: Some(self.uid),
+        });
+        job
+    }
+}
+
+/// The user-controlled part of a cron job, as written in a manifest or sent to `POST /cronjobs`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct CronJobSpec {
+    pub name: String,
+
+    #[serde(default)]
+    pub labels: Labels,
+
+    pub schedule: String,
+
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
+
+    #[serde(default)]
+    pub concurrency_policy: ConcurrencyPolicy,
+
+    #[serde(default)]
+    pub suspend: bool,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub starting_deadline_secs: Option<u64>,
+
+    #[serde(default = "default_successful_jobs_history_limit")]
+    pub successful_jobs_history_limit: u32,
+
+    #[serde(default = "default_failed_jobs_history_limit")]
+    pub failed_jobs_history_limit: u32,
+
+    pub job_template: JobSpec,
+}
+
+/// Looks up an IANA time zone like `Europe/Berlin` in the database built into chrono-tz.
+fn parse_timezone(name: &str) -> Result<Tz, String> {
+    name.parse().map_err(|_| format!("'{}' is not an IANA time zone", name))
+}
+
+fn default_timezone() -> String {
+    "UTC".to_string()
+}
+
+fn default_successful_jobs_history_limit() -> u32 {
+    3
+}
+
+fn default_failed_jobs_history_limit() -> u32 {
+    1
+}
+
+impl CronJobSpec {
+    /// The spec a cron job was created from.
+    pub fn from_cronjob(cronjob: &CronJob) -> Self {
+        CronJobSpec {
+            name: cronjob.name.clone(),
+            labels: cronjob.labels.clone(),
+            schedule: cronjob.schedule.clone(),
+            timezone: cronjob.timezone.clone(),
+            concurrency_policy: cronjob.concurrency_policy.clone(),
+            suspend: cronjob.suspend,
+            starting_deadline_secs: cronjob.starting_deadline_secs,
+            successful_jobs_history_limit: cronjob.successful_jobs_history_limit,
+            failed_jobs_history_limit: cronjob.failed_jobs_history_limit,
+            job_template: cronjob.job_template.clone(),
+        }
+    }
+
+    pub fn into_cronjob(self) -> CronJob {
+        CronJob {
+            name: self.name,
+            uid: Uuid::new_v4(),
+            resource_version: 0,
+            labels: self.labels,
+            schedule: self.schedule,
+            timezone: self.timezone,
+            concurrency_policy: self.concurrency_policy,
+            suspend: self.suspend,
+            starting_deadline_secs: self.starting_deadline_secs,
+            successful_jobs_history_limit: self.successful_jobs_history_limit,
+            failed_jobs_history_limit: self.failed_jobs_history_limit,
+            job_template: self.job_template,
+            status: CronJobStatus::default(),
+            created_at: Utc::now(),
+        }
+    }
+
+    /// Overwrites the user-controlled fields of `cronjob`, keeping its status and jobs.
+    pub fn apply_to(self, cronjob: &mut CronJob) {
+        cronjob.labels = self.labels;
+        cronjob.schedule = self.schedule;
+        cronjob.timezone = self.timezone;
+        cronjob.concurrency_policy = self.concurrency_policy;
+        cronjob.suspend = self.suspend;
+        cronjob.starting_deadline_secs = self.starting_deadline_secs;
+        cronjob.successful_jobs_history_limit = self.successful_jobs_history_limit;
+        cronjob.failed_jobs_history_limit = self.failed_jobs_history_limit;
+        cronjob.job_template = self.job_template;
+    }
+
+    /// Checks the spec, returns one error per invalid field. Empty means valid.
+    pub fn validate(&self) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+
+        if let Err(message) = validate_name(&self.name) {
+            errors.push(FieldError::new("name", message));
+        } else if self.name.len() > MAX_NAME_LENGTH {
+            errors.push(FieldError::new("name", format!("must be at most {} characters", MAX_NAME_LENGTH)));
+        }
+        for (key, value) in &self.labels {
+            if let Err(message) = labels::validate_key(key).and_then(|_| labels::validate_value(value)) {
+                errors.push(FieldError::new(format!("labels.{}", key), message));
+            }
+        }
+        if let Err(message) = self.schedule.parse::<Schedule>() {
+            errors.push(FieldError::new("schedule", message));
+        }
+        if let Err(message) = parse_timezone(&self.timezone) {
+            errors.push(FieldError::new("timezone", message));
+        }
+        if !self.job_template.name.is_empty() {
+            errors.push(FieldError::new("job_template.name", "must be left out, jobs are named after the cron job"));
+        }
+        errors.extend(
+            self.job_template
+                .validate_template()
+                .into_iter()
+                .map(|e| FieldError::new(format!("job_template.{}", e.field), e.message)),
+        );
+
+        errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::TaskSpec;
+    use crate::probe::RestartPolicy;
+    use crate::task::Task;
+
+    fn spec(name: &str, schedule: &str) -> CronJobSpec {
+        CronJobSpec {
+            name: name.to_string(),
+            labels: Labels::new(),
+            schedule: schedule.to_string(),
+            timezone: default_timezone(),
+            concurrency_policy: ConcurrencyPolicy::Allow,
+            suspend: false,
+            starting_deadline_secs: None,
+            successful_jobs_history_limit: 3,
+            failed_jobs_history_limit: 1,
+            job_template: JobSpec {
+                name: String::new(),
+                labels: Labels::new(),
+                completions: 1,
+                parallelism: 1,
+                backoff_limit: 0,
+                ttl_secs_after_finished: None,
+                template: TaskSpec {
+                    restart_policy: RestartPolicy::Never,
+                    ..TaskSpec::from_task(&Task::new(String::new(), "busybox".to_string()))
+                },
+            },
+        }
+    }
+
+    #[test]
+    fn test_new_job_is_owned() {
+        let cronjob = spec("report", "0 * * * *").into_cronjob();
+        let job = cronjob.new_job("2026-03-01T10:00:00Z".parse().unwrap());
+
+        assert_eq!(job.name, format!("report-{}", 1772359200 / 60));
+        assert!(cronjob.owns(&job));
+        assert!(cronjob.new_manual_job().name.starts_with("report-manual-"));
+        assert!(job.name.len() <= MAX_NAME_LENGTH + 13);
+        // created again under the same name
+        assert!(!spec("report", "0 * * * *").into_cronjob().owns(&job));
+    }
+
+    #[test]
+    fn test_validate_cronjob() {
+        assert!(spec("report", "@hourly").validate().is_empty());
+
+        let mut invalid = spec(&"a".repeat(40), "0 25 * * *");
+        invalid.timezone = "Nowhere/Special".to_string();
+        invalid.job_template.name = "report".to_string();
+        invalid.job_template.parallelism = 0;
+        let fields: Vec<String> = invalid.validate().into_iter().map(|e| e.field).collect();
+        assert_eq!(fields, ["name", "schedule", "timezone", "job_template.name", "job_template.parallelism"]);
+    }
+}
@@ -1,13 +1,61 @@
 use serde::{Deserialize, Serialize};
 
+use crate::task::TaskStatus;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum OrchError {
     DockerError(String),
     TaskNotFound(String),
     NodeNotFound(String),
+    ServiceNotFound(String),
+    JobNotFound(String),
+    CronJobNotFound(String),
+    WorkflowNotFound(String),
     SchedulerError(String),
     NetworkError(String),
     TaskStoreError(String),
+    /// The task's lifecycle doesn't allow moving from `current` to `requested`.
+    InvalidTransition {
+        task_id: String,
+        current: TaskStatus,
+        requested: TaskStatus,
+    },
+    /// A write was based on an outdated `resource_version` of the task.
+    VersionConflict {
+        task_id: String,
+        expected: u64,
+        actual: u64,
+    },
+    /// A watch asked to resume from a version the change feed doesn't hold, because it was dropped
+    /// from the history or handed out before the manager restarted.
+    ResourceVersionTooOld(u64),
+    /// The submitted spec is invalid, one entry per offending field.
+    ValidationFailed(Vec<FieldError>),
+    /// The request is valid but conflicts with the current state of the resource.
+    Conflict(String),
+}
+
+/// A validation error of a single field, e.g. `liveness_probe.period_secs`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FieldError {
+    /// Path of the field in the spec, segments separated by `.`
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        FieldError {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for FieldError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
 }
 
 impl std::fmt::Display for OrchError {
@@ -16,9 +64,29 @@ impl std::fmt::Display for OrchError {
             OrchError::DockerError(msg) => write!(f, "Docker operation failed: {}", msg),
             OrchError::TaskNotFound(id) => write!(f, "Task not found: {}", id),
             OrchError::NodeNotFound(id) => write!(f, "Node not found: {}", id),
+            OrchError::ServiceNotFound(name) => write!(f, "Service not found: {}", name),
+            OrchError::JobNotFound(name) => write!(f, "Job not found: {}", name),
+            OrchError::CronJobNotFound(name) => write!(f, "Cron job not found: {}", name),
+            OrchError::WorkflowNotFound(name) => write!(f, "Workflow not found: {}", name),
             OrchError::SchedulerError(msg) => write!(f, "Scheduler error: {}", msg),
             OrchError::NetworkError(msg) => write!(f, "Network error: {}", msg),
             OrchError::TaskStoreError(msg) => write!(f, "Task store error: {}", msg),
+            OrchError::InvalidTransition { task_id, current, requested } => write!(
+                f,
+                "Invalid transition for task {}: {:?} -> {:?}",
+                task_id, current, requested
+            ),
+            OrchError::VersionConflict { task_id, expected, actual } => write!(
+                f,
+                "Task {} was modified concurrently: expected version {}, found {}",
+                task_id, expected, actual
+            ),
+            OrchError::ResourceVersionTooOld(version) => write!(f, "Resource version {} is no longer available, list again", version),
+            OrchError::ValidationFailed(errors) => {
+                let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                write!(f, "Validation failed: {}", errors.join(", "))
+            }
+            OrchError::Conflict(msg) => write!(f, "Conflict: {}", msg),
         }
     }
 }
@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::io;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// Upper bound for the request head, protects us from clients that never send `\r\n\r\n`.
+pub const MAX_HEAD_SIZE: usize = 16 * 1024;
+
+/// The request line and headers of an HTTP/1.1 request.
+///
+/// The manager and the worker API both read requests off a raw `TcpStream` and parse their head with this.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RequestHead {
+    pub method: String,
+    pub path: String,
+    pub query: HashMap<String, String>,
+    /// Header names are lowercased
+    pub headers: HashMap<String, String>,
+}
+
+impl RequestHead {
+    /// Parses everything before the blank line that ends the head, see [`find_head_end`].
+    pub fn parse(head: &str) -> Self {
+        let mut lines = head.lines();
+
+        let request_line = lines.next().unwrap_or("");
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next().unwrap_or("").to_string();
+        let target = parts.next().unwrap_or("/");
+        let (path, raw_query) = target.split_once('?').unwrap_or((target, ""));
+
+        let headers = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(k, v)| (k.trim().to_lowercase(), v.trim().to_string()))
+            .collect();
+
+        RequestHead { method, path: path.to_string(), query: parse_query(raw_query), headers }
+    }
+
+    /// Path split into its non-empty segments, e.g. `/tasks/1/logs` -> `["tasks", "1", "logs"]`
+    pub fn segments(&self) -> Vec<&str> {
+        self.path.split('/').filter(|s| !s.is_empty()).collect()
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(&name.to_lowercase()).map(|v| v.as_str())
+    }
+
+    /// Length of the body as announced by `Content-Length`, 0 if it's missing or invalid.
+    pub fn content_length(&self) -> usize {
+        self.header("content-length").and_then(|v| v.parse().ok()).unwrap_or(0)
+    }
+}
+
+/// Position of the `\r\n\r\n` that ends the request head, the body starts 4 bytes later.
+pub fn find_head_end(buffer: &[u8]) -> Option<usize> {
+    buffer.windows(4).position(|w| w == b"\r\n\r\n")
+}
+
+/// Reads one request off the stream, its head and the body announced by `Content-Length`.
+///
+/// Returns `None` if the client closed the connection without sending anything. The manager
+/// and the worker API both serve their connections with this.
+pub async fn read_request<S: AsyncRead + Unpin>(stream: &mut S) -> io::Result<Option<(RequestHead, Vec<u8>)>> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0; 1024];
+
+    // read until we've seen the end of the head
+    let head_end = loop {
+        if let Some(pos) = find_head_end(&buffer) {
+            break pos;
+        }
+        if buffer.len() > MAX_HEAD_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "request head too large"));
+        }
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            if buffer.is_empty() {
+                return Ok(None);
+            }
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed before end of request head"));
+        }
+        buffer.extend_from_slice(&chunk[..n]);
+    };
+
+    let head = RequestHead::parse(&String::from_utf8_lossy(&buffer[..head_end]));
+
+    // the body starts after "\r\n\r\n", the rest comes as announced by Content-Length
+    let mut body = buffer[head_end + 4..].to_vec();
+    while body.len() < head.content_length() {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            break;
+        }
+        body.extend_from_slice(&chunk[..n]);
+    }
+
+    Ok(Some((head, body)))
+}
+
+/// Splits a query string into decoded key-value pairs, a key without `=` gets an empty value.
+pub fn parse_query(raw: &str) -> HashMap<String, String> {
+    raw.split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((k, v)) => (percent_decode(k), percent_decode(v)),
+            None => (percent_decode(pair), String::new()),
+        })
+        .collect()
+}
+
+/// Decodes `%XX` escapes and `+` (a space in query strings), e.g. `app%3Dweb` -> `app=web`.
+fn percent_decode(raw: &str) -> String {
+    let bytes = raw.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let escaped = std::str::from_utf8(&bytes[i + 1..i + 3])
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                match escaped {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_request_head() {
+        let raw = "GET /tasks/by-name/web?labelSelector=app%3Dweb%2Cenv+in+(prod)&follow HTTP/1.1\r\n\
+                   Host: localhost\r\nContent-Length: 12\r\nIf-Match: \"7\"";
+        let head = RequestHead::parse(raw);
+
+        assert_eq!(head.method, "GET");
+        assert_eq!(head.segments(), ["tasks", "by-name", "web"]);
+        assert_eq!(head.query["labelSelector"], "app=web,env in (prod)");
+        assert_eq!(head.query["follow"], "");
+        assert_eq!(head.header("IF-MATCH"), Some("\"7\""));
+        assert_eq!(head.content_length(), 12);
+        assert_eq!(find_head_end(b"GET / HTTP/1.1\r\n\r\nbody"), Some(14));
+    }
+
+    #[tokio::test]
+    async fn test_read_request() {
+        let mut raw: &[u8] = b"POST /containers/c1/exec HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello";
+        let (head, body) = read_request(&mut raw).await.unwrap().unwrap();
+        assert_eq!((head.method.as_str(), head.path.as_str(), body.as_slice()), ("POST", "/containers/c1/exec", &b"hello"[..]));
+
+        let mut closed: &[u8] = b"";
+        assert!(read_request(&mut closed).await.unwrap().is_none());
+        let mut cut_off: &[u8] = b"GET / HTTP/1.1\r\nHost: x";
+        assert_eq!(read_request(&mut cut_off).await.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+        let huge = format!("GET / HTTP/1.1\r\nX: {}", "a".repeat(MAX_HEAD_SIZE + 1));
+        assert_eq!(read_request(&mut huge.as_bytes()).await.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,343 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::error::FieldError;
+use crate::labels::{self, Labels};
+use crate::manifest::{validate_name, TaskSpec};
+use crate::probe::RestartPolicy;
+use crate::task::{Owner, OwnerKind, Task};
+
+/// Longest job name, leaving room for the `-{index}-xxxxx` suffix of its task names.
+const MAX_NAME_LENGTH: usize = 51;
+
+/// Most completions a job can ask for, keeps the index in task names at 4 digits.
+pub const MAX_COMPLETIONS: u32 = 10_000;
+
+/// Environment variable telling a job's task which completion index it works on.
+pub const JOB_INDEX_ENV: &str = "ORCH_JOB_INDEX";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub enum JobState {
+    /// Tasks are still running or waiting to be retried
+    #[default]
+    Running,
+    /// Every index succeeded
+    Complete,
+    /// More tasks failed than the backoff limit allows
+    Failed,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub enum IndexState {
+    /// No task was started for the index yet
+    #[default]
+    Pending,
+    /// A task of the index hasn't finished yet
+    Running,
+    /// A task of the index exited successfully
+    Succeeded,
+    /// The last task of the index failed, another one is started after a backoff
+    Failed,
+}
+
+/// The outcome of one completion index of a job.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct IndexResult {
+    pub index: u32,
+    pub state: IndexState,
+    /// Failed tasks of the index so far
+    pub failures: u32,
+    /// The latest task of the index
+    pub task: Option<String>,
+    /// Why the latest task finished, e.g. `Exited with code 1`
+    pub reason: Option<String>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+/// What the job controller last saw of the job's tasks.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct JobStatus {
+    pub state: JobState,
+    /// Unfinished tasks
+    pub active: u32,
+    /// Indexes that succeeded
+    pub succeeded: u32,
+    /// Failed tasks, counted against the backoff limit
+    pub failed: u32,
+    /// One entry per completion index
+    pub results: Vec<IndexResult>,
+    /// Why the job failed
+    pub message: Option<String>,
+    /// Time the job reached `Complete` or `Failed`
+    pub finished_at: Option<DateTime<Utc>>,
+    /// Whether the finished tasks were deleted after `ttl_secs_after_finished`
+    #[serde(default)]
+    pub cleaned_up: bool,
+}
+
+/// Runs tasks of the template to completion, once per index from 0 to `completions - 1`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Job {
+    pub name: String,
+
+    /// Unique per job, so a job created again under the same name doesn't take over the old tasks
+    pub uid: Uuid,
+
+    /// Bumped by the manager on every change, used for optimistic concurrency (`If-Match`)
+    #[serde(default)]
+    pub resource_version: u64,
+
+    #[serde(default)]
+    pub labels: Labels,
+
+    /// How many tasks have to exit successfully, each gets its own index
+    pub completions: u32,
+
+    /// How many tasks may run at once
+    pub parallelism: u32,
+
+    /// How many failed tasks the job tolerates before it fails
+    pub backoff_limit: u32,
+
+    /// Deletes the tasks this many seconds after the job finished, they are kept when unset
+    pub ttl_secs_after_finished: Option<u64>,
+
+    /// What each task runs, its name is left out
+    pub template: TaskSpec,
+
+    #[serde(default)]
+    pub status: JobStatus,
+
+    /// The cron job that started this job, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<Owner>,
+
+    pub created_at: DateTime<Utc>,
+}
+
+impl Job {
+    /// Whether the task was created by this job.
+    pub fn owns(&self, task: &Task) -> bool {
+        task.owner
+            .as_ref()
+            .is_some_and(|owner| owner.kind == OwnerKind::Job && owner.uid == Some(self.uid))
+    }
+
+    /// The completion index the task works on, `None` for tasks of other owners.
+    pub fn index_of(&self, task: &Task) -> Option<u32> {
+        task.owner.as_ref().filter(|_| self.owns(task)).and_then(|owner| owner.index)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.status.state != JobState::Running
+    }
+
+    /// A new pending task for the index, named `{job}-{index}-xxxxx` and told its index through the environment.
+    pub fn new_task(&self, index: u32) -> Task {
+        let mut spec = self.template.clone();
+        let suffix = Uuid::new_v4().simple().to_string();
+        spec.name = format!("{}-{}-{}", self.name, index, &suffix[..5]);
+        spec.env.insert(JOB_INDEX_ENV.to_string(), index.to_string());
+
+        let mut task = spec.into_task();
+        task.owner = Some(Owner {
+            kind: OwnerKind::Job,
+            name: self.name.clone(),
+            revision: 0,
+            index: Some(index),
+            uid: Some(self.uid),
+        });
+        task
+    }
+}
+
+/// The user-controlled part of a job, as written in a manifest or sent to `POST /jobs`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct JobSpec {
+    /// Left out in the job template of a cron job, which names its jobs itself
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub name: String,
+
+    #[serde(default)]
+    pub labels: Labels,
+
+    #[serde(default = "default_one")]
+    pub completions: u32,
+
+    #[serde(default = "default_one")]
+    pub parallelism: u32,
+
+    #[serde(default = "default_backoff_limit")]
+    pub backoff_limit: u32,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl_secs_after_finished: Option<u64>,
+
+    pub template: TaskSpec,
+}
+
+fn default_one() -> u32 {
+    1
+}
+
+fn default_backoff_limit() -> u32 {
+    6
+}
+
+impl JobSpec {
+    /// The spec a job was created from.
+    pub fn from_job(job: &Job) -> Self {
+        JobSpec {
+            name: job.name.clone(),
+            labels: job.labels.clone(),
+            completions: job.completions,
+            parallelism: job.parallelism,
+            backoff_limit: job.backoff_limit,
+            ttl_secs_after_finished: job.ttl_secs_after_finished,
+            template: job.template.clone(),
+        }
+    }
+
+    pub fn into_job(self) -> Job {
+        Job {
+            name: self.name,
+            uid: Uuid::new_v4(),
+            resource_version: 0,
+            labels: self.labels,
+            completions: self.completions,
+            parallelism: self.parallelism,
+            backoff_limit: self.backoff_limit,
+            ttl_secs_after_finished: self.ttl_secs_after_finished,
+            template: self.template,
+            status: JobStatus::default(),
+            owner: None,
+            created_at: Utc::now(),
+        }
+    }
+
+    /// Overwrites the fields of `job` that may change while it runs, see [`JobSpec::immutable_changes`].
+    pub fn apply_to(self, job: &mut Job) {
+        job.labels = self.labels;
+        job.parallelism = self.parallelism;
+        job.ttl_secs_after_finished = self.ttl_secs_after_finished;
+    }
+
+    /// The fields that differ from `job` but can't change once it exists, the job has to be replaced instead.
+    pub fn immutable_changes(&self, job: &Job) -> Vec<FieldError> {
+        let message = "can't change once the job exists, delete and create it again";
+        let mut errors = Vec::new();
+        if self.completions != job.completions {
+            errors.push(FieldError::new("completions", message));
+        }
+        if self.backoff_limit != job.backoff_limit {
+            errors.push(FieldError::new("backoff_limit", message));
+        }
+        if self.template != job.template {
+            errors.push(FieldError::new("template", message));
+        }
+        errors
+    }
+
+    /// Checks the spec, returns one error per invalid field. Empty means valid.
+    pub fn validate(&self) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+
+        if let Err(message) = validate_name(&self.name) {
+            errors.push(FieldError::new("name", message));
+        } else if self.name.len() > MAX_NAME_LENGTH {
+            errors.push(FieldError::new("name", format!("must be at most {} characters", MAX_NAME_LENGTH)));
+        }
+        errors.extend(self.validate_template());
+
+        errors
+    }
+
+    /// Checks everything but the name, like `validate` does for the job template of a cron job.
+    pub fn validate_template(&self) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+
+        for (key, value) in &self.labels {
+            if let Err(message) = labels::validate_key(key).and_then(|_| labels::validate_value(value)) {
+                errors.push(FieldError::new(format!("labels.{}", key), message));
+            }
+        }
+        if !(1..=MAX_COMPLETIONS).contains(&self.completions) {
+            errors.push(FieldError::new("completions", format!("must be between 1 and {}", MAX_COMPLETIONS)));
+        }
+        if self.parallelism == 0 {
+            errors.push(FieldError::new("parallelism", "must be at least 1"));
+        }
+        if !self.template.name.is_empty() {
+            errors.push(FieldError::new("template.name", "must be left out, tasks are named after the job"));
+        }
+        if self.template.restart_policy == RestartPolicy::Always {
+            errors.push(FieldError::new("template.restart_policy", "must be Never or OnFailure, tasks of a job have to finish"));
+        }
+        errors.extend(
+            self.template
+                .validate_template()
+                .into_iter()
+                .map(|e| FieldError::new(format!("template.{}", e.field), e.message)),
+        );
+
+        errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(name: &str) -> JobSpec {
+        JobSpec {
+            name: name.to_string(),
+            labels: Labels::new(),
+            completions: 3,
+            parallelism: 2,
+            backoff_limit: 6,
+            ttl_secs_after_finished: None,
+            template: TaskSpec {
+                restart_policy: RestartPolicy::Never,
+                ..TaskSpec::from_task(&Task::new(String::new(), "busybox".to_string()))
+            },
+        }
+    }
+
+    #[test]
+    fn test_new_task_has_an_index() {
+        let job = spec("batch").into_job();
+        let task = job.new_task(2);
+
+        assert!(task.name.starts_with("batch-2-"));
+        assert_eq!(task.env[JOB_INDEX_ENV], "2");
+        assert_eq!(job.index_of(&task), Some(2));
+        assert_eq!(task.owner.as_ref().unwrap().to_string(), "job/batch");
+        assert_eq!(spec("other").into_job().index_of(&task), None);
+        // created again under the same name
+        assert_eq!(spec("batch").into_job().index_of(&task), None);
+    }
+
+    #[test]
+    fn test_validate_job() {
+        assert!(spec("batch").validate().is_empty());
+
+        let mut invalid = spec(&"a".repeat(55));
+        invalid.completions = 0;
+        invalid.parallelism = 0;
+        invalid.template.name = "batch".to_string();
+        invalid.template.restart_policy = RestartPolicy::Always;
+        let fields: Vec<String> = invalid.validate().into_iter().map(|e| e.field).collect();
+        assert_eq!(fields, ["name", "completions", "parallelism", "template.name", "template.restart_policy"]);
+
+        let job = spec("batch").into_job();
+        let mut update = spec("batch");
+        update.parallelism = 5;
+        assert!(update.immutable_changes(&job).is_empty());
+        update.completions = 4;
+        update.template.image = "alpine".to_string();
+        let fields: Vec<String> = update.immutable_changes(&job).into_iter().map(|e| e.field).collect();
+        assert_eq!(fields, ["completions", "template"]);
+    }
+}
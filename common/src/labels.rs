@@ -0,0 +1,287 @@
+use std::collections::BTreeMap;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// Key/value pairs attached to tasks and nodes, used to group and select them.
+pub type Labels = BTreeMap<String, String>;
+
+/// Checks a label or annotation key: an optional `prefix/` (lowercase DNS name) followed by
+/// up to 63 letters, digits, `-`, `_` and `.`, starting and ending alphanumeric.
+pub fn validate_key(key: &str) -> Result<(), String> {
+    let (prefix, name) = match key.split_once('/') {
+        Some((prefix, name)) => (Some(prefix), name),
+        None => (None, key),
+    };
+
+    if let Some(prefix) = prefix
+        && (prefix.is_empty()
+            || prefix.len() > 253
+            || !prefix.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '.'))
+    {
+        return Err(format!("invalid prefix '{}' in key '{}', expected a lowercase DNS name", prefix, key));
+    }
+
+    if name.is_empty() {
+        return Err(format!("key '{}' has an empty name", key));
+    }
+    validate_value(name).map_err(|_| {
+        format!(
+            "invalid key '{}', names are up to 63 letters, digits, '-', '_' or '.', starting and ending alphanumeric",
+            key
+        )
+    })
+}
+
+/// Checks a label value: empty, or up to 63 letters, digits, `-`, `_` and `.`, starting and ending alphanumeric.
+pub fn validate_value(value: &str) -> Result<(), String> {
+    let valid = value.is_empty()
+        || (value.len() <= 63
+            && value.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+            && value.starts_with(|c: char| c.is_ascii_alphanumeric())
+            && value.ends_with(|c: char| c.is_ascii_alphanumeric()));
+
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "invalid value '{}', values are up to 63 letters, digits, '-', '_' or '.', starting and ending alphanumeric",
+            value
+        ))
+    }
+}
+
+/// Parses `key=value` pairs separated by commas, e.g. `disk=ssd,zone=eu-1`.
+pub fn parse_labels(input: &str) -> Result<Labels, String> {
+    let mut labels = Labels::new();
+    for pair in input.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (key, value) = pair
+            .split_once('=')
+            .ok_or_else(|| format!("invalid label '{}', expected key=value", pair))?;
+        let (key, value) = (key.trim(), value.trim());
+        validate_key(key)?;
+        validate_value(value)?;
+        labels.insert(key.to_string(), value.to_string());
+    }
+    Ok(labels)
+}
+
+/// How a requirement tests the value of its key.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Operator {
+    /// `key=value` or `key==value`
+    Equals(String),
+    /// `key!=value`, also matches if the key is missing
+    NotEquals(String),
+    /// `key in (a,b)`
+    In(Vec<String>),
+    /// `key notin (a,b)`, also matches if the key is missing
+    NotIn(Vec<String>),
+    /// `key`
+    Exists,
+    /// `!key`
+    DoesNotExist,
+}
+
+/// A single condition of a selector.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Requirement {
+    pub key: String,
+    pub operator: Operator,
+}
+
+impl std::fmt::Display for Requirement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.operator {
+            Operator::Equals(value) => write!(f, "{}={}", self.key, value),
+            Operator::NotEquals(value) => write!(f, "{}!={}", self.key, value),
+            Operator::In(values) => write!(f, "{} in ({})", self.key, values.join(",")),
+            Operator::NotIn(values) => write!(f, "{} notin ({})", self.key, values.join(",")),
+            Operator::Exists => write!(f, "{}", self.key),
+            Operator::DoesNotExist => write!(f, "!{}", self.key),
+        }
+    }
+}
+
+impl Requirement {
+    pub fn matches(&self, labels: &Labels) -> bool {
+        let value = labels.get(&self.key);
+        match &self.operator {
+            Operator::Equals(expected) => value == Some(expected),
+            Operator::NotEquals(expected) => value != Some(expected),
+            Operator::In(values) => value.is_some_and(|v| values.contains(v)),
+            Operator::NotIn(values) => value.is_none_or(|v| !values.contains(v)),
+            Operator::Exists => value.is_some(),
+            Operator::DoesNotExist => value.is_none(),
+        }
+    }
+}
+
+/// A label selector like `app=web,tier!=db,env in (prod,staging)`.
+///
+/// Requirements are separated by commas and all of them must match. The empty selector
+/// matches everything.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Selector {
+    pub requirements: Vec<Requirement>,
+}
+
+impl Selector {
+    pub fn matches(&self, labels: &Labels) -> bool {
+        self.requirements.iter().all(|r| r.matches(labels))
+    }
+}
+
+impl std::fmt::Display for Selector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let requirements: Vec<String> = self.requirements.iter().map(ToString::to_string).collect();
+        write!(f, "{}", requirements.join(","))
+    }
+}
+
+/// Selectors are written as strings in manifests and JSON, e.g. `"app=web,tier!=db"`.
+impl Serialize for Selector {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Selector {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let selector = String::deserialize(deserializer)?;
+        selector
+            .parse()
+            .map_err(|e| de::Error::custom(format!("invalid label selector '{}': {}", selector, e)))
+    }
+}
+
+impl std::str::FromStr for Selector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut requirements = Vec::new();
+
+        // split on the commas that aren't inside `( )`
+        let mut depth = 0;
+        let mut start = 0;
+        let mut terms = Vec::new();
+        for (i, c) in s.char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                ',' if depth == 0 => {
+                    terms.push(&s[start..i]);
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        terms.push(&s[start..]);
+
+        for term in terms.into_iter().map(str::trim).filter(|t| !t.is_empty()) {
+            requirements.push(parse_requirement(term)?);
+        }
+
+        Ok(Selector { requirements })
+    }
+}
+
+fn parse_requirement(term: &str) -> Result<Requirement, String> {
+    let requirement = |key: &str, operator| -> Result<Requirement, String> {
+        let key = key.trim();
+        validate_key(key)?;
+        Ok(Requirement { key: key.to_string(), operator })
+    };
+    let value = |value: &str| -> Result<String, String> {
+        let value = value.trim();
+        validate_value(value)?;
+        Ok(value.to_string())
+    };
+
+    if let Some(open) = term.find('(') {
+        let values = term[open + 1..]
+            .strip_suffix(')')
+            .ok_or_else(|| format!("missing ')' in '{}'", term))?
+            .split(',')
+            .map(value)
+            .collect::<Result<Vec<String>, String>>()?;
+
+        let mut words = term[..open].split_whitespace();
+        return match (words.next(), words.next(), words.next()) {
+            (Some(key), Some("in"), None) => requirement(key, Operator::In(values)),
+            (Some(key), Some("notin"), None) => requirement(key, Operator::NotIn(values)),
+            _ => Err(format!("invalid requirement '{}', expected 'key in (a,b)' or 'key notin (a,b)'", term)),
+        };
+    }
+
+    if let Some(key) = term.strip_prefix('!') {
+        return requirement(key, Operator::DoesNotExist);
+    }
+    if let Some((key, v)) = term.split_once("!=") {
+        return requirement(key, Operator::NotEquals(value(v)?));
+    }
+    if let Some((key, v)) = term.split_once("==").or_else(|| term.split_once('=')) {
+        return requirement(key, Operator::Equals(value(v)?));
+    }
+    requirement(term, Operator::Exists)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(pairs: &[(&str, &str)]) -> Labels {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_parse_selector() {
+        let selector: Selector = "app=web, tier!=db,env in (prod, staging),!canary,team".parse().unwrap();
+        assert_eq!(
+            selector.requirements.iter().map(|r| r.operator.clone()).collect::<Vec<_>>(),
+            vec![
+                Operator::Equals("web".to_string()),
+                Operator::NotEquals("db".to_string()),
+                Operator::In(vec!["prod".to_string(), "staging".to_string()]),
+                Operator::DoesNotExist,
+                Operator::Exists,
+            ]
+        );
+        assert_eq!(selector.requirements[2].key, "env");
+
+        assert!("".parse::<Selector>().unwrap().requirements.is_empty());
+        assert!("env in (a,b".parse::<Selector>().is_err());
+        assert!("app=we b".parse::<Selector>().is_err());
+        assert!("env within (a)".parse::<Selector>().is_err());
+    }
+
+    #[test]
+    fn test_selector_round_trip() {
+        let selector: Selector = "app==web, tier!=db,env in (prod, staging),!canary".parse().unwrap();
+        assert_eq!(selector.to_string(), "app=web,tier!=db,env in (prod,staging),!canary");
+
+        let json = serde_json::to_string(&selector).unwrap();
+        assert_eq!(serde_json::from_str::<Selector>(&json).unwrap(), selector);
+        assert!(serde_json::from_str::<Selector>("\"env in (a\"").is_err());
+    }
+
+    #[test]
+    fn test_selector_matches() {
+        let web = labels(&[("app", "web"), ("env", "prod")]);
+        let db = labels(&[("app", "db"), ("tier", "db")]);
+
+        let selector: Selector = "tier!=db,env notin (dev)".parse().unwrap();
+        assert!(selector.matches(&web));
+        assert!(!selector.matches(&db));
+
+        let selector: Selector = "app in (web,db),!env".parse().unwrap();
+        assert!(!selector.matches(&web));
+        assert!(selector.matches(&db));
+    }
+
+    #[test]
+    fn test_parse_labels() {
+        assert_eq!(parse_labels("disk=ssd, example.com/zone=eu-1").unwrap(), labels(&[("disk", "ssd"), ("example.com/zone", "eu-1")]));
+        assert!(parse_labels("disk").is_err());
+        assert!(parse_labels("Bad/key=x").is_err());
+    }
+}
@@ -1,7 +1,33 @@
+pub mod affinity;
+pub mod cron;
+pub mod cronjob;
 pub mod error;
+pub mod http;
+pub mod job;
+pub mod labels;
+pub mod manifest;
 pub mod node;
+pub mod probe;
+pub mod service;
+pub mod stats;
+pub mod taint;
 pub mod task;
+pub mod watch;
+pub mod workflow;
 
-pub use error::OrchError;
-pub use node::NodeStatus;
-pub use task::{Task, TaskStatus};
+pub use affinity::{Affinity, NodeAffinity, Preference, TaskAffinity};
+pub use chrono_tz::Tz;
+pub use cron::Schedule;
+pub use cronjob::{ConcurrencyPolicy, CronJob, CronJobSpec, CronJobStatus};
+pub use error::{FieldError, OrchError};
+pub use job::{IndexResult, IndexState, Job, JobSpec, JobState, JobStatus};
+pub use labels::{Labels, Selector};
+pub use manifest::{DryRun, Manifest, ManifestFormat, TaskSpec};
+pub use node::{Node, NodeDetails, NodeStatus, NodeUpdate, Resources};
+pub use probe::{HealthStatus, Probe, ProbeAction, RestartPolicy, TaskHealth};
+pub use service::{Backoff, Rollout, RolloutState, Service, ServiceDetails, ServiceRevision, ServiceSpec, UpdateStrategy};
+pub use stats::{Heartbeat, NodeMetrics, TaskMetrics, TaskUsage, Usage, UsageReport, UsageSample};
+pub use taint::{Taint, TaintEffect, Toleration, TolerationOperator};
+pub use task::{Owner, OwnerKind, PriorityClass, Rescheduling, StatusTransition, Task, TaskStatus};
+pub use watch::{EventType, WatchEvent, WatchObject};
+pub use workflow::{Step, StepState, StepStatus, Workflow, WorkflowSpec, WorkflowState, WorkflowStatus};
@@ -0,0 +1,611 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::affinity::{Affinity, Preference};
+use crate::error::FieldError;
+use crate::labels::{self, Labels};
+use crate::probe::{Probe, ProbeAction, RestartPolicy};
+use crate::cronjob::CronJobSpec;
+use crate::job::JobSpec;
+use crate::service::ServiceSpec;
+use crate::taint::{Toleration, TolerationOperator};
+use crate::task::{PriorityClass, Task};
+use crate::workflow::WorkflowSpec;
+
+/// The user-controlled part of a task, as written in a manifest or sent to `POST /tasks`.
+///
+/// Everything else on a `Task` (status, node, history, ...) is owned by the cluster.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct TaskSpec {
+    /// Left out in the template of a service, which names its tasks itself
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    pub image: String,
+
+    #[serde(default)]
+    pub labels: Labels,
+
+    #[serde(default)]
+    pub annotations: Labels,
+
+    /// Memory requirement in MB
+    #[serde(default = "default_memory")]
+    pub memory: i32,
+
+    /// CPU requirement in cores
+    #[serde(default = "default_cpu")]
+    pub cpu: f32,
+
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+
+    /// Only nodes with all of these labels can run the task
+    #[serde(default)]
+    pub node_selector: Labels,
+
+    #[serde(default, skip_serializing_if = "Affinity::is_empty")]
+    pub affinity: Affinity,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tolerations: Vec<Toleration>,
+
+    #[serde(default)]
+    pub liveness_probe: Option<Probe>,
+
+    #[serde(default)]
+    pub readiness_probe: Option<Probe>,
+
+    #[serde(default)]
+    pub restart_policy: RestartPolicy,
+
+    #[serde(default)]
+    pub priority_class: PriorityClass,
+}
+
+fn default_memory() -> i32 {
+    256
+}
+
+fn default_cpu() -> f32 {
+    0.5
+}
+
+impl TaskSpec {
+    /// The spec a task was created from.
+    pub fn from_task(task: &Task) -> Self {
+        TaskSpec {
+            name: task.name.clone(),
+            image: task.image.clone(),
+            labels: task.labels.clone(),
+            annotations: task.annotations.clone(),
+            memory: task.memory,
+            cpu: task.cpu,
+            env: task.env.clone(),
+            node_selector: task.node_selector.clone(),
+            affinity: task.affinity.clone(),
+            tolerations: task.tolerations.clone(),
+            liveness_probe: task.liveness_probe.clone(),
+            readiness_probe: task.readiness_probe.clone(),
+            restart_policy: task.restart_policy.clone(),
+            priority_class: task.priority_class,
+        }
+    }
+
+    /// Creates a new pending task running this spec.
+    pub fn into_task(self) -> Task {
+        let mut task = Task::new(self.name.clone(), self.image.clone());
+        self.apply_to(&mut task);
+        task
+    }
+
+    /// Overwrites the spec fields of `task`, leaving its state untouched.
+    pub fn apply_to(self, task: &mut Task) {
+        task.name = self.name;
+        task.image = self.image;
+        task.labels = self.labels;
+        task.annotations = self.annotations;
+        task.memory = self.memory;
+        task.cpu = self.cpu;
+        task.env = self.env;
+        task.node_selector = self.node_selector;
+        task.affinity = self.affinity;
+        task.tolerations = self.tolerations;
+        task.liveness_probe = self.liveness_probe;
+        task.readiness_probe = self.readiness_probe;
+        task.restart_policy = self.restart_policy;
+        task.priority_class = self.priority_class;
+    }
+
+    /// Checks the spec, returns one error per invalid field. Empty means valid.
+    pub fn validate(&self) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+
+        if self.name.is_empty() {
+            errors.push(FieldError::new("name", "must not be empty"));
+        }
+        errors.extend(self.validate_template());
+
+        errors
+    }
+
+    /// Problems that don't keep the task from being created, shown by the CLI.
+    ///
+    /// Tasks were named freely before manifests existed, so a name that isn't a DNS label like the
+    /// names of services and the other resources is only worth a warning.
+    pub fn warnings(&self) -> Vec<FieldError> {
+        match validate_name(&self.name) {
+            Err(message) if !self.name.is_empty() => vec![FieldError::new("name", message)],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Checks everything but the name, like `validate` does for the template of a service.
+    pub fn validate_template(&self) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+
+        if self.image.trim().is_empty() {
+            errors.push(FieldError::new("image", "must not be empty"));
+        } else if self.image.contains(char::is_whitespace) {
+            errors.push(FieldError::new("image", "must not contain whitespace"));
+        }
+        for (key, value) in &self.labels {
+            if let Err(message) = labels::validate_key(key).and_then(|_| labels::validate_value(value)) {
+                errors.push(FieldError::new(format!("labels.{}", key), message));
+            }
+        }
+        for key in self.annotations.keys() {
+            if let Err(message) = labels::validate_key(key) {
+                errors.push(FieldError::new(format!("annotations.{}", key), message));
+            }
+        }
+        if self.memory <= 0 {
+            errors.push(FieldError::new("memory", "must be a positive number of MB"));
+        }
+        if !(self.cpu.is_finite() && self.cpu > 0.0) {
+            errors.push(FieldError::new("cpu", "must be a positive number of cores"));
+        }
+        for key in self.env.keys() {
+            if key.is_empty() || key.contains('=') {
+                errors.push(FieldError::new(format!("env.{}", key), "must be a non-empty name without '='"));
+            }
+        }
+        for (key, value) in &self.node_selector {
+            if let Err(message) = labels::validate_key(key).and_then(|_| labels::validate_value(value)) {
+                errors.push(FieldError::new(format!("node_selector.{}", key), message));
+            }
+        }
+        validate_affinity(&self.affinity, &mut errors);
+        for (i, toleration) in self.tolerations.iter().enumerate() {
+            validate_toleration(&format!("tolerations[{}]", i), toleration, &mut errors);
+        }
+        if let Some(probe) = &self.liveness_probe {
+            validate_probe("liveness_probe", probe, &mut errors);
+        }
+        if let Some(probe) = &self.readiness_probe {
+            validate_probe("readiness_probe", probe, &mut errors);
+        }
+
+        errors
+    }
+}
+
+/// Names follow DNS labels: up to 63 lowercase letters, digits and `-`, starting and ending alphanumeric.
+pub(crate) fn validate_name(name: &str) -> Result<(), &'static str> {
+    if name.is_empty() {
+        return Err("must not be empty");
+    }
+    if name.len() > 63 {
+        return Err("must be at most 63 characters");
+    }
+    if !name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-') {
+        return Err("may only contain lowercase letters, digits and '-'");
+    }
+    if name.starts_with('-') || name.ends_with('-') {
+        return Err("must start and end with a letter or digit");
+    }
+    Ok(())
+}
+
+fn validate_affinity(affinity: &Affinity, errors: &mut Vec<FieldError>) {
+    let preferences = [
+        ("affinity.node_affinity.preferred", &affinity.node_affinity.preferred),
+        ("affinity.task_affinity.preferred", &affinity.task_affinity.preferred),
+        ("affinity.task_anti_affinity.preferred", &affinity.task_anti_affinity.preferred),
+    ];
+    for (field, preferred) in preferences {
+        for (i, Preference { weight, .. }) in preferred.iter().enumerate() {
+            if !(1..=100).contains(weight) {
+                errors.push(FieldError::new(format!("{}[{}].weight", field, i), "must be between 1 and 100"));
+            }
+        }
+    }
+}
+
+fn validate_toleration(field: &str, toleration: &Toleration, errors: &mut Vec<FieldError>) {
+    match (&toleration.key, &toleration.operator) {
+        (None, TolerationOperator::Equal) => {
+            errors.push(FieldError::new(format!("{}.key", field), "is required unless the operator is Exists"));
+        }
+        (_, TolerationOperator::Exists) if !toleration.value.is_empty() => {
+            errors.push(FieldError::new(format!("{}.value", field), "must be empty when the operator is Exists"));
+        }
+        _ => {}
+    }
+}
+
+fn validate_probe(field: &str, probe: &Probe, errors: &mut Vec<FieldError>) {
+    match &probe.action {
+        ProbeAction::Http { path, port } => {
+            if !path.starts_with('/') {
+                errors.push(FieldError::new(format!("{}.action.path", field), "must start with '/'"));
+            }
+            if *port == 0 {
+                errors.push(FieldError::new(format!("{}.action.port", field), "must not be 0"));
+            }
+        }
+        ProbeAction::Tcp { port } => {
+            if *port == 0 {
+                errors.push(FieldError::new(format!("{}.action.port", field), "must not be 0"));
+            }
+        }
+        ProbeAction::Exec { command } => {
+            if command.is_empty() {
+                errors.push(FieldError::new(format!("{}.action.command", field), "must not be empty"));
+            }
+        }
+    }
+
+    for (name, value) in [
+        ("period_secs", probe.period_secs),
+        ("timeout_secs", probe.timeout_secs),
+        ("success_threshold", probe.success_threshold as u64),
+        ("failure_threshold", probe.failure_threshold as u64),
+    ] {
+        if value == 0 {
+            errors.push(FieldError::new(format!("{}.{}", field, name), "must be at least 1"));
+        }
+    }
+}
+
+/// Answer of a dry-run create or update: the task as it would be stored and where it would run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DryRun {
+    pub task: Task,
+    /// The node the scheduler would pick right now
+    pub node_id: Option<String>,
+    /// Why the scheduler couldn't place the task right now
+    pub unschedulable: Option<String>,
+}
+
+/// A resource declared in a manifest file, tagged by its `kind`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "kind")]
+pub enum Manifest {
+    Task(TaskSpec),
+    Service(ServiceSpec),
+    Job(JobSpec),
+    CronJob(CronJobSpec),
+    Workflow(WorkflowSpec),
+}
+
+impl Manifest {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Manifest::Task(_) => "task",
+            Manifest::Service(_) => "service",
+            Manifest::Job(_) => "job",
+            Manifest::CronJob(_) => "cronjob",
+            Manifest::Workflow(_) => "workflow",
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Manifest::Task(spec) => &spec.name,
+            Manifest::Service(spec) => &spec.name,
+            Manifest::Job(spec) => &spec.name,
+            Manifest::CronJob(spec) => &spec.name,
+            Manifest::Workflow(spec) => &spec.name,
+        }
+    }
+}
+
+/// The file formats manifests can be written in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ManifestFormat {
+    /// One resource per document, documents separated by `---`
+    Yaml,
+    /// One `[[resources]]` table per resource
+    Toml,
+}
+
+impl ManifestFormat {
+    /// Picks the format from the file extension.
+    pub fn from_path(path: &Path) -> Result<Self, ManifestError> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("yaml") | Some("yml") => Ok(ManifestFormat::Yaml),
+            Some("toml") => Ok(ManifestFormat::Toml),
+            _ => Err(ManifestError {
+                line: None,
+                message: format!("{} is neither a .yaml, .yml nor .toml file", path.display()),
+            }),
+        }
+    }
+}
+
+/// A manifest that couldn't be read, `line` is 1-based if known.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ManifestError {
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl std::fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}", line, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for ManifestError {}
+
+/// A resource of a manifest file together with where it was declared.
+#[derive(Debug, Clone)]
+pub struct ManifestDocument {
+    pub manifest: Manifest,
+    /// 0-based line indices of the file the resource spans
+    pub lines: Range<usize>,
+}
+
+impl ManifestDocument {
+    /// Finds the 1-based line declaring `field` (e.g. `liveness_probe.period_secs`) in `source`.
+    ///
+    /// Each path segment is looked up after the line of the previous one. If a segment can't be
+    /// found (e.g. the field was left out), the line of the closest enclosing field is returned.
+    pub fn locate(&self, source: &str, field: &str) -> usize {
+        let lines: Vec<&str> = source.lines().collect();
+        find_field(&lines, self.lines.clone(), field) + 1
+    }
+}
+
+/// 0-based index of the line declaring `field` within `range`, see `ManifestDocument::locate`.
+fn find_field(lines: &[&str], range: Range<usize>, field: &str) -> usize {
+    let end = range.end.min(lines.len());
+    let mut found = range.start;
+
+    // list indices like `preferred[0]` point at the list itself
+    for segment in field.split('.').map(|s| s.split('[').next().unwrap_or(s)) {
+        match (found..end).find(|&i| line_key(lines[i]) == Some(segment)) {
+            Some(i) => found = i,
+            None => break,
+        }
+    }
+
+    found
+}
+
+/// The key a line declares: `key:` in YAML, `key =` or a `[table.key]` header in TOML.
+fn line_key(line: &str) -> Option<&str> {
+    let line = line.trim();
+    let line = line.strip_prefix("- ").unwrap_or(line);
+
+    if line.starts_with('[') {
+        return line.trim_matches(['[', ']']).rsplit('.').next();
+    }
+
+    let end = line.find([':', '='])?;
+    Some(line[..end].trim().trim_matches(['"', '\'']))
+}
+
+/// Parses all resources of a manifest file.
+pub fn parse_manifests(source: &str, format: ManifestFormat) -> Result<Vec<ManifestDocument>, ManifestError> {
+    match format {
+        ManifestFormat::Yaml => parse_yaml(source),
+        ManifestFormat::Toml => parse_toml(source),
+    }
+}
+
+fn parse_yaml(source: &str) -> Result<Vec<ManifestDocument>, ManifestError> {
+    let lines: Vec<&str> = source.lines().collect();
+    let mut documents = Vec::new();
+    let mut start = 0;
+
+    // split on `---` ourselves, so each document knows its lines
+    for end in (0..lines.len()).filter(|&i| lines[i].trim_end() == "---").chain([lines.len()]) {
+        let range = start..end;
+        start = end + 1;
+
+        let content = lines[range.clone()].join("\n");
+        let is_empty = lines[range.clone()]
+            .iter()
+            .all(|l| l.trim().is_empty() || l.trim_start().starts_with('#'));
+        if is_empty {
+            continue;
+        }
+
+        let manifest = serde_yaml::from_str::<Manifest>(&content).map_err(|e| {
+            // errors about the content of a resource carry no position, but usually name the field
+            let line = match e.location() {
+                Some(location) => range.start + location.line(),
+                None => match e.to_string().split('`').nth(1) {
+                    Some(field) => find_field(&lines, range.clone(), field) + 1,
+                    None => range.start + 1,
+                },
+            };
+            ManifestError {
+                line: Some(line),
+                message: e.to_string(),
+            }
+        })?;
+        documents.push(ManifestDocument { manifest, lines: range });
+    }
+
+    Ok(documents)
+}
+
+#[derive(Deserialize)]
+struct TomlManifests {
+    #[serde(default)]
+    resources: Vec<toml::Spanned<Manifest>>,
+}
+
+fn parse_toml(source: &str) -> Result<Vec<ManifestDocument>, ManifestError> {
+    let parsed = toml::from_str::<TomlManifests>(source).map_err(|e| ManifestError {
+        line: e.span().map(|span| source[..span.start].lines().count().max(1)),
+        message: e.message().to_string(),
+    })?;
+
+    // the parser knows where each resource is, whether it's a `[[resources]]` table or inline
+    let line_of = |offset: usize| source[..offset].matches('\n').count();
+    Ok(parsed
+        .resources
+        .into_iter()
+        .map(|resource| {
+            let span = resource.span();
+            let lines = line_of(span.start)..line_of(span.end.max(span.start + 1) - 1) + 1;
+            ManifestDocument { manifest: resource.into_inner(), lines }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const YAML: &str = "\
+kind: Task
+name: web
+image: nginx:latest
+---
+# the database
+kind: Task
+name: db
+image: postgres:16
+memory: 1024
+liveness_probe:
+  action:
+    Tcp:
+      port: 5432
+  period_secs: 0
+";
+
+    #[test]
+    fn test_parse_yaml_documents() {
+        let documents = parse_manifests(YAML, ManifestFormat::Yaml).unwrap();
+        assert_eq!(documents.len(), 2);
+        assert_eq!(documents[0].manifest.name(), "web");
+        assert_eq!(documents[1].lines, 4..14);
+
+        let Manifest::Task(db) = &documents[1].manifest else {
+            panic!("expected a task");
+        };
+        assert_eq!(db.memory, 1024);
+        assert_eq!(db.cpu, 0.5);
+        assert_eq!(db.validate(), vec![FieldError::new("liveness_probe.period_secs", "must be at least 1")]);
+        assert_eq!(documents[1].locate(YAML, "liveness_probe.period_secs"), 14);
+        assert_eq!(documents[1].locate(YAML, "env.HOME"), 5);
+    }
+
+    #[test]
+    fn test_parse_toml_resources() {
+        let source = "\
+[[resources]]
+kind = \"Task\"
+name = \"web\"
+image = \"nginx\"
+
+[[ resources ]]
+kind = \"Task\"
+name = \"Worker\"
+image = \"busybox\"
+";
+        let documents = parse_manifests(source, ManifestFormat::Toml).unwrap();
+        assert_eq!(documents.len(), 2);
+        assert_eq!(documents[1].lines, 5..9);
+
+        let Manifest::Task(spec) = &documents[1].manifest else {
+            panic!("expected a task");
+        };
+        assert!(spec.validate().is_empty());
+        assert_eq!(spec.warnings().len(), 1);
+        assert_eq!(documents[1].locate(source, "name"), 8);
+
+        let inline = "\
+resources = [
+  { kind = \"Task\", name = \"web\", image = \"nginx\" },
+  { kind = \"Task\", name = \"db\", image = \"postgres\", memory = 0 },
+]
+";
+        let documents = parse_manifests(inline, ManifestFormat::Toml).unwrap();
+        assert_eq!(documents.iter().map(|d| d.lines.clone()).collect::<Vec<_>>(), [1..2, 2..3]);
+        assert_eq!(documents[1].locate(inline, "memory"), 3);
+    }
+
+    #[test]
+    fn test_parse_affinity() {
+        let source = "\
+kind: Task
+name: web
+image: nginx
+node_selector:
+  disk: ssd
+affinity:
+  task_anti_affinity:
+    preferred:
+      - weight: 0
+        selector: app=web
+";
+        let documents = parse_manifests(source, ManifestFormat::Yaml).unwrap();
+        let Manifest::Task(spec) = &documents[0].manifest else {
+            panic!("expected a task");
+        };
+        assert_eq!(spec.node_selector.get("disk").map(String::as_str), Some("ssd"));
+        assert_eq!(spec.affinity.task_anti_affinity.preferred[0].selector.to_string(), "app=web");
+
+        let errors = spec.validate();
+        assert_eq!(errors[0].field, "affinity.task_anti_affinity.preferred[0].weight");
+        assert_eq!(documents[0].locate(source, &errors[0].field), 9);
+
+        let err = parse_manifests(&source.replace("app=web", "app in (web"), ManifestFormat::Yaml).unwrap_err();
+        assert!(err.message.contains("invalid label selector"), "{}", err.message);
+    }
+
+    #[test]
+    fn test_parse_service() {
+        let source = "\
+kind: Service
+name: web
+replicas: 3
+template:
+  image: nginx
+  cpu: 0
+";
+        let documents = parse_manifests(source, ManifestFormat::Yaml).unwrap();
+        let Manifest::Service(spec) = &documents[0].manifest else {
+            panic!("expected a service");
+        };
+        assert_eq!(documents[0].manifest.kind(), "service");
+        assert_eq!(spec.replicas, 3);
+
+        let errors = spec.validate();
+        assert_eq!(errors[0].field, "template.cpu");
+        assert_eq!(documents[0].locate(source, &errors[0].field), 6);
+    }
+
+    #[test]
+    fn test_parse_errors_have_lines() {
+        let err = parse_manifests("kind: Task\nname: web\nimage: nginx\n---\nkind: Task\nname: db\nimage: nginx\n  port: 80\n", ManifestFormat::Yaml)
+            .unwrap_err();
+        assert_eq!(err.line, Some(8));
+
+        let err = parse_manifests("kind: Task\nname: web\nimgae: nginx\n", ManifestFormat::Yaml).unwrap_err();
+        assert!(err.message.contains("imgae"), "{}", err.message);
+        assert_eq!(err.line, Some(3));
+    }
+}
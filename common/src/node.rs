@@ -1,4 +1,15 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::labels::Labels;
+use crate::taint::Taint;
+use crate::task::Task;
+
+/// Default port the worker's API server (logs, exec) listens on.
+pub const DEFAULT_WORKER_PORT: u16 = 3001;
+
 /// The operational status of a worker node
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
 pub enum NodeStatus {
     /// The node is online and sending heartbeats.
     Ready,
@@ -6,31 +17,108 @@ pub enum NodeStatus {
     NotReady,
 }
 
+/// An amount of CPU (cores), memory and disk (MB).
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct Resources {
+    pub cpu: f32,
+    pub memory: i32,
+    pub disk: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Node {
     /// Unique identifier for the machine (e.g., "worker-01")
     pub id: String,
+    /// Bumped by the manager on every change of the node
+    #[serde(default)]
+    pub resource_version: u64,
     pub name: String,
+    /// Identifying metadata used to select nodes (e.g., `disk=ssd`)
+    #[serde(default)]
+    pub labels: Labels,
+    /// Free-form metadata for tools and people
+    #[serde(default)]
+    pub annotations: Labels,
+    /// Keep tasks without a matching toleration away from the node
+    #[serde(default)]
+    pub taints: Vec<Taint>,
+    /// Cordoned: no new tasks are scheduled here, the ones already running stay
+    #[serde(default)]
+    pub unschedulable: bool,
     pub ip_address: String,
+    /// Port of the worker's API server, used by the manager to relay logs and exec sessions
+    #[serde(default = "default_api_port")]
+    pub api_port: u16,
     pub status: NodeStatus,
+    /// What the worker detected on its host
+    #[serde(default)]
+    pub capacity: Resources,
+    /// Set aside for the system daemons, the node's total is its capacity minus this
+    #[serde(default)]
+    pub reserved: Resources,
+    /// Allocatable memory in MB, what the scheduler hands out to tasks
     pub total_memory: i32,
+    /// Allocatable CPU in cores
     pub total_cpu: f32,
     pub available_memory: i32,
     pub available_cpu: f32,
+    /// When the worker last registered, it does so periodically as a heartbeat
+    #[serde(default)]
+    pub last_heartbeat: Option<DateTime<Utc>>,
 }
 
 impl Node {
     pub fn new(name: String, total_memory: i32, total_cpu: f32) -> Self {
         Node {
             id: name.clone(),
+            resource_version: 0,
             name,
+            labels: Labels::new(),
+            annotations: Labels::new(),
+            taints: Vec::new(),
+            unschedulable: false,
             ip_address: "127.0.0.1".to_string(),
+            api_port: DEFAULT_WORKER_PORT,
             status: NodeStatus::NotReady,
+            capacity: Resources { cpu: total_cpu, memory: total_memory, disk: 0 },
+            reserved: Resources::default(),
             total_memory,
             total_cpu,
             available_memory: total_memory,
             available_cpu: total_cpu,
+            last_heartbeat: None,
         }
     }
+
+    /// The `host:port` address of the worker's API server.
+    pub fn api_address(&self) -> String {
+        format!("{}:{}", self.ip_address, self.api_port)
+    }
+}
+
+fn default_api_port() -> u16 {
+    DEFAULT_WORKER_PORT
+}
+
+/// A node with the resources claimed by its unfinished tasks, served by `GET /nodes`.
+///
+/// `available_memory` and `available_cpu` of the node are what's left after `allocated_*`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NodeDetails {
+    #[serde(flatten)]
+    pub node: Node,
+    pub allocated_memory: i32,
+    pub allocated_cpu: f32,
+    /// Number of unfinished tasks assigned to the node
+    pub task_count: usize,
+}
+
+/// Response of the node maintenance endpoints (taints, cordon, drain).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NodeUpdate {
+    pub node: Node,
+    /// Tasks taken off the node: failed by a `NoExecute` taint or marked for rescheduling by a drain
+    pub evicted: Vec<Task>,
 }
 
 #[cfg(test)]
@@ -45,4 +133,19 @@ mod tests {
         assert_eq!(node.available_memory, 4096);
         assert!(matches!(node.status, NodeStatus::NotReady));
     }
+
+    #[test]
+    fn test_api_address() {
+        let node = Node::new("worker-1".to_string(), 4096, 4.0);
+        assert_eq!(node.api_address(), format!("127.0.0.1:{}", DEFAULT_WORKER_PORT));
+    }
+
+    #[test]
+    fn test_api_port_defaults_to_worker_port() {
+        let mut json = serde_json::to_value(Node::new("worker-1".to_string(), 4096, 4.0)).unwrap();
+        json.as_object_mut().unwrap().remove("api_port");
+
+        let node: Node = serde_json::from_value(json).unwrap();
+        assert_eq!(node.api_port, DEFAULT_WORKER_PORT);
+    }
 }
\ No newline at end of file
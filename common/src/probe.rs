@@ -0,0 +1,135 @@
+use serde::{Deserialize, Serialize};
+
+/// How a probe checks the container.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum ProbeAction {
+    /// `GET http://<container-ip>:<port><path>`, any 2xx/3xx response counts as success.
+    Http { path: String, port: u16 },
+    /// Succeeds if a TCP connection to `<container-ip>:<port>` can be opened.
+    Tcp { port: u16 },
+    /// Runs the command inside the container, exit code 0 counts as success.
+    Exec { command: Vec<String> },
+}
+
+/// A periodic health check executed by the worker against a running container.
+///
+/// Field semantics follow Kubernetes probes: the probe starts `initial_delay_secs` after the
+/// container started and runs every `period_secs`. The result only flips after
+/// `success_threshold` consecutive successes or `failure_threshold` consecutive failures.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Probe {
+    pub action: ProbeAction,
+
+    #[serde(default)]
+    pub initial_delay_secs: u64,
+
+    #[serde(default = "default_period_secs")]
+    pub period_secs: u64,
+
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+
+    #[serde(default = "default_success_threshold")]
+    pub success_threshold: u32,
+
+    #[serde(default = "default_failure_threshold")]
+    pub failure_threshold: u32,
+}
+
+impl Probe {
+    /// Creates a probe with the default timings (every 10s, 1s timeout, 3 failures to trip).
+    pub fn new(action: ProbeAction) -> Self {
+        Probe {
+            action,
+            initial_delay_secs: 0,
+            period_secs: default_period_secs(),
+            timeout_secs: default_timeout_secs(),
+            success_threshold: default_success_threshold(),
+            failure_threshold: default_failure_threshold(),
+        }
+    }
+}
+
+fn default_period_secs() -> u64 {
+    10
+}
+
+fn default_timeout_secs() -> u64 {
+    1
+}
+
+fn default_success_threshold() -> u32 {
+    1
+}
+
+fn default_failure_threshold() -> u32 {
+    3
+}
+
+/// What the worker does when a task's container exits or fails its liveness probe.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, Eq, PartialEq)]
+pub enum RestartPolicy {
+    /// Always restart the container, the task never finishes on its own.
+    #[default]
+    Always,
+    /// Restart the container when it's unhealthy or exits with a non-zero code,
+    /// the task is `Complete` once it exits with 0.
+    OnFailure,
+    /// Never restart, the task is `Complete` or `Failed` with the container.
+    Never,
+}
+
+impl RestartPolicy {
+    /// Whether a stopped container is started again, `failed` unless it exited with code 0.
+    pub fn restarts(&self, failed: bool) -> bool {
+        match self {
+            RestartPolicy::Always => true,
+            RestartPolicy::OnFailure => failed,
+            RestartPolicy::Never => false,
+        }
+    }
+}
+
+/// Result of a probe as seen by the manager.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, Eq, PartialEq)]
+pub enum HealthStatus {
+    /// No probe configured or no result yet.
+    #[default]
+    Unknown,
+    Healthy,
+    Unhealthy,
+}
+
+/// Health of a task's container as reported by the worker.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, Eq, PartialEq)]
+pub struct TaskHealth {
+    /// Result of the liveness probe
+    pub live: HealthStatus,
+    /// Result of the readiness probe
+    pub ready: HealthStatus,
+    /// How often the worker restarted the container after it exited or failed its liveness probe
+    pub restart_count: u32,
+    /// Details of the last failed probe
+    pub message: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_probe_defaults_on_deserialize() {
+        let json = r#"{"action": {"Tcp": {"port": 5432}}}"#;
+        let probe: Probe = serde_json::from_str(json).unwrap();
+        assert_eq!(probe, Probe::new(ProbeAction::Tcp { port: 5432 }));
+        assert_eq!(probe.failure_threshold, 3);
+    }
+
+    #[test]
+    fn test_restart_policies() {
+        let restarts = |policy: RestartPolicy| (policy.restarts(true), policy.restarts(false));
+        assert_eq!(restarts(RestartPolicy::Always), (true, true));
+        assert_eq!(restarts(RestartPolicy::OnFailure), (true, false));
+        assert_eq!(restarts(RestartPolicy::Never), (false, false));
+    }
+}
@@ -0,0 +1,391 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::error::FieldError;
+use crate::labels::{self, Labels};
+use crate::manifest::{validate_name, TaskSpec};
+use crate::task::{Owner, OwnerKind, Task};
+
+/// Longest service name, leaving room for the `-xxxxx` suffix of its task names.
+const MAX_NAME_LENGTH: usize = 57;
+
+/// How many revisions of the template a service remembers for rollbacks, the current one included.
+pub const REVISION_HISTORY_LIMIT: usize = 10;
+
+/// How a service replaces its tasks when the template changes.
+///
+/// New tasks are started and old ones stopped in steps, only counting tasks that are ready,
+/// so the service keeps serving throughout the update.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct UpdateStrategy {
+    /// How many of the `replicas` may be unavailable during the update
+    pub max_unavailable: u32,
+    /// How many tasks may run on top of the `replicas` during the update
+    pub max_surge: u32,
+}
+
+impl Default for UpdateStrategy {
+    fn default() -> Self {
+        UpdateStrategy { max_unavailable: 1, max_surge: 1 }
+    }
+}
+
+/// A template the service ran at some point, numbered from 1.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ServiceRevision {
+    pub revision: u64,
+    pub template: TaskSpec,
+    pub created_at: DateTime<Utc>,
+    /// What changed, e.g. `image nginx:1.26 -> nginx:1.27`
+    pub change_cause: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub enum RolloutState {
+    /// Tasks of an older revision are being replaced
+    Progressing,
+    /// Every task runs the current revision
+    #[default]
+    Complete,
+    /// A task of the current revision failed, the update stopped until the template changes again.
+    /// Missing replicas are still replaced, from the revision the old tasks run
+    Paused,
+}
+
+/// Where the service is in replacing its tasks after the last template change.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct Rollout {
+    pub state: RolloutState,
+    /// Why the rollout paused
+    pub message: Option<String>,
+}
+
+/// Replicas that failed one after another, their replacements start with a growing delay.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct Backoff {
+    /// Replicas that failed since all of them were last ready
+    pub failures: u32,
+    /// No replacements are started before this
+    pub retry_at: Option<DateTime<Utc>>,
+}
+
+/// Keeps `replicas` identical tasks running, replacing the ones that fail or disappear.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Service {
+    pub name: String,
+
+    /// Bumped by the manager on every change, used for optimistic concurrency (`If-Match`)
+    #[serde(default)]
+    pub resource_version: u64,
+
+    #[serde(default)]
+    pub labels: Labels,
+
+    /// How many tasks of the template should be running
+    pub replicas: u32,
+
+    /// What each task runs, its name is left out
+    pub template: TaskSpec,
+
+    #[serde(default)]
+    pub strategy: UpdateStrategy,
+
+    /// The revision of `template`, tasks record the revision they were created from
+    #[serde(default)]
+    pub revision: u64,
+
+    /// Previous templates and the current one, oldest first
+    #[serde(default)]
+    pub history: Vec<ServiceRevision>,
+
+    #[serde(default)]
+    pub rollout: Rollout,
+
+    #[serde(default)]
+    pub backoff: Backoff,
+
+    pub created_at: DateTime<Utc>,
+}
+
+impl Service {
+    /// Whether the task is one of this service's replicas, of any revision.
+    pub fn owns(&self, task: &Task) -> bool {
+        task.owner
+            .as_ref()
+            .is_some_and(|owner| owner.kind == OwnerKind::Service && owner.name == self.name)
+    }
+
+    /// Whether the task runs the current template.
+    ///
+    /// Revisions with the same template count as one, so the tasks of the revision a rollback
+    /// returns to are kept. Revisions that fell out of the history count as outdated.
+    pub fn is_updated(&self, task: &Task) -> bool {
+        let Some(owner) = task.owner.as_ref().filter(|_| self.owns(task)) else {
+            return false;
+        };
+        owner.revision == self.revision
+            || self.history.iter().any(|r| r.revision == owner.revision && r.template == self.template)
+    }
+
+    /// The owner recorded on the tasks of this service.
+    pub fn owner(&self) -> Owner {
+        Owner { kind: OwnerKind::Service, name: self.name.clone(), revision: self.revision, index: None, uid: None }
+    }
+
+    /// Switches to a new template as the next revision and starts rolling it out.
+    pub fn set_template(&mut self, template: TaskSpec, change_cause: String) {
+        self.revision += 1;
+        self.history.push(ServiceRevision {
+            revision: self.revision,
+            template: template.clone(),
+            created_at: Utc::now(),
+            change_cause,
+        });
+        if self.history.len() > REVISION_HISTORY_LIMIT {
+            self.history.remove(0);
+        }
+
+        self.template = template;
+        self.rollout = Rollout { state: RolloutState::Progressing, message: None };
+    }
+
+    /// Rolls out the template of an earlier revision again, by default the one before the current.
+    pub fn rollback(&mut self, to: Option<u64>) -> Result<(), String> {
+        let target = match to {
+            Some(revision) if revision == self.revision => {
+                return Err(format!("revision {} is the current one", revision));
+            }
+            Some(revision) => self.history.iter().find(|r| r.revision == revision),
+            None => self.history.iter().rev().find(|r| r.revision != self.revision),
+        };
+        let Some(target) = target else {
+            return Err(match to {
+                Some(revision) => format!("revision {} is not in the history", revision),
+                None => "there is no previous revision".to_string(),
+            });
+        };
+
+        let (template, cause) = (target.template.clone(), format!("rollback to revision {}", target.revision));
+        self.set_template(template, cause);
+        Ok(())
+    }
+
+    /// A new pending replica, named after the service with a random suffix.
+    pub fn new_task(&self) -> Task {
+        self.new_task_of(self.revision)
+    }
+
+    /// A new pending replica of an earlier revision, of the current one if it's no longer in the history.
+    pub fn new_task_of(&self, revision: u64) -> Task {
+        let (revision, mut spec) = match self.history.iter().find(|r| r.revision == revision) {
+            Some(r) => (r.revision, r.template.clone()),
+            None => (self.revision, self.template.clone()),
+        };
+        let suffix = uuid::Uuid::new_v4().simple().to_string();
+        spec.name = format!("{}-{}", self.name, &suffix[..5]);
+
+        let mut task = spec.into_task();
+        task.owner = Some(Owner { revision, ..self.owner() });
+        task
+    }
+}
+
+/// The user-controlled part of a service, as written in a manifest or sent to `POST /services`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ServiceSpec {
+    pub name: String,
+
+    #[serde(default)]
+    pub labels: Labels,
+
+    #[serde(default = "default_replicas")]
+    pub replicas: u32,
+
+    pub template: TaskSpec,
+
+    #[serde(default)]
+    pub strategy: UpdateStrategy,
+}
+
+fn default_replicas() -> u32 {
+    1
+}
+
+impl ServiceSpec {
+    /// The spec a service was created from.
+    pub fn from_service(service: &Service) -> Self {
+        ServiceSpec {
+            name: service.name.clone(),
+            labels: service.labels.clone(),
+            replicas: service.replicas,
+            template: service.template.clone(),
+            strategy: service.strategy.clone(),
+        }
+    }
+
+    /// A new service at revision 1 of its template.
+    pub fn into_service(self) -> Service {
+        let mut service = Service {
+            name: self.name,
+            resource_version: 0,
+            labels: self.labels,
+            replicas: self.replicas,
+            template: self.template.clone(),
+            strategy: self.strategy,
+            revision: 0,
+            history: Vec::new(),
+            rollout: Rollout::default(),
+            backoff: Backoff::default(),
+            created_at: Utc::now(),
+        };
+        service.set_template(self.template, "created".to_string());
+        service.rollout = Rollout::default();
+        service
+    }
+
+    /// Overwrites the spec fields of `service`, leaving its name and state untouched.
+    ///
+    /// A changed template becomes a new revision, which the controller then rolls out.
+    pub fn apply_to(self, service: &mut Service) {
+        if self.template != service.template {
+            let cause = change_cause(&service.template, &self.template);
+            service.set_template(self.template, cause);
+        }
+        service.labels = self.labels;
+        service.replicas = self.replicas;
+        service.strategy = self.strategy;
+    }
+
+    /// Checks the spec, returns one error per invalid field. Empty means valid.
+    pub fn validate(&self) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+
+        if let Err(message) = validate_name(&self.name) {
+            errors.push(FieldError::new("name", message));
+        } else if self.name.len() > MAX_NAME_LENGTH {
+            errors.push(FieldError::new("name", format!("must be at most {} characters", MAX_NAME_LENGTH)));
+        }
+        for (key, value) in &self.labels {
+            if let Err(message) = labels::validate_key(key).and_then(|_| labels::validate_value(value)) {
+                errors.push(FieldError::new(format!("labels.{}", key), message));
+            }
+        }
+        if !self.template.name.is_empty() {
+            errors.push(FieldError::new("template.name", "must be left out, tasks are named after the service"));
+        }
+        if self.strategy.max_unavailable == 0 && self.strategy.max_surge == 0 {
+            errors.push(FieldError::new("strategy.max_surge", "must be at least 1 when max_unavailable is 0"));
+        }
+        errors.extend(
+            self.template
+                .validate_template()
+                .into_iter()
+                .map(|e| FieldError::new(format!("template.{}", e.field), e.message)),
+        );
+
+        errors
+    }
+}
+
+/// Describes a template change for the revision history, naming the image if it changed.
+fn change_cause(from: &TaskSpec, to: &TaskSpec) -> String {
+    if from.image != to.image {
+        format!("image {} -> {}", from.image, to.image)
+    } else {
+        "template changed".to_string()
+    }
+}
+
+/// A service with the state of its tasks, served by `GET /services`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ServiceDetails {
+    #[serde(flatten)]
+    pub service: Service,
+    /// Unfinished tasks of the service
+    pub current_replicas: u32,
+    /// Tasks whose container runs
+    pub running_replicas: u32,
+    /// Running tasks whose readiness probe passes, or that have none
+    pub ready_replicas: u32,
+    /// Unfinished tasks of the current revision
+    pub updated_replicas: u32,
+    /// Ready tasks of the current revision
+    pub updated_ready_replicas: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(name: &str) -> ServiceSpec {
+        ServiceSpec {
+            name: name.to_string(),
+            labels: Labels::new(),
+            replicas: 3,
+            template: TaskSpec::from_task(&Task::new(String::new(), "nginx".to_string())),
+            strategy: UpdateStrategy::default(),
+        }
+    }
+
+    #[test]
+    fn test_new_task_is_owned() {
+        let service = spec("web").into_service();
+        let task = service.new_task();
+
+        assert!(task.name.starts_with("web-"));
+        assert_eq!(task.name.len(), "web-".len() + 5);
+        assert_eq!(task.image, "nginx");
+        assert!(service.owns(&task));
+        assert!(!spec("api").into_service().owns(&task));
+        assert_eq!(task.owner.unwrap().to_string(), "service/web");
+    }
+
+    #[test]
+    fn test_revisions_and_rollback() {
+        let mut service = spec("web").into_service();
+        assert_eq!((service.revision, service.rollout.state.clone()), (1, RolloutState::Complete));
+        let first = service.new_task();
+
+        let mut update = spec("web");
+        update.template.image = "nginx:1.27".to_string();
+        update.clone().apply_to(&mut service);
+        assert_eq!(service.revision, 2);
+        assert_eq!(service.rollout.state, RolloutState::Progressing);
+        assert_eq!(service.history[1].change_cause, "image nginx -> nginx:1.27");
+        assert!(service.owns(&first) && !service.is_updated(&first));
+
+        // the same template again is no new revision
+        update.replicas = 5;
+        update.apply_to(&mut service);
+        assert_eq!((service.revision, service.replicas), (2, 5));
+
+        let second = service.new_task();
+        service.rollback(None).unwrap();
+        assert_eq!((service.revision, service.template.image.as_str()), (3, "nginx"));
+        assert_eq!(service.history[2].change_cause, "rollback to revision 1");
+        // the tasks of revision 1 already run the template rolled back to
+        assert!(service.is_updated(&first) && !service.is_updated(&second));
+        assert_eq!(service.new_task_of(2).image, "nginx:1.27");
+        assert!(service.rollback(Some(3)).is_err());
+        assert!(service.rollback(Some(7)).is_err());
+
+        for i in 0..REVISION_HISTORY_LIMIT {
+            service.set_template(service.template.clone(), format!("change {}", i));
+        }
+        assert_eq!(service.history.len(), REVISION_HISTORY_LIMIT);
+    }
+
+    #[test]
+    fn test_validate_service() {
+        assert!(spec("web").validate().is_empty());
+
+        let mut invalid = spec(&"a".repeat(60));
+        invalid.template.name = "web".to_string();
+        invalid.template.memory = 0;
+        invalid.strategy = UpdateStrategy { max_unavailable: 0, max_surge: 0 };
+        let fields: Vec<String> = invalid.validate().into_iter().map(|e| e.field).collect();
+        assert_eq!(fields, ["name", "template.name", "strategy.max_surge", "template.memory"]);
+    }
+}
@@ -0,0 +1,98 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::node::Node;
+
+/// Resources actually in use, as opposed to the amounts tasks request.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct Usage {
+    /// CPU in cores
+    pub cpu: f32,
+    /// Memory in MB
+    pub memory: i32,
+}
+
+/// The usage of a node or task at one point in time.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct UsageSample {
+    pub timestamp: DateTime<Utc>,
+    #[serde(flatten)]
+    pub usage: Usage,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct TaskUsage {
+    pub task_id: Uuid,
+    #[serde(flatten)]
+    pub usage: Usage,
+}
+
+/// What the worker measured since its last heartbeat: the whole node and each of its containers.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct UsageReport {
+    pub timestamp: DateTime<Utc>,
+    pub node: Usage,
+    pub tasks: Vec<TaskUsage>,
+}
+
+/// Body of `POST /nodes`, the node registration plus the usage measured by the worker.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Heartbeat {
+    #[serde(flatten)]
+    pub node: Node,
+    /// Missing until the worker took its first measurement
+    #[serde(default)]
+    pub usage: Option<UsageReport>,
+}
+
+/// Recent usage of a node, oldest sample first, served by `GET /metrics/nodes`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NodeMetrics {
+    pub node_id: String,
+    /// Allocatable CPU in cores
+    pub total_cpu: f32,
+    /// Allocatable memory in MB
+    pub total_memory: i32,
+    pub samples: Vec<UsageSample>,
+}
+
+/// Recent usage of a task next to what it requested, served by `GET /metrics/tasks`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TaskMetrics {
+    pub task_id: Uuid,
+    pub name: String,
+    pub node_id: String,
+    /// Requested CPU in cores
+    pub cpu: f32,
+    /// Requested memory in MB
+    pub memory: i32,
+    pub samples: Vec<UsageSample>,
+}
+
+impl NodeMetrics {
+    pub fn latest(&self) -> Option<&Usage> {
+        self.samples.last().map(|s| &s.usage)
+    }
+}
+
+impl TaskMetrics {
+    pub fn latest(&self) -> Option<&Usage> {
+        self.samples.last().map(|s| &s.usage)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heartbeat_without_usage() {
+        let node = Node::new("worker-1".to_string(), 4096, 4.0);
+        let body = serde_json::to_string(&node).unwrap();
+
+        let heartbeat: Heartbeat = serde_json::from_str(&body).unwrap();
+        assert_eq!(heartbeat.node.id, "worker-1");
+        assert!(heartbeat.usage.is_none());
+    }
+}
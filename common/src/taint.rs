@@ -0,0 +1,167 @@
+use serde::{Deserialize, Serialize};
+
+use crate::labels;
+
+/// What a taint does to the tasks that don't tolerate it.
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
+pub enum TaintEffect {
+    /// New tasks are not scheduled on the node
+    NoSchedule,
+    /// The scheduler avoids the node unless nothing else fits
+    PreferNoSchedule,
+    /// Like `NoSchedule`, and tasks already on the node are evicted
+    NoExecute,
+}
+
+impl std::str::FromStr for TaintEffect {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "NoSchedule" => Ok(TaintEffect::NoSchedule),
+            "PreferNoSchedule" => Ok(TaintEffect::PreferNoSchedule),
+            "NoExecute" => Ok(TaintEffect::NoExecute),
+            _ => Err(format!("unknown taint effect '{}', expected NoSchedule, PreferNoSchedule or NoExecute", s)),
+        }
+    }
+}
+
+/// Marks a node so that only tasks tolerating it run there, e.g. `dedicated=db:NoSchedule`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Taint {
+    pub key: String,
+    #[serde(default)]
+    pub value: String,
+    pub effect: TaintEffect,
+}
+
+impl std::fmt::Display for Taint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.value.is_empty() {
+            write!(f, "{}:{:?}", self.key, self.effect)
+        } else {
+            write!(f, "{}={}:{:?}", self.key, self.value, self.effect)
+        }
+    }
+}
+
+impl std::str::FromStr for Taint {
+    type Err = String;
+
+    /// Parses `key=value:Effect` or `key:Effect`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (pair, effect) = s
+            .rsplit_once(':')
+            .ok_or_else(|| format!("invalid taint '{}', expected key=value:Effect", s))?;
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        labels::validate_key(key)?;
+        labels::validate_value(value)?;
+
+        Ok(Taint {
+            key: key.to_string(),
+            value: value.to_string(),
+            effect: effect.parse()?,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, Eq, PartialEq)]
+pub enum TolerationOperator {
+    /// The taint's value must equal the toleration's
+    #[default]
+    Equal,
+    /// Any value, without a key any taint
+    Exists,
+}
+
+/// Allows a task to run on nodes with matching taints.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Toleration {
+    /// `None` with the `Exists` operator tolerates every taint
+    #[serde(default)]
+    pub key: Option<String>,
+    #[serde(default)]
+    pub operator: TolerationOperator,
+    #[serde(default)]
+    pub value: String,
+    /// `None` tolerates all effects
+    #[serde(default)]
+    pub effect: Option<TaintEffect>,
+}
+
+/// Written like the taints it matches, `key=value:Effect`, `key` for `Exists` and `*` for any key.
+impl std::fmt::Display for Toleration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.key, &self.operator) {
+            (None, _) => write!(f, "*")?,
+            (Some(key), TolerationOperator::Exists) => write!(f, "{}", key)?,
+            (Some(key), TolerationOperator::Equal) => write!(f, "{}={}", key, self.value)?,
+        }
+        match &self.effect {
+            Some(effect) => write!(f, ":{:?}", effect),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Toleration {
+    pub fn tolerates(&self, taint: &Taint) -> bool {
+        if self.effect.as_ref().is_some_and(|effect| *effect != taint.effect) {
+            return false;
+        }
+
+        match (&self.key, &self.operator) {
+            (None, TolerationOperator::Exists) => true,
+            (None, TolerationOperator::Equal) => false,
+            (Some(key), _) if *key != taint.key => false,
+            (Some(_), TolerationOperator::Exists) => true,
+            (Some(_), TolerationOperator::Equal) => self.value == taint.value,
+        }
+    }
+}
+
+/// Whether one of the tolerations allows the taint.
+pub fn tolerated(tolerations: &[Toleration], taint: &Taint) -> bool {
+    tolerations.iter().any(|t| t.tolerates(taint))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_taint() {
+        let taint: Taint = "dedicated=db:NoSchedule".parse().unwrap();
+        assert_eq!(taint.key, "dedicated");
+        assert_eq!(taint.value, "db");
+        assert_eq!(taint.effect, TaintEffect::NoSchedule);
+        assert_eq!(taint.to_string(), "dedicated=db:NoSchedule");
+        assert_eq!("gpu:NoExecute".parse::<Taint>().unwrap().to_string(), "gpu:NoExecute");
+
+        assert!("dedicated=db".parse::<Taint>().is_err());
+        assert!("dedicated=db:Never".parse::<Taint>().is_err());
+    }
+
+    #[test]
+    fn test_tolerations() {
+        let taint: Taint = "dedicated=db:NoExecute".parse().unwrap();
+        let toleration = |key: Option<&str>, operator, value: &str, effect| Toleration {
+            key: key.map(str::to_string),
+            operator,
+            value: value.to_string(),
+            effect,
+        };
+
+        assert!(toleration(Some("dedicated"), TolerationOperator::Equal, "db", None).tolerates(&taint));
+        assert!(!toleration(Some("dedicated"), TolerationOperator::Equal, "web", None).tolerates(&taint));
+        assert!(toleration(Some("dedicated"), TolerationOperator::Exists, "", None).tolerates(&taint));
+        assert!(!toleration(Some("dedicated"), TolerationOperator::Exists, "", Some(TaintEffect::NoSchedule)).tolerates(&taint));
+        assert!(toleration(None, TolerationOperator::Exists, "", None).tolerates(&taint));
+        assert!(!toleration(None, TolerationOperator::Equal, "", None).tolerates(&taint));
+
+        assert_eq!(toleration(Some("dedicated"), TolerationOperator::Equal, "db", Some(TaintEffect::NoExecute)).to_string(), "dedicated=db:NoExecute");
+        assert_eq!(toleration(None, TolerationOperator::Exists, "", None).to_string(), "*");
+    }
+}
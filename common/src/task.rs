@@ -4,6 +4,12 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::affinity::Affinity;
+use crate::error::OrchError;
+use crate::labels::Labels;
+use crate::probe::{HealthStatus, Probe, RestartPolicy, TaskHealth};
+use crate::taint::Toleration;
+
 /// Represents the state machine of a Task (Pod).
 ///
 /// The lifecycle flows as follows:
@@ -12,6 +18,11 @@ use uuid::Uuid;
 /// 3. `Running`: The Docker container is successfully active on the Worker.
 /// 4. `Completed`: The process exited with code 0.
 /// 5. `Failed`: The process crashed or the image failed to pull.
+///
+/// A task can fail at any point before it completes, `Complete` and `Failed` are final.
+/// Draining a node, or preemption by a task of a higher priority, sends `Scheduled` and `Running` tasks back
+/// to `Pending` once their container stopped, see [`Rescheduling`].
+/// See [`TaskStatus::can_transition_to`].
 #[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
 pub enum TaskStatus {
     Pending, // created but not scheduled
@@ -21,6 +32,142 @@ pub enum TaskStatus {
     Failed,
 }
 
+impl TaskStatus {
+    /// Whether the lifecycle allows moving from `self` to `next`.
+    pub fn can_transition_to(&self, next: &TaskStatus) -> bool {
+        use TaskStatus::*;
+
+        matches!(
+            (self, next),
+            (Pending, Scheduled)
+                | (Pending, Failed)
+                | (Scheduled, Pending)
+                | (Scheduled, Running)
+                | (Scheduled, Failed)
+                | (Running, Pending)
+                | (Running, Complete)
+                | (Running, Failed)
+        )
+    }
+
+    /// `Complete` and `Failed` tasks never change status again.
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, TaskStatus::Complete | TaskStatus::Failed)
+    }
+}
+
+impl std::str::FromStr for TaskStatus {
+    type Err = String;
+
+    /// Parses a status case-insensitively, e.g. `running` or `Running`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "pending" => Ok(TaskStatus::Pending),
+            "scheduled" => Ok(TaskStatus::Scheduled),
+            "running" => Ok(TaskStatus::Running),
+            "complete" | "completed" => Ok(TaskStatus::Complete),
+            "failed" => Ok(TaskStatus::Failed),
+            _ => Err(format!("unknown task status '{}'", s)),
+        }
+    }
+}
+
+/// A single entry in a task's status timeline.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StatusTransition {
+    /// `None` for the entry recorded when the task was created
+    pub from: Option<TaskStatus>,
+    pub to: TaskStatus,
+    pub at: DateTime<Utc>,
+    /// The node the task was assigned to at the time of the transition
+    pub node_id: Option<String>,
+    /// Why the transition happened (e.g. the error that made the task fail)
+    pub reason: Option<String>,
+}
+
+/// The kinds of resources that create tasks and look after them.
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
+pub enum OwnerKind {
+    Service,
+    Job,
+    CronJob,
+    Workflow,
+}
+
+/// Marks a task that is being moved off its node, e.g. by a drain.
+///
+/// The task stays bound to the node, and keeps claiming its resources there, until its worker
+/// reports that the container stopped or the deadline passed. Then it goes back to `Pending`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Rescheduling {
+    /// Recorded when the task goes back to `Pending`, e.g. `Drained from node worker-1`
+    pub reason: String,
+    /// When the task goes back to `Pending` even if its worker never confirmed the container stopped
+    pub deadline: DateTime<Utc>,
+}
+
+/// How long a worker gets to stop the container of a task that is being rescheduled.
+pub const DEFAULT_GRACE_PERIOD_SECS: u64 = 30;
+
+/// How important a task is compared to the others, in increasing order.
+///
+/// The scheduler places pending tasks with a higher priority first, and when one fits nowhere it
+/// sends running tasks of a lower priority back to `Pending` to make room for it.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum PriorityClass {
+    /// Batch work that may be preempted by any other task
+    Low,
+    #[default]
+    Normal,
+    High,
+    /// Never preempted
+    Critical,
+}
+
+impl std::str::FromStr for PriorityClass {
+    type Err = String;
+
+    /// Parses a priority class case-insensitively, e.g. `high` or `High`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "low" => Ok(PriorityClass::Low),
+            "normal" => Ok(PriorityClass::Normal),
+            "high" => Ok(PriorityClass::High),
+            "critical" => Ok(PriorityClass::Critical),
+            _ => Err(format!("unknown priority class '{}'", s)),
+        }
+    }
+}
+
+/// The resource a task was created by, e.g. `service/web`.
+///
+/// The owner replaces the task when it's gone and deletes it once it's no longer needed.
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
+pub struct Owner {
+    pub kind: OwnerKind,
+    pub name: String,
+    /// The revision of the owner's template the task was created from
+    #[serde(default)]
+    pub revision: u64,
+    /// The completion index of a job's task, or the position of a workflow's step
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index: Option<u32>,
+    /// Tells apart owners that were deleted and created again under the same name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uid: Option<Uuid>,
+}
+
+impl std::fmt::Display for Owner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            OwnerKind::Service => write!(f, "service/{}", self.name),
+            OwnerKind::Job => write!(f, "job/{}", self.name),
+            OwnerKind::CronJob => write!(f, "cronjob/{}", self.name),
+            OwnerKind::Workflow => write!(f, "workflow/{}", self.name),
+        }
+    }
+}
+
 /// A unit of work to be executed on the cluster.
 ///
 /// This struct roughly corresponds to a Kubernetes "Pod" or a single Docker container definition.
@@ -29,12 +176,24 @@ pub struct Task {
     /// Unique internal identifier
     pub id: Uuid,
 
+    /// Bumped by the manager on every change, used for optimistic concurrency (`If-Match`)
+    #[serde(default)]
+    pub resource_version: u64,
+
     /// Human-readable name (e.g., "nginx-prod")
     pub name: String,
 
     /// The Docker image to run (e.g., "postgres:13")
     pub image: String,
 
+    /// Identifying metadata used to group and select tasks (e.g., `app=web`)
+    #[serde(default)]
+    pub labels: Labels,
+
+    /// Free-form metadata for tools and people, never used for selection
+    #[serde(default)]
+    pub annotations: Labels,
+
     /// Memory requirement in MB
     pub memory: i32,
 
@@ -44,6 +203,22 @@ pub struct Task {
     /// Environment variables to inject into the container.
     pub env: HashMap<String, String>,
 
+    /// Only nodes with all of these labels can run the task
+    #[serde(default)]
+    pub node_selector: Labels,
+
+    /// Node and task (anti-)affinity rules used by the scheduler
+    #[serde(default)]
+    pub affinity: Affinity,
+
+    /// Taints of the nodes this task may run on anyway
+    #[serde(default)]
+    pub tolerations: Vec<Toleration>,
+
+    /// Decides the scheduling order and which tasks may be preempted for this one
+    #[serde(default)]
+    pub priority_class: PriorityClass,
+
     /// Status
     pub status: TaskStatus,
 
@@ -53,33 +228,159 @@ pub struct Task {
     /// Started time of the task
     pub started_at: Option<DateTime<Utc>>,
 
+    /// Time the task reached `Complete` or `Failed`
+    #[serde(default)]
+    pub finished_at: Option<DateTime<Utc>>,
+
+    /// Every status the task went through, oldest first
+    #[serde(default)]
+    pub history: Vec<StatusTransition>,
+
+    /// Set while the task is moved off its node, see [`Rescheduling`]
+    #[serde(default)]
+    pub rescheduling: Option<Rescheduling>,
+
+    /// The node set aside for this pending task while the tasks it preempted there stop
+    #[serde(default)]
+    pub nominated_node: Option<String>,
+
     /// The ID of the Worker Node where this task is assigned
     /// This is `None` when the task is in `Pending` state
     pub node_id: Option<String>, // the node where this task is running
 
     /// The actual Docker Container ID returned by the Docker Deamon.
     pub container_id: Option<String>,
+
+    /// Restarts the container when it fails, see `restart_policy`
+    #[serde(default)]
+    pub liveness_probe: Option<Probe>,
+
+    /// Tells whether the container is ready to serve
+    #[serde(default)]
+    pub readiness_probe: Option<Probe>,
+
+    /// What the worker does when the liveness probe fails
+    #[serde(default)]
+    pub restart_policy: RestartPolicy,
+
+    /// Probe results as last reported by the worker
+    #[serde(default)]
+    pub health: TaskHealth,
+
+    /// The resource that created the task, `None` for tasks submitted directly
+    #[serde(default)]
+    pub owner: Option<Owner>,
 }
 
 impl Task {
     /// Creates a new Task with default resource limits.
     pub fn new(name: String, image: String) -> Self {
+        let created_at = Utc::now();
         Task {
             id: Uuid::new_v4(),
+            resource_version: 0,
             name,
             image,
+            labels: Labels::new(),
+            annotations: Labels::new(),
             memory: 256, // default to low memory footprint
             cpu: 0.5,    // default to half a core
             env: HashMap::new(),
+            node_selector: Labels::new(),
+            affinity: Affinity::default(),
+            tolerations: Vec::new(),
+            priority_class: PriorityClass::default(),
             status: TaskStatus::Pending,
-            created_at: Utc::now(),
+            created_at,
             started_at: None,
+            finished_at: None,
+            history: vec![StatusTransition {
+                from: None,
+                to: TaskStatus::Pending,
+                at: created_at,
+                node_id: None,
+                reason: Some("Created".to_string()),
+            }],
+            rescheduling: None,
+            nominated_node: None,
             node_id: None,
             container_id: None,
+            liveness_probe: None,
+            readiness_probe: None,
+            restart_policy: RestartPolicy::default(),
+            health: TaskHealth::default(),
+            owner: None,
         }
     }
+
+    /// Moves the task to `status` and records the transition in its history.
+    ///
+    /// Also keeps `started_at` and `finished_at` in sync with the new status.
+    /// Fails with `OrchError::InvalidTransition` if the lifecycle doesn't allow the move.
+    pub fn transition(&mut self, status: TaskStatus, reason: Option<String>) -> Result<(), OrchError> {
+        if !self.status.can_transition_to(&status) {
+            return Err(OrchError::InvalidTransition {
+                task_id: self.id.to_string(),
+                current: self.status.clone(),
+                requested: status,
+            });
+        }
+
+        let now = Utc::now();
+
+        match status {
+            TaskStatus::Running => {
+                self.started_at = Some(now);
+                self.finished_at = None;
+            }
+            TaskStatus::Complete | TaskStatus::Failed => self.finished_at = Some(now),
+            TaskStatus::Pending | TaskStatus::Scheduled => {}
+        }
+
+        self.history.push(StatusTransition {
+            from: Some(self.status.clone()),
+            to: status.clone(),
+            at: now,
+            node_id: self.node_id.clone(),
+            reason,
+        });
+        self.status = status;
+        Ok(())
+    }
+
+    /// Sends the task back to `Pending` and unbinds it from its node, ending a rescheduling.
+    pub fn unbind(&mut self, reason: Option<String>) -> Result<(), OrchError> {
+        let reason = self.rescheduling.as_ref().map(|r| r.reason.clone()).or(reason);
+        self.transition(TaskStatus::Pending, reason)?;
+        self.rescheduling = None;
+        self.node_id = None;
+        self.container_id = None;
+        self.health = TaskHealth::default();
+        Ok(())
+    }
+
+    /// Whether the task's container runs and passes its readiness probe, if it has one.
+    pub fn is_ready(&self) -> bool {
+        self.status == TaskStatus::Running
+            && (self.readiness_probe.is_none() || self.health.ready == HealthStatus::Healthy)
+    }
+
+    /// Whether the manager failed the task to get it off its node, e.g. for a `NoExecute` taint.
+    ///
+    /// The worker stops the container of an evicted task, see [`EVICTED`].
+    pub fn is_evicted(&self) -> bool {
+        self.status == TaskStatus::Failed
+            && self
+                .history
+                .last()
+                .and_then(|t| t.reason.as_deref())
+                .is_some_and(|reason| reason.starts_with(EVICTED))
+    }
 }
 
+/// Prefix of the transition reason recorded when the manager evicts a task.
+pub const EVICTED: &str = "Evicted";
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -94,6 +395,73 @@ mod tests {
         assert!(task.container_id.is_none());
     }
 
+    #[test]
+    fn test_transition_records_history_and_timestamps() {
+        let mut task = Task::new("t".to_string(), "img".to_string());
+        assert_eq!(task.history.len(), 1);
+
+        task.node_id = Some("worker-1".to_string());
+        task.transition(TaskStatus::Scheduled, None).unwrap();
+        task.transition(TaskStatus::Running, None).unwrap();
+        assert!(task.started_at.is_some());
+        assert!(task.finished_at.is_none());
+
+        task.transition(TaskStatus::Failed, Some("OOMKilled".to_string())).unwrap();
+        assert!(task.finished_at.is_some());
+
+        let last = task.history.last().unwrap();
+        assert_eq!(last.from, Some(TaskStatus::Running));
+        assert_eq!(last.to, TaskStatus::Failed);
+        assert_eq!(last.node_id.as_deref(), Some("worker-1"));
+        assert_eq!(last.reason.as_deref(), Some("OOMKilled"));
+        assert_eq!(task.history.len(), 4);
+    }
+
+    #[test]
+    fn test_rejects_illegal_transitions() {
+        let mut task = Task::new("t".to_string(), "img".to_string());
+        assert!(task.transition(TaskStatus::Running, None).is_err());
+
+        task.transition(TaskStatus::Scheduled, None).unwrap();
+        task.transition(TaskStatus::Running, None).unwrap();
+        task.transition(TaskStatus::Complete, None).unwrap();
+
+        // a drained task goes back to pending, but never straight to running
+        let mut drained = Task::new("d".to_string(), "img".to_string());
+        drained.transition(TaskStatus::Scheduled, None).unwrap();
+        drained.transition(TaskStatus::Pending, Some("Drained".to_string())).unwrap();
+        assert!(drained.transition(TaskStatus::Running, None).is_err());
+
+        // a late report from the worker must not revive the task
+        match task.transition(TaskStatus::Running, None) {
+            Err(OrchError::InvalidTransition { current, requested, .. }) => {
+                assert_eq!(current, TaskStatus::Complete);
+                assert_eq!(requested, TaskStatus::Running);
+            }
+            other => panic!("expected InvalidTransition, got {:?}", other),
+        }
+        assert_eq!(task.status, TaskStatus::Complete);
+        assert_eq!(task.history.len(), 4);
+    }
+
+    #[test]
+    fn test_terminal_states_are_final() {
+        use TaskStatus::*;
+        let all = [Pending, Scheduled, Running, Complete, Failed];
+
+        for from in [Complete, Failed] {
+            assert!(from.is_terminal());
+            assert!(all.iter().all(|to| !from.can_transition_to(to)));
+        }
+    }
+
+    #[test]
+    fn test_parse_status() {
+        assert_eq!("running".parse::<TaskStatus>(), Ok(TaskStatus::Running));
+        assert_eq!("Complete".parse::<TaskStatus>(), Ok(TaskStatus::Complete));
+        assert!("sleeping".parse::<TaskStatus>().is_err());
+    }
+
     #[test]
     fn test_unique_ids() {
         let task1 = Task::new("t1".to_string(), "img".to_string());
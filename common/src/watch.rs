@@ -0,0 +1,36 @@
+use serde::{Deserialize, Serialize};
+
+use crate::cronjob::CronJob;
+use crate::job::Job;
+use crate::node::Node;
+use crate::service::Service;
+use crate::task::Task;
+use crate::workflow::Workflow;
+
+/// What happened to the object of a [`WatchEvent`].
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
+pub enum EventType {
+    Added,
+    Modified,
+    Deleted,
+}
+
+/// The object a [`WatchEvent`] is about, in the state right after the change.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum WatchObject {
+    Task(Box<Task>),
+    Node(Box<Node>),
+    Service(Box<Service>),
+    Job(Box<Job>),
+    CronJob(Box<CronJob>),
+    Workflow(Box<Workflow>),
+}
+
+/// A single entry of the manager's change feed, streamed by `GET /watch`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WatchEvent {
+    pub event_type: EventType,
+    /// The store revision of this change, pass it as `resourceVersion` to resume after it
+    pub resource_version: u64,
+    pub object: WatchObject,
+}
@@ -0,0 +1,382 @@
+use std::collections::HashSet;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::error::FieldError;
+use crate::labels::{self, Labels};
+use crate::manifest::{validate_name, TaskSpec};
+use crate::probe::RestartPolicy;
+use crate::task::{Owner, OwnerKind, Task};
+
+/// Longest workflow and step name, together they leave room for the `-xxxxx` suffix of task names.
+const MAX_NAME_LENGTH: usize = 28;
+
+/// Environment variable telling a workflow's task which step it runs.
+pub const WORKFLOW_STEP_ENV: &str = "ORCH_WORKFLOW_STEP";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub enum WorkflowState {
+    /// Steps are running or waiting for their dependencies
+    #[default]
+    Running,
+    /// Every step succeeded
+    Succeeded,
+    /// Every step finished, and at least one failed or was skipped
+    Failed,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub enum StepState {
+    /// A dependency hasn't succeeded yet
+    #[default]
+    Waiting,
+    /// The step's task was created and hasn't finished yet
+    Running,
+    /// The step's task completed
+    Succeeded,
+    /// The step's task failed
+    Failed,
+    /// A dependency failed or was skipped, so the step never runs
+    Skipped,
+}
+
+impl StepState {
+    /// `Succeeded`, `Failed` and `Skipped` steps never change state again.
+    pub fn is_finished(&self) -> bool {
+        matches!(self, StepState::Succeeded | StepState::Failed | StepState::Skipped)
+    }
+}
+
+/// Where one step of a workflow is at.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct StepStatus {
+    pub name: String,
+    pub state: StepState,
+    /// The step's task, once it was created
+    pub task: Option<String>,
+    /// Why the step failed or was skipped
+    pub reason: Option<String>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+/// What the workflow controller last saw of the workflow's tasks.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct WorkflowStatus {
+    pub state: WorkflowState,
+    /// One entry per step, in the order of the spec
+    pub steps: Vec<StepStatus>,
+    /// Which steps failed
+    pub message: Option<String>,
+    /// Time the workflow reached `Succeeded` or `Failed`
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+/// One task template of a workflow, started once every step it depends on succeeded.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Step {
+    pub name: String,
+
+    /// Names of the steps that have to complete first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
+
+    /// What the step's task runs, its name is left out
+    pub template: TaskSpec,
+}
+
+/// A DAG of steps: each runs as one task once the steps it depends on completed.
+///
+/// A step whose task fails skips every step that depends on it, directly or not,
+/// the others carry on. The workflow fails once everything that can run finished.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Workflow {
+    pub name: String,
+
+    /// Unique per workflow, so a workflow created again under the same name doesn't take over the old tasks
+    pub uid: Uuid,
+
+    /// Bumped by the manager on every change, used for optimistic concurrency (`If-Match`)
+    #[serde(default)]
+    pub resource_version: u64,
+
+    #[serde(default)]
+    pub labels: Labels,
+
+    pub steps: Vec<Step>,
+
+    #[serde(default)]
+    pub status: WorkflowStatus,
+
+    pub created_at: DateTime<Utc>,
+}
+
+impl Workflow {
+    /// Whether the task was created by this workflow.
+    pub fn owns(&self, task: &Task) -> bool {
+        task.owner
+            .as_ref()
+            .is_some_and(|owner| owner.kind == OwnerKind::Workflow && owner.uid == Some(self.uid))
+    }
+
+    /// The position of the step the task runs, `None` for tasks of other owners.
+    pub fn step_of(&self, task: &Task) -> Option<usize> {
+        task.owner.as_ref().filter(|_| self.owns(task)).and_then(|owner| owner.index).map(|i| i as usize)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.status.state != WorkflowState::Running
+    }
+
+    /// A new pending task for the step at `index`, named `{workflow}-{step}-xxxxx`.
+    pub fn new_task(&self, index: usize) -> Task {
+        let step = &self.steps[index];
+        let mut spec = step.template.clone();
+        let suffix = Uuid::new_v4().simple().to_string();
+        spec.name = format!("{}-{}-{}", self.name, step.name, &suffix[..5]);
+        spec.env.insert(WORKFLOW_STEP_ENV.to_string(), step.name.clone());
+
+        let mut task = spec.into_task();
+        task.owner = Some(Owner {
+            kind: OwnerKind::Workflow,
+            name: self.name.clone(),
+            revision: 0,
+            index: Some(index as u32),
+            uid: Some(self.uid),
+        });
+        task
+    }
+
+    /// The positions of the steps each step depends on.
+    pub fn dependencies(&self) -> Vec<Vec<usize>> {
+        dependencies(&self.steps)
+    }
+
+    /// The steps grouped by depth: the first level depends on nothing, every other step sits
+    /// one level below the deepest step it depends on. Positions keep the order of the spec.
+    pub fn levels(&self) -> Vec<Vec<usize>> {
+        let dependencies = self.dependencies();
+        let order = topological_order(&dependencies).unwrap_or_else(|_| (0..self.steps.len()).collect());
+
+        let mut depth = vec![0; self.steps.len()];
+        for &i in &order {
+            depth[i] = dependencies[i].iter().map(|&d| depth[d] + 1).max().unwrap_or(0);
+        }
+
+        let mut levels = vec![Vec::new(); depth.iter().max().map_or(0, |d| d + 1)];
+        for (i, d) in depth.into_iter().enumerate() {
+            levels[d].push(i);
+        }
+        levels
+    }
+}
+
+/// The positions of the steps each step depends on, unknown names are left out.
+fn dependencies(steps: &[Step]) -> Vec<Vec<usize>> {
+    steps
+        .iter()
+        .map(|step| {
+            step.depends_on
+                .iter()
+                .filter_map(|name| steps.iter().position(|s| s.name == *name))
+                .collect()
+        })
+        .collect()
+}
+
+/// The steps ordered so that each comes after the ones it depends on, or the position of a step on a cycle.
+fn topological_order(dependencies: &[Vec<usize>]) -> Result<Vec<usize>, usize> {
+    let mut order = Vec::with_capacity(dependencies.len());
+    let mut placed = vec![false; dependencies.len()];
+
+    while order.len() < dependencies.len() {
+        let ready: Vec<usize> = (0..dependencies.len())
+            .filter(|&i| !placed[i] && dependencies[i].iter().all(|&d| placed[d]))
+            .collect();
+        // whatever is left waits on itself somewhere
+        if ready.is_empty() {
+            return Err((0..dependencies.len()).find(|&i| !placed[i]).expect("steps are left"));
+        }
+        for i in ready {
+            placed[i] = true;
+            order.push(i);
+        }
+    }
+
+    Ok(order)
+}
+
+/// The user-controlled part of a workflow, as written in a manifest or sent to `POST /workflows`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct WorkflowSpec {
+    pub name: String,
+
+    #[serde(default)]
+    pub labels: Labels,
+
+    pub steps: Vec<Step>,
+}
+
+impl WorkflowSpec {
+    /// The spec a workflow was created from.
+    pub fn from_workflow(workflow: &Workflow) -> Self {
+        WorkflowSpec { name: workflow.name.clone(), labels: workflow.labels.clone(), steps: workflow.steps.clone() }
+    }
+
+    pub fn into_workflow(self) -> Workflow {
+        Workflow {
+            name: self.name,
+            uid: Uuid::new_v4(),
+            resource_version: 0,
+            labels: self.labels,
+            steps: self.steps,
+            status: WorkflowStatus::default(),
+            created_at: Utc::now(),
+        }
+    }
+
+    /// Overwrites the fields of `workflow` that may change while it runs, only the labels.
+    pub fn apply_to(self, workflow: &mut Workflow) {
+        workflow.labels = self.labels;
+    }
+
+    /// The fields that differ from `workflow` but can't change once it exists, it has to be replaced instead.
+    pub fn immutable_changes(&self, workflow: &Workflow) -> Vec<FieldError> {
+        if self.steps != workflow.steps {
+            return vec![FieldError::new("steps", "can't change once the workflow exists, delete and create it again")];
+        }
+        Vec::new()
+    }
+
+    /// Checks the spec, returns one error per invalid field. Empty means valid.
+    pub fn validate(&self) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+
+        if let Err(message) = validate_name(&self.name) {
+            errors.push(FieldError::new("name", message));
+        } else if self.name.len() > MAX_NAME_LENGTH {
+            errors.push(FieldError::new("name", format!("must be at most {} characters", MAX_NAME_LENGTH)));
+        }
+        for (key, value) in &self.labels {
+            if let Err(message) = labels::validate_key(key).and_then(|_| labels::validate_value(value)) {
+                errors.push(FieldError::new(format!("labels.{}", key), message));
+            }
+        }
+        if self.steps.is_empty() {
+            errors.push(FieldError::new("steps", "must have at least one step"));
+        }
+
+        let mut names = HashSet::new();
+        for (i, step) in self.steps.iter().enumerate() {
+            let field = |name: &str| format!("steps[{}].{}", i, name);
+
+            if let Err(message) = validate_name(&step.name) {
+                errors.push(FieldError::new(field("name"), message));
+            } else if step.name.len() > MAX_NAME_LENGTH {
+                errors.push(FieldError::new(field("name"), format!("must be at most {} characters", MAX_NAME_LENGTH)));
+            } else if !names.insert(step.name.as_str()) {
+                errors.push(FieldError::new(field("name"), format!("'{}' is used by another step", step.name)));
+            }
+            for dependency in &step.depends_on {
+                if *dependency == step.name {
+                    errors.push(FieldError::new(field("depends_on"), "a step can't depend on itself"));
+                } else if !self.steps.iter().any(|s| s.name == *dependency) {
+                    errors.push(FieldError::new(field("depends_on"), format!("there's no step named '{}'", dependency)));
+                }
+            }
+            if !step.template.name.is_empty() {
+                errors.push(FieldError::new(field("template.name"), "must be left out, tasks are named after the step"));
+            }
+            if step.template.restart_policy == RestartPolicy::Always {
+                errors.push(FieldError::new(field("template.restart_policy"), "must be Never or OnFailure, steps have to finish"));
+            }
+            errors.extend(
+                step.template
+                    .validate_template()
+                    .into_iter()
+                    .map(|e| FieldError::new(format!("{}.{}", field("template"), e.field), e.message)),
+            );
+        }
+
+        // self-dependencies were reported above
+        let dependencies: Vec<Vec<usize>> = dependencies(&self.steps)
+            .into_iter()
+            .enumerate()
+            .map(|(i, d)| d.into_iter().filter(|&d| d != i).collect())
+            .collect();
+        if let Err(i) = topological_order(&dependencies) {
+            errors.push(FieldError::new(
+                format!("steps[{}].depends_on", i),
+                format!("'{}' is part of a dependency cycle", self.steps[i].name),
+            ));
+        }
+
+        errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(name: &str, depends_on: &[&str]) -> Step {
+        Step {
+            name: name.to_string(),
+            depends_on: depends_on.iter().map(|d| d.to_string()).collect(),
+            template: TaskSpec {
+                restart_policy: RestartPolicy::Never,
+                ..TaskSpec::from_task(&Task::new(String::new(), "busybox".to_string()))
+            },
+        }
+    }
+
+    fn spec(steps: Vec<Step>) -> WorkflowSpec {
+        WorkflowSpec { name: "etl".to_string(), labels: Labels::new(), steps }
+    }
+
+    #[test]
+    fn test_levels_and_tasks() {
+        let workflow = spec(vec![
+            step("load", &["transform", "enrich"]),
+            step("extract", &[]),
+            step("transform", &["extract"]),
+            step("enrich", &["extract"]),
+        ])
+        .into_workflow();
+
+        assert_eq!(workflow.levels(), [vec![1], vec![2, 3], vec![0]]);
+
+        let task = workflow.new_task(2);
+        assert!(task.name.starts_with("etl-transform-"));
+        assert_eq!(task.env[WORKFLOW_STEP_ENV], "transform");
+        assert_eq!(workflow.step_of(&task), Some(2));
+        assert_eq!(task.owner.as_ref().unwrap().to_string(), "workflow/etl");
+        assert_eq!(spec(vec![step("a", &[])]).into_workflow().step_of(&task), None);
+    }
+
+    #[test]
+    fn test_validate_workflow() {
+        assert!(spec(vec![step("a", &[]), step("b", &["a"])]).validate().is_empty());
+        assert_eq!(spec(Vec::new()).validate()[0].field, "steps");
+
+        let invalid = spec(vec![
+            step("a", &["a"]),
+            step("a", &[]),
+            step("b", &["c", "missing"]),
+            step("c", &["b"]),
+        ]);
+        let errors: Vec<String> = invalid.validate().into_iter().map(|e| format!("{}: {}", e.field, e.message)).collect();
+        assert_eq!(
+            errors,
+            [
+                "steps[0].depends_on: a step can't depend on itself",
+                "steps[1].name: 'a' is used by another step",
+                "steps[2].depends_on: there's no step named 'missing'",
+                "steps[2].depends_on: 'b' is part of a dependency cycle",
+            ]
+        );
+    }
+}
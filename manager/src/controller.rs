@@ -0,0 +1,330 @@
+use chrono::{DateTime, Utc};
+use common::{Backoff, OrchError, OwnerKind, Rollout, RolloutState, Service, Task, TaskStatus};
+use crate::reconcile::{self, delete, retry_delay};
+use crate::store::SharedState;
+
+const CONTROLLER: &str = "Service controller";
+
+/// What the controller does to bring a service to its replica count and current revision.
+struct Plan<'a> {
+    /// New tasks to start
+    create: usize,
+    /// The revision they start from, the current one unless the rollout is paused
+    revision: u64,
+    /// Finished tasks, surplus tasks after scaling down and old tasks replaced by the rollout
+    delete: Vec<&'a Task>,
+    /// Why the rollout has to pause, set when a task of the current revision failed during it
+    pause: Option<String>,
+    /// Every task runs the current revision and is ready
+    complete: bool,
+    /// The service's failures as of this round
+    backoff: Backoff,
+}
+
+/// Compares the unfinished tasks of the service with its replica count and revision.
+///
+/// Finished tasks don't count, they are replaced and deleted. When there are too many,
+/// the tasks that are furthest from serving go first: pending, then scheduled, then
+/// running but not ready, the newest first within each group.
+///
+/// Tasks of an older revision are replaced step by step: at most `max_surge` tasks run on
+/// top of the replicas, and old tasks are only stopped while at least `replicas - max_unavailable`
+/// ready tasks remain. Old tasks that aren't ready don't serve and can go right away.
+/// A paused rollout stops moving between revisions until the template changes again, missing
+/// replicas are then replaced from the newest revision the old tasks run.
+///
+/// Every failed task pushes the next replacement back, exponentially with the failures in a row.
+/// Once all replicas are ready again, the count starts over.
+fn plan<'a>(service: &Service, owned: &[&'a Task], now: DateTime<Utc>) -> Plan<'a> {
+    let (finished, active): (Vec<&Task>, Vec<&Task>) = owned.iter().partition(|t| t.status.is_terminal());
+    let (mut updated, mut old): (Vec<&Task>, Vec<&Task>) = active.into_iter().partition(|t| service.is_updated(t));
+    let desired = service.replicas as usize;
+
+    let mut plan = Plan {
+        create: 0,
+        revision: service.revision,
+        delete: Vec::new(),
+        pause: None,
+        complete: false,
+        backoff: service.backoff.clone(),
+    };
+    // failed tasks are deleted in the same round, so each one is counted once
+    let failed = finished.iter().filter(|t| t.status == TaskStatus::Failed).count() as u32;
+    if failed > 0 {
+        plan.backoff.failures += failed;
+        plan.backoff.retry_at = Some(now + retry_delay(plan.backoff.failures));
+    } else if updated.iter().chain(&old).filter(|t| t.is_ready()).count() >= desired {
+        plan.backoff = Backoff::default();
+    }
+
+    if !old.is_empty()
+        && let Some(failed) = finished.iter().find(|t| t.status == TaskStatus::Failed && service.is_updated(t))
+    {
+        plan.pause = Some(format!("task {} of revision {} failed", failed.name, service.revision));
+    }
+    plan.delete = finished;
+    let retrying = plan.backoff.retry_at.is_some_and(|at| at > now);
+
+    let by_serving = |t: &&Task| (serving_rank(t), std::cmp::Reverse(t.created_at));
+    if service.rollout.state == RolloutState::Paused || plan.pause.is_some() {
+        if let Some(revision) = old.iter().filter_map(|t| t.owner.as_ref()).map(|o| o.revision).max() {
+            plan.revision = revision;
+        }
+        let mut active: Vec<&Task> = updated.into_iter().chain(old).collect();
+        if active.len() > desired {
+            active.sort_by_key(by_serving);
+            plan.delete.extend(active.drain(..active.len() - desired));
+        }
+        plan.create = if retrying { 0 } else { desired - active.len() };
+        return plan;
+    }
+
+    if updated.len() > desired {
+        updated.sort_by_key(by_serving);
+        plan.delete.extend(updated.drain(..updated.len() - desired));
+    }
+
+    let max_total = desired + service.strategy.max_surge as usize;
+    let min_available = desired.saturating_sub(service.strategy.max_unavailable as usize);
+    plan.create = (desired - updated.len()).min(max_total.saturating_sub(updated.len() + old.len()));
+    if retrying {
+        plan.create = 0;
+    }
+
+    let available = updated.iter().chain(&old).filter(|t| t.is_ready()).count();
+    let mut removable = available.saturating_sub(min_available);
+    old.sort_by_key(by_serving);
+    for task in old.iter().copied() {
+        if !task.is_ready() {
+            plan.delete.push(task);
+        } else if removable > 0 {
+            plan.delete.push(task);
+            removable -= 1;
+        }
+    }
+
+    plan.complete = old.is_empty() && updated.len() == desired && updated.iter().all(|t| t.is_ready());
+    plan
+}
+
+fn serving_rank(task: &Task) -> u8 {
+    match task.status {
+        TaskStatus::Pending => 0,
+        TaskStatus::Scheduled => 1,
+        TaskStatus::Running if !task.is_ready() => 2,
+        _ => 3,
+    }
+}
+
+/// Keeps the tasks of every service in line with its replica count.
+///
+/// Tasks of deleted services are deleted too, the workers then stop their containers.
+pub async fn run_service_controller(store: SharedState) -> Result<(), OrchError> {
+    reconcile::run(CONTROLLER, store, reconcile).await
+}
+
+fn reconcile(store: &SharedState) -> Result<(), OrchError> {
+    let services = store.list_resources::<Service>()?;
+    let tasks = store.list_tasks()?;
+    reconcile::delete_orphans(CONTROLLER, store, &services, &tasks, OwnerKind::Service, Service::owns);
+
+    let now = Utc::now();
+    for service in &services {
+        let owned: Vec<&Task> = tasks.iter().filter(|t| service.owns(t)).collect();
+        let plan = plan(service, &owned, now);
+        let paused = plan.pause.is_some() || service.rollout.state == RolloutState::Paused;
+
+        let mut rollout = service.rollout.clone();
+        if let Some(message) = plan.pause {
+            println!("{}: service/{} paused the rollout of revision {}, {}", CONTROLLER, service.name, service.revision, message);
+            rollout = Rollout { state: RolloutState::Paused, message: Some(message) };
+        } else if plan.complete && service.rollout.state == RolloutState::Progressing {
+            println!("{}: service/{} rolled out revision {}", CONTROLLER, service.name, service.revision);
+            rollout = Rollout::default();
+        }
+        if let Some(retry_at) = plan.backoff.retry_at.filter(|_| plan.backoff != service.backoff) {
+            println!(
+                "{}: service/{} had {} failed tasks in a row, replacing them from {}",
+                CONTROLLER,
+                service.name,
+                plan.backoff.failures,
+                retry_at.to_rfc3339()
+            );
+        }
+        // recorded first, failed tasks must not be deleted before they're counted
+        if rollout != service.rollout || plan.backoff != service.backoff {
+            let (version, backoff) = (Some(service.resource_version), plan.backoff);
+            let result = store.update_resource(&service.name, version, |s: &mut Service| {
+                s.rollout = rollout;
+                s.backoff = backoff;
+            });
+            // changed since it was listed, e.g. got a new template, the next round looks again
+            if let Err(e) = result {
+                eprintln!("{}: {}", CONTROLLER, e);
+                continue;
+            }
+        }
+
+        for task in &plan.delete {
+            let reason = match task.status {
+                TaskStatus::Failed => "it failed and will be replaced",
+                TaskStatus::Complete => "it completed and will be replaced",
+                _ if !paused && !service.is_updated(task) => "it was replaced by the rollout",
+                _ => "the service was scaled down",
+            };
+            delete(CONTROLLER, store, *task, reason);
+        }
+
+        for _ in 0..plan.create {
+            match store.add_task(service.new_task_of(plan.revision)) {
+                Ok(task) => println!("{}: service/{} created task {}", CONTROLLER, service.name, task.name),
+                Err(e) => {
+                    // the replica is still missing next round
+                    eprintln!("{}: service/{}: {}", CONTROLLER, service.name, e);
+                    break;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::ServiceSpec;
+
+    fn service(replicas: u32) -> Service {
+        let template = common::TaskSpec::from_task(&Task::new(String::new(), "nginx".to_string()));
+        let strategy = Default::default();
+        ServiceSpec { name: "web".to_string(), labels: Default::default(), replicas, template, strategy }.into_service()
+    }
+
+    /// The service with a new image, the tasks created so far become the old revision.
+    fn update(service: &mut Service) {
+        let mut template = service.template.clone();
+        template.image = "nginx:1.27".to_string();
+        service.set_template(template, "test".to_string());
+    }
+
+    fn ids(tasks: &[&Task]) -> Vec<uuid::Uuid> {
+        tasks.iter().map(|t| t.id).collect()
+    }
+
+    fn task(service: &Service, status: TaskStatus) -> Task {
+        let mut task = service.new_task();
+        task.status = status;
+        task
+    }
+
+    #[test]
+    fn test_plan_replaces_finished_tasks() {
+        let service = service(3);
+        let running = task(&service, TaskStatus::Running);
+        let complete = task(&service, TaskStatus::Complete);
+
+        let plan = plan(&service, &[&running, &complete], Utc::now());
+        assert_eq!(plan.create, 2);
+        assert_eq!(ids(&plan.delete), [complete.id]);
+        assert_eq!(super::plan(&service, &[], Utc::now()).create, 3);
+    }
+
+    #[test]
+    fn test_plan_backs_off_failing_replicas() {
+        let mut service = service(2);
+        let running = task(&service, TaskStatus::Running);
+        let now = Utc::now();
+
+        // each failure in a row doubles the wait before the replacement starts
+        for (failures, delay) in [(1, 10), (2, 20), (3, 40)] {
+            let failed = task(&service, TaskStatus::Failed);
+            let plan = plan(&service, &[&running, &failed], now);
+            assert_eq!(ids(&plan.delete), [failed.id]);
+            assert_eq!(plan.create, 0);
+            assert_eq!(plan.backoff.failures, failures);
+            assert_eq!(plan.backoff.retry_at, Some(now + chrono::Duration::seconds(delay)));
+            service.backoff = plan.backoff;
+        }
+
+        let later = now + chrono::Duration::seconds(40);
+        let plan = super::plan(&service, &[&running], later);
+        assert_eq!((plan.create, plan.backoff.failures), (1, 3));
+
+        // all replicas ready again, the next failure starts over
+        let replacement = task(&service, TaskStatus::Running);
+        let plan = super::plan(&service, &[&running, &replacement], later);
+        assert_eq!(plan.backoff, Backoff::default());
+    }
+
+    #[test]
+    fn test_plan_scales_down_least_ready_first() {
+        let service = service(1);
+        let ready = task(&service, TaskStatus::Running);
+        let scheduled = task(&service, TaskStatus::Scheduled);
+        let pending = task(&service, TaskStatus::Pending);
+
+        let plan = plan(&service, &[&ready, &scheduled, &pending], Utc::now());
+        assert_eq!(plan.create, 0);
+        assert_eq!(ids(&plan.delete), [pending.id, scheduled.id]);
+    }
+
+    #[test]
+    fn test_plan_rolls_out_within_surge_and_unavailable() {
+        let mut service = service(3);
+        let old: Vec<Task> = (0..3).map(|_| task(&service, TaskStatus::Running)).collect();
+        update(&mut service);
+        let old_refs: Vec<&Task> = old.iter().collect();
+
+        // one task on top, and one old task may go since two stay ready
+        let plan = plan(&service, &old_refs, Utc::now());
+        assert_eq!(plan.create, 1);
+        assert_eq!(plan.delete.len(), 1);
+        assert!(!plan.complete);
+
+        // the new task isn't ready yet, so no more old ones go
+        let starting = task(&service, TaskStatus::Scheduled);
+        let plan = super::plan(&service, &[&old[0], &old[1], &starting], Utc::now());
+        assert_eq!((plan.create, plan.delete.len()), (1, 0));
+
+        let new: Vec<Task> = (0..3).map(|_| task(&service, TaskStatus::Running)).collect();
+        let plan = super::plan(&service, &[&new[0], &new[1], &new[2]], Utc::now());
+        assert!(plan.complete && plan.create == 0 && plan.delete.is_empty());
+    }
+
+    #[test]
+    fn test_plan_pauses_when_new_tasks_fail() {
+        let mut service = service(2);
+        let old = task(&service, TaskStatus::Running);
+        update(&mut service);
+        let failed = task(&service, TaskStatus::Failed);
+
+        let plan = plan(&service, &[&old, &failed], Utc::now());
+        assert_eq!(plan.create, 0);
+        assert_eq!(ids(&plan.delete), [failed.id]);
+        assert!(plan.pause.unwrap().contains("of revision 2 failed"));
+
+        // paused, the old task keeps serving and the missing replica starts from its revision
+        service.rollout.state = RolloutState::Paused;
+        let plan = super::plan(&service, &[&old], Utc::now());
+        assert!(plan.delete.is_empty() && plan.pause.is_none());
+        assert_eq!((plan.create, plan.revision), (1, 1));
+
+        // scaling down still applies, the task furthest from serving goes first
+        service.replicas = 1;
+        let pending = service.new_task_of(1);
+        let plan = super::plan(&service, &[&old, &pending], Utc::now());
+        assert_eq!((plan.create, ids(&plan.delete)), (0, vec![pending.id]));
+    }
+
+    #[test]
+    fn test_plan_keeps_tasks_a_rollback_returns_to() {
+        let mut service = service(2);
+        let old: Vec<Task> = (0..2).map(|_| task(&service, TaskStatus::Running)).collect();
+        update(&mut service);
+        service.rollback(None).unwrap();
+
+        let plan = plan(&service, &[&old[0], &old[1]], Utc::now());
+        assert!(plan.complete && plan.create == 0 && plan.delete.is_empty());
+    }
+}
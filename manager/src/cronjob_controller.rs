@@ -0,0 +1,260 @@
+use chrono::{DateTime, Utc};
+use common::{ConcurrencyPolicy, CronJob, CronJobStatus, Job, JobState, OrchError, OwnerKind, Schedule, Tz};
+use crate::reconcile::{self, delete};
+use crate::store::SharedState;
+
+const CONTROLLER: &str = "Cron job controller";
+
+/// The most missed runs that are counted one by one. With more, e.g. after a very long stall,
+/// none of them is started and the schedule starts over from now.
+const MAX_MISSED_RUNS: u64 = 1000;
+
+/// What the cron job controller does for one cron job.
+struct Plan<'a> {
+    /// The scheduled time to start a job for
+    run: Option<DateTime<Utc>>,
+    /// Jobs replaced by the new run, or beyond the history limits
+    delete: Vec<&'a Job>,
+    /// The cron job's status as of this round
+    status: CronJobStatus,
+}
+
+/// Works out whether a run is due and which jobs to clean up.
+///
+/// Due runs are the scheduled times since the last one that was handled, so runs missed while the
+/// controller wasn't looking, e.g. while the cron job was suspended, are caught up on. Only the
+/// latest of them is started, and only if it's within `starting_deadline_secs`, the others count
+/// as missed. A run that is due while earlier jobs are still going follows the concurrency policy.
+fn plan<'a>(cronjob: &CronJob, schedule: &Schedule, timezone: &Tz, owned: &[&'a Job], now: DateTime<Utc>) -> Plan<'a> {
+    let mut plan = Plan { run: None, delete: Vec::new(), status: cronjob.status.clone() };
+    let status = &mut plan.status;
+
+    let active: Vec<&Job> = owned.iter().copied().filter(|j| !j.is_finished()).collect();
+    let mut active_names: Vec<String> = active.iter().map(|j| j.name.clone()).collect();
+    active_names.sort();
+    status.active = active_names;
+
+    let last_success = owned.iter().filter(|j| j.status.state == JobState::Complete).filter_map(|j| j.status.finished_at).max();
+    status.last_successful_time = status.last_successful_time.max(last_success);
+
+    for (state, limit) in [
+        (JobState::Complete, cronjob.successful_jobs_history_limit),
+        (JobState::Failed, cronjob.failed_jobs_history_limit),
+    ] {
+        let mut finished: Vec<&Job> = owned.iter().copied().filter(|j| j.status.state == state).collect();
+        finished.sort_by_key(|j| std::cmp::Reverse(j.status.finished_at));
+        plan.delete.extend(finished.into_iter().skip(limit as usize));
+    }
+
+    status.next_schedule_time = schedule.next_after(now, timezone);
+    if status.next_schedule_time.is_none() {
+        status.message = Some(format!("the schedule '{}' never fires", cronjob.schedule));
+    }
+    if cronjob.suspend {
+        return plan;
+    }
+
+    let mut due = Vec::new();
+    let mut time = status.last_schedule_time.unwrap_or(cronjob.created_at);
+    while let Some(next) = schedule.next_after(time, timezone).filter(|&next| next <= now) {
+        if due.len() as u64 == MAX_MISSED_RUNS {
+            status.last_schedule_time = Some(now);
+            status.missed_runs += MAX_MISSED_RUNS;
+            status.message = Some(format!("more than {} runs were missed, started over from now", MAX_MISSED_RUNS));
+            return plan;
+        }
+        due.push(next);
+        time = next;
+    }
+    let Some(&latest) = due.last() else {
+        return plan;
+    };
+
+    status.last_schedule_time = Some(latest);
+    let missed = due.len() as u64 - 1;
+    status.missed_runs += missed;
+
+    if let Some(deadline) = cronjob.starting_deadline_secs
+        && latest + chrono::Duration::seconds(deadline as i64) < now
+    {
+        status.missed_runs += 1;
+        status.message = Some(format!("missed the run at {}, it was more than {}s late", latest.to_rfc3339(), deadline));
+        return plan;
+    }
+
+    if let Some(running) = active.first() {
+        match cronjob.concurrency_policy {
+            ConcurrencyPolicy::Allow => {}
+            ConcurrencyPolicy::Forbid => {
+                status.message = Some(format!("skipped the run at {}, job {} is still running", latest.to_rfc3339(), running.name));
+                return plan;
+            }
+            ConcurrencyPolicy::Replace => plan.delete.extend(active),
+        }
+    }
+
+    plan.run = Some(latest);
+    status.message = (missed > 0).then(|| format!("missed {} runs, started only the one at {}", missed, latest.to_rfc3339()));
+    plan
+}
+
+/// Starts the jobs of every cron job on schedule, and deletes the jobs of deleted cron jobs.
+pub async fn run_cronjob_controller(store: SharedState) -> Result<(), OrchError> {
+    reconcile::run(CONTROLLER, store, reconcile).await
+}
+
+fn reconcile(store: &SharedState) -> Result<(), OrchError> {
+    let cronjobs = store.list_resources::<CronJob>()?;
+    let jobs = store.list_resources::<Job>()?;
+    reconcile::delete_orphans(CONTROLLER, store, &cronjobs, &jobs, OwnerKind::CronJob, CronJob::owns);
+
+    let now = Utc::now();
+    for cronjob in &cronjobs {
+        // both were checked when the cron job was stored
+        let (schedule, timezone) = match cronjob.schedule() {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                eprintln!("{}: cronjob/{}: {}", CONTROLLER, cronjob.name, e);
+                continue;
+            }
+        };
+        let owned: Vec<&Job> = jobs.iter().filter(|j| cronjob.owns(j)).collect();
+        let plan = plan(cronjob, &schedule, &timezone, &owned, now);
+
+        for job in &plan.delete {
+            let reason = if job.is_finished() { "beyond the history limit" } else { "replaced by a new run" };
+            delete(CONTROLLER, store, *job, reason);
+        }
+        if let Some(scheduled) = plan.run {
+            match store.add_resource(cronjob.new_job(scheduled)) {
+                Ok(job) => println!("{}: cronjob/{} created job {}", CONTROLLER, cronjob.name, job.name),
+                // started in an earlier round whose status update didn't go through
+                Err(OrchError::Conflict(_)) => {}
+                Err(e) => {
+                    // the run stays due and is tried again next round
+                    eprintln!("{}: {}", CONTROLLER, e);
+                    continue;
+                }
+            }
+        }
+
+        if plan.status != cronjob.status
+            && let Err(e) = store.update_resource(&cronjob.name, Some(cronjob.resource_version), |c: &mut CronJob| c.status = plan.status)
+        {
+            eprintln!("{}: {}", CONTROLLER, e);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::{CronJobSpec, JobSpec, Task, TaskSpec};
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        s.parse().unwrap()
+    }
+
+    fn cronjob(schedule: &str, policy: ConcurrencyPolicy) -> CronJob {
+        let mut cronjob = CronJobSpec {
+            name: "report".to_string(),
+            labels: Default::default(),
+            schedule: schedule.to_string(),
+            timezone: "UTC".to_string(),
+            concurrency_policy: policy,
+            suspend: false,
+            starting_deadline_secs: None,
+            successful_jobs_history_limit: 1,
+            failed_jobs_history_limit: 1,
+            job_template: JobSpec {
+                name: String::new(),
+                labels: Default::default(),
+                completions: 1,
+                parallelism: 1,
+                backoff_limit: 0,
+                ttl_secs_after_finished: None,
+                template: TaskSpec::from_task(&Task::new(String::new(), "busybox".to_string())),
+            },
+        }
+        .into_cronjob();
+        cronjob.created_at = utc("2026-03-01T09:30:00Z");
+        cronjob
+    }
+
+    fn plan_at<'a>(cronjob: &CronJob, owned: &[&'a Job], now: &str) -> Plan<'a> {
+        let (schedule, timezone) = cronjob.schedule().unwrap();
+        plan(cronjob, &schedule, &timezone, owned, utc(now))
+    }
+
+    fn job(cronjob: &CronJob, scheduled: &str, state: JobState) -> Job {
+        let mut job = cronjob.new_job(utc(scheduled));
+        job.status.finished_at = (state != JobState::Running).then(|| utc(scheduled) + chrono::Duration::minutes(5));
+        job.status.state = state;
+        job
+    }
+
+    #[test]
+    fn test_plan_starts_only_the_latest_missed_run() {
+        let cronjob = cronjob("0 * * * *", ConcurrencyPolicy::Allow);
+        assert_eq!(plan_at(&cronjob, &[], "2026-03-01T09:59:00Z").run, None);
+
+        // 10:00, 11:00 and 12:00 are due
+        let plan = plan_at(&cronjob, &[], "2026-03-01T12:00:30Z");
+        assert_eq!(plan.run, Some(utc("2026-03-01T12:00:00Z")));
+        assert_eq!(plan.status.missed_runs, 2);
+        assert_eq!(plan.status.last_schedule_time, plan.run);
+        assert_eq!(plan.status.next_schedule_time, Some(utc("2026-03-01T13:00:00Z")));
+
+        let mut late = cronjob.clone();
+        late.starting_deadline_secs = Some(10);
+        let plan = plan_at(&late, &[], "2026-03-01T12:00:30Z");
+        assert_eq!(plan.run, None);
+        assert_eq!(plan.status.missed_runs, 3);
+
+        let mut suspended = cronjob.clone();
+        suspended.suspend = true;
+        let plan = plan_at(&suspended, &[], "2026-03-01T12:00:30Z");
+        assert_eq!((plan.run, plan.status.last_schedule_time), (None, None));
+    }
+
+    #[test]
+    fn test_plan_follows_concurrency_policy() {
+        let now = "2026-03-01T11:00:10Z";
+        let mut forbid = cronjob("0 * * * *", ConcurrencyPolicy::Forbid);
+        forbid.status.last_schedule_time = Some(utc("2026-03-01T10:00:00Z"));
+        let running = job(&forbid, "2026-03-01T10:00:00Z", JobState::Running);
+
+        let plan = plan_at(&forbid, &[&running], now);
+        assert_eq!(plan.run, None);
+        assert_eq!(plan.status.active, [running.name.as_str()]);
+        assert!(plan.status.message.unwrap().contains("still running"));
+
+        let mut replace = forbid.clone();
+        replace.concurrency_policy = ConcurrencyPolicy::Replace;
+        let plan = plan_at(&replace, &[&running], now);
+        assert_eq!(plan.run, Some(utc("2026-03-01T11:00:00Z")));
+        assert_eq!(plan.delete.iter().map(|j| &j.name).collect::<Vec<_>>(), [&running.name]);
+
+        let mut allow = forbid.clone();
+        allow.concurrency_policy = ConcurrencyPolicy::Allow;
+        let plan = plan_at(&allow, &[&running], now);
+        assert_eq!(plan.run, Some(utc("2026-03-01T11:00:00Z")));
+        assert!(plan.delete.is_empty());
+    }
+
+    #[test]
+    fn test_plan_keeps_job_history() {
+        let mut cronjob = cronjob("0 * * * *", ConcurrencyPolicy::Allow);
+        cronjob.status.last_schedule_time = Some(utc("2026-03-01T12:00:00Z"));
+        let old = job(&cronjob, "2026-03-01T10:00:00Z", JobState::Complete);
+        let new = job(&cronjob, "2026-03-01T11:00:00Z", JobState::Complete);
+        let failed = job(&cronjob, "2026-03-01T12:00:00Z", JobState::Failed);
+
+        let plan = plan_at(&cronjob, &[&old, &new, &failed], "2026-03-01T12:30:00Z");
+        assert_eq!(plan.delete.iter().map(|j| &j.name).collect::<Vec<_>>(), [&old.name]);
+        assert_eq!(plan.status.last_successful_time, new.status.finished_at);
+        assert!(plan.status.active.is_empty());
+    }
+}
@@ -1,108 +1,922 @@
-use serde::Deserialize;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use std::time::Duration;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
 use tokio::net::TcpStream;
 use uuid::Uuid;
-use common::{Task, TaskStatus};
-use crate::store::SharedState;
+use common::{
+    CronJob, CronJobSpec, DryRun, FieldError, Heartbeat, Job, JobSpec, Node, NodeDetails, NodeMetrics, NodeStatus, NodeUpdate, OrchError, Selector, Service,
+    ServiceDetails, ServiceSpec, Taint, Task, TaskHealth, TaskMetrics, TaskSpec, TaskStatus, Workflow, WorkflowSpec,
+};
+use common::task::DEFAULT_GRACE_PERIOD_SECS;
+use crate::http::{self, Request};
+use crate::scheduler;
+use crate::store::{NamedResource, SharedState};
 
 #[derive(Deserialize)]
 struct UpdateStatusRequest {
     status: TaskStatus,
     container_id: Option<String>,
+    reason: Option<String>,
 }
 
 pub async fn handle_connection(mut stream: TcpStream, store: SharedState) -> anyhow::Result<()> {
-    let mut buffer = [0; 1024];
-
-    let n = stream.read(&mut buffer).await?;
-    if n == 0 {
+    let Some(request) = http::read_request(&mut stream).await? else {
         return Ok(());
-    }
+    };
 
-    let request = String::from_utf8_lossy(&buffer[..n]);
-
-    if request.starts_with("GET /tasks") {
-        handle_get_tasks(stream, store).await?;
-    } else if request.starts_with("POST /tasks") {
-        handle_post_task(stream, request.to_string(), store).await?;
-    } else if request.starts_with("PUT /tasks") {
-        handle_update_status(stream, request.to_string(), store).await?; 
-    } else {
-        let response = "HTTP/1.1 404 NOT FOUND\r\nContent-Length: 0\r\n\r\n";
-        stream.write_all(response.as_bytes()).await?;
+    match (request.head.method.as_str(), request.segments().as_slice()) {
+        ("GET", ["tasks"]) => handle_get_tasks(stream, &request, store).await?,
+        ("POST", ["tasks"]) => handle_post_task(stream, &request, store).await?,
+        ("GET", ["tasks", "by-name", name]) => handle_get_task_by_name(stream, name, store).await?,
+        ("GET", ["tasks", id]) => handle_get_task(stream, id, store).await?,
+        ("PUT", ["tasks", id]) => handle_update_task(stream, id, &request, store).await?,
+        ("DELETE", ["tasks", id]) => handle_delete_task(stream, id, &request, store).await?,
+        ("PUT", ["tasks", id, "status"]) => handle_update_status(stream, id, &request, store).await?,
+        ("PUT", ["tasks", id, "health"]) => handle_update_health(stream, id, &request, store).await?,
+        ("GET", ["tasks", id, "logs"]) => handle_get_logs(stream, id, &request, store).await?,
+        ("POST", ["tasks", id, "exec"]) => handle_exec(stream, id, &request, store).await?,
+        ("GET", ["nodes"]) => handle_get_nodes(stream, &request, store).await?,
+        ("GET", ["nodes", id]) => handle_get_node(stream, id, store).await?,
+        ("POST", ["nodes"]) => handle_register_node(stream, &request, store).await?,
+        ("POST", ["nodes", id, "taints"]) => handle_add_taint(stream, id, &request, store).await?,
+        ("DELETE", ["nodes", id, "taints", key]) => handle_remove_taint(stream, id, key, store).await?,
+        ("POST", ["nodes", id, "cordon"]) => handle_cordon(stream, id, true, store).await?,
+        ("POST", ["nodes", id, "uncordon"]) => handle_cordon(stream, id, false, store).await?,
+        ("POST", ["nodes", id, "drain"]) => handle_drain(stream, id, &request, store).await?,
+        ("GET", ["services"]) => handle_get_services(stream, &request, store).await?,
+        ("POST", ["services"]) => handle_post_resource::<ServiceSpec>(stream, &request, store).await?,
+        ("GET", ["services", name]) => handle_get_service(stream, name, store).await?,
+        ("PUT", ["services", name]) => handle_update_resource::<ServiceSpec>(stream, name, &request, store).await?,
+        ("DELETE", ["services", name]) => handle_delete_resource::<Service>(stream, name, &request, store).await?,
+        ("PUT", ["services", name, "scale"]) => handle_scale_service(stream, name, &request, store).await?,
+        ("POST", ["services", name, "rollback"]) => handle_rollback_service(stream, name, &request, store).await?,
+        ("GET", ["jobs"]) => handle_get_resources::<Job>(stream, &request, store).await?,
+        ("POST", ["jobs"]) => handle_post_resource::<JobSpec>(stream, &request, store).await?,
+        ("GET", ["jobs", name]) => handle_get_resource::<Job>(stream, name, store).await?,
+        ("PUT", ["jobs", name]) => handle_update_resource::<JobSpec>(stream, name, &request, store).await?,
+        ("DELETE", ["jobs", name]) => handle_delete_resource::<Job>(stream, name, &request, store).await?,
+        ("GET", ["cronjobs"]) => handle_get_resources::<CronJob>(stream, &request, store).await?,
+        ("POST", ["cronjobs"]) => handle_post_resource::<CronJobSpec>(stream, &request, store).await?,
+        ("GET", ["cronjobs", name]) => handle_get_resource::<CronJob>(stream, name, store).await?,
+        ("PUT", ["cronjobs", name]) => handle_update_resource::<CronJobSpec>(stream, name, &request, store).await?,
+        ("DELETE", ["cronjobs", name]) => handle_delete_resource::<CronJob>(stream, name, &request, store).await?,
+        ("PUT", ["cronjobs", name, "suspend"]) => handle_suspend_cronjob(stream, name, &request, store).await?,
+        ("POST", ["cronjobs", name, "trigger"]) => handle_trigger_cronjob(stream, name, store).await?,
+        ("GET", ["workflows"]) => handle_get_resources::<Workflow>(stream, &request, store).await?,
+        ("POST", ["workflows"]) => handle_post_resource::<WorkflowSpec>(stream, &request, store).await?,
+        ("GET", ["workflows", name]) => handle_get_resource::<Workflow>(stream, name, store).await?,
+        ("PUT", ["workflows", name]) => handle_update_resource::<WorkflowSpec>(stream, name, &request, store).await?,
+        ("DELETE", ["workflows", name]) => handle_delete_resource::<Workflow>(stream, name, &request, store).await?,
+        ("GET", ["metrics", "nodes"]) => handle_node_metrics(stream, &request, store).await?,
+        ("GET", ["metrics", "tasks"]) => handle_task_metrics(stream, &request, store).await?,
+        ("GET", ["watch"]) => handle_watch(stream, &request, store).await?,
+        _ => http::respond_empty(&mut stream, "404 NOT FOUND").await?,
     }
 
     Ok(())
 }
 
-async fn handle_get_tasks(mut stream: TcpStream, store: SharedState) -> anyhow::Result<()> {
-    let mut tasks = store.list_tasks()?;
-    tasks = tasks
+/// `GET /tasks?status=&node=&name=&labelSelector=`, all filters are optional.
+async fn handle_get_tasks(mut stream: TcpStream, request: &Request, store: SharedState) -> anyhow::Result<()> {
+    let selector = match label_selector(request) {
+        Ok(selector) => selector,
+        Err(e) => return respond_bad_request(&mut stream, e).await,
+    };
+    let status = match request.query_param("status").map(|s| s.parse::<TaskStatus>()) {
+        Some(Ok(status)) => Some(status),
+        Some(Err(_)) => return http::respond_empty(&mut stream, "400 BAD REQUEST").await,
+        None => None,
+    };
+    let node = request.query_param("node");
+    let name = request.query_param("name");
+
+    let tasks = match &selector {
+        Some(selector) => store.select_tasks(selector)?,
+        None => store.list_tasks()?,
+    };
+    let tasks = tasks
         .into_iter()
-        .filter(|t| t.status == TaskStatus::Scheduled)
+        .filter(|t| status.as_ref().is_none_or(|s| &t.status == s))
+        .filter(|t| node.is_none_or(|n| t.node_id.as_deref() == Some(n)))
+        .filter(|t| name.is_none_or(|n| t.name == n))
         .collect::<Vec<Task>>();
     let body = serde_json::to_string(&tasks)?;
 
-    let response = format!(
-        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
-        body.len(),
-        body
-    );
+    http::respond_json(&mut stream, "200 OK", &body).await
+}
+
+async fn handle_get_task(mut stream: TcpStream, id: &str, store: SharedState) -> anyhow::Result<()> {
+    let Ok(id_uuid) = Uuid::parse_str(id) else {
+        return http::respond_empty(&mut stream, "400 BAD REQUEST").await;
+    };
+
+    match store.get_task(id_uuid)? {
+        Some(task) => respond_task(&mut stream, "200 OK", &task).await,
+        None => respond_error(&mut stream, OrchError::TaskNotFound(id.to_string())).await,
+    }
+}
+
+async fn handle_get_task_by_name(mut stream: TcpStream, name: &str, store: SharedState) -> anyhow::Result<()> {
+    match store.get_task_by_name(name)? {
+        Some(task) => respond_task(&mut stream, "200 OK", &task).await,
+        None => respond_error(&mut stream, OrchError::TaskNotFound(name.to_string())).await,
+    }
+}
+
+/// `POST /tasks?dryRun=true` validates the spec and checks where it would be scheduled, without storing it.
+async fn handle_post_task(mut stream: TcpStream, request: &Request, store: SharedState) -> anyhow::Result<()> {
+    let Ok(spec) = serde_json::from_str::<TaskSpec>(&request.body) else {
+        return http::respond_empty(&mut stream, "400 BAD REQUEST").await;
+    };
+
+    let errors = spec.validate();
+    if !errors.is_empty() {
+        return respond_error(&mut stream, OrchError::ValidationFailed(errors)).await;
+    }
+
+    if is_dry_run(request) {
+        return respond_dry_run(&mut stream, spec.into_task(), &store).await;
+    }
+
+    // acquire a write lock and save the task
+    match store.add_task(spec.into_task()) {
+        Ok(new_task) => respond_task(&mut stream, "201 CREATED", &new_task).await,
+        Err(e) => respond_error(&mut stream, e).await,
+    }
+}
+
+/// `PUT /tasks/{id}` replaces the spec of a pending task, honouring `If-Match` and `?dryRun=true`.
+async fn handle_update_task(mut stream: TcpStream, id: &str, request: &Request, store: SharedState) -> anyhow::Result<()> {
+    let Ok(id_uuid) = Uuid::parse_str(id) else {
+        return http::respond_empty(&mut stream, "400 BAD REQUEST").await;
+    };
+
+    let Ok(expected_version) = request.if_match() else {
+        return http::respond_empty(&mut stream, "400 BAD REQUEST").await;
+    };
+
+    let Ok(spec) = serde_json::from_str::<TaskSpec>(&request.body) else {
+        return http::respond_empty(&mut stream, "400 BAD REQUEST").await;
+    };
+
+    let errors = spec.validate();
+    if !errors.is_empty() {
+        return respond_error(&mut stream, OrchError::ValidationFailed(errors)).await;
+    }
+
+    let dry_run = is_dry_run(request);
+    match store.update_spec(id_uuid, spec, expected_version, dry_run) {
+        Ok(Some(task)) if dry_run => respond_dry_run(&mut stream, task, &store).await,
+        Ok(Some(task)) => respond_task(&mut stream, "200 OK", &task).await,
+        Ok(None) => respond_error(&mut stream, OrchError::TaskNotFound(id.to_string())).await,
+        Err(e) => respond_error(&mut stream, e).await,
+    }
+}
+
+/// `DELETE /tasks/{id}` removes the task, the worker running it stops its container.
+async fn handle_delete_task(mut stream: TcpStream, id: &str, request: &Request, store: SharedState) -> anyhow::Result<()> {
+    let Ok(id_uuid) = Uuid::parse_str(id) else {
+        return http::respond_empty(&mut stream, "400 BAD REQUEST").await;
+    };
+
+    let Ok(expected_version) = request.if_match() else {
+        return http::respond_empty(&mut stream, "400 BAD REQUEST").await;
+    };
+
+    match store.delete_task(id_uuid, expected_version) {
+        Ok(Some(task)) => respond_task(&mut stream, "200 OK", &task).await,
+        Ok(None) => respond_error(&mut stream, OrchError::TaskNotFound(id.to_string())).await,
+        Err(e) => respond_error(&mut stream, e).await,
+    }
+}
+
+async fn handle_update_status(mut stream: TcpStream, id: &str, request: &Request, store: SharedState) -> anyhow::Result<()> {
+    let id_uuid = match Uuid::parse_str(id) {
+        Ok(id_uuid) => id_uuid,
+        Err(e) => return respond_bad_request(&mut stream, OrchError::ValidationFailed(vec![FieldError::new("id", e.to_string())])).await,
+    };
+
+    let Ok(expected_version) = request.if_match() else {
+        return http::respond_empty(&mut stream, "400 BAD REQUEST").await;
+    };
+
+    let update_req = match serde_json::from_str::<UpdateStatusRequest>(request.body.trim()) {
+        Ok(update_req) => update_req,
+        Err(e) => return respond_bad_request(&mut stream, OrchError::ValidationFailed(vec![FieldError::new("body", e.to_string())])).await,
+    };
+
+    match store.update_status(id_uuid, update_req.status, update_req.container_id, update_req.reason, expected_version) {
+        Ok(Some(task)) => respond_task(&mut stream, "200 OK", &task).await,
+        Ok(None) => respond_error(&mut stream, OrchError::TaskNotFound(id.to_string())).await,
+        Err(e) => respond_error(&mut stream, e).await,
+    }
+}
+
+async fn handle_update_health(mut stream: TcpStream, id: &str, request: &Request, store: SharedState) -> anyhow::Result<()> {
+    let Ok(id_uuid) = Uuid::parse_str(id) else {
+        return http::respond_empty(&mut stream, "400 BAD REQUEST").await;
+    };
+
+    let Ok(expected_version) = request.if_match() else {
+        return http::respond_empty(&mut stream, "400 BAD REQUEST").await;
+    };
+
+    let Ok(health) = serde_json::from_str::<TaskHealth>(&request.body) else {
+        return http::respond_empty(&mut stream, "400 BAD REQUEST").await;
+    };
+
+    match store.update_health(id_uuid, health, expected_version) {
+        Ok(Some(task)) => respond_task(&mut stream, "200 OK", &task).await,
+        Ok(None) => respond_error(&mut stream, OrchError::TaskNotFound(id.to_string())).await,
+        Err(e) => respond_error(&mut stream, e).await,
+    }
+}
+
+/// Relays `GET /tasks/{id}/logs?tail=&since=&follow=` to the worker running the task.
+async fn handle_get_logs(stream: TcpStream, id: &str, request: &Request, store: SharedState) -> anyhow::Result<()> {
+    // only pass through the parameters the worker understands
+    let query = ["tail", "since", "follow"]
+        .iter()
+        .filter_map(|key| request.query_param(key).map(|v| format!("{}={}", key, v)))
+        .collect::<Vec<_>>()
+        .join("&");
+
+    relay_to_worker(stream, id, store, |container_id, host| {
+        format!(
+            "GET /containers/{}/logs?{} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+            container_id, query, host
+        )
+    })
+    .await
+}
+
+/// Relays `POST /tasks/{id}/exec` to the worker running the task.
+///
+/// The body (`{"command": [..], "interactive": bool, "tty": bool}`) is forwarded untouched,
+/// once the worker answers with `101 Switching Protocols` the connection carries the raw session.
+async fn handle_exec(stream: TcpStream, id: &str, request: &Request, store: SharedState) -> anyhow::Result<()> {
+    relay_to_worker(stream, id, store, |container_id, host| {
+        format!(
+            "POST /containers/{}/exec HTTP/1.1\r\nHost: {}\r\nConnection: Upgrade\r\nUpgrade: tcp\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            container_id,
+            host,
+            request.body.len(),
+            request.body
+        )
+    })
+    .await
+}
+
+/// Finds the node running the task, sends it the request built by `upstream_request`
+/// (called with the container id and the node's API address) and pipes both directions
+/// of the connection through until either side hangs up.
+async fn relay_to_worker(
+    mut stream: TcpStream,
+    id: &str,
+    store: SharedState,
+    upstream_request: impl FnOnce(&str, &str) -> String,
+) -> anyhow::Result<()> {
+    let Ok(id_uuid) = Uuid::parse_str(id) else {
+        return http::respond_empty(&mut stream, "400 BAD REQUEST").await;
+    };
+
+    let Some(task) = store.get_task(id_uuid)? else {
+        return respond_error(&mut stream, OrchError::TaskNotFound(id.to_string())).await;
+    };
+
+    let (Some(node_id), Some(container_id)) = (task.node_id, task.container_id) else {
+        let body = serde_json::to_string(&OrchError::TaskStoreError(format!("Task {} has no running container", id)))?;
+        return http::respond_json(&mut stream, "409 CONFLICT", &body).await;
+    };
+
+    let Some(node) = store.get_node(&node_id)? else {
+        let body = serde_json::to_string(&OrchError::NodeNotFound(node_id))?;
+        return http::respond_json(&mut stream, "502 BAD GATEWAY", &body).await;
+    };
+
+    let mut upstream = match TcpStream::connect(node.api_address()).await {
+        Ok(upstream) => upstream,
+        Err(e) => {
+            return respond_error(&mut stream, OrchError::NetworkError(format!("Failed to reach node {}: {}", node.id, e))).await;
+        }
+    };
+
+    upstream.write_all(upstream_request(&container_id, &node.api_address()).as_bytes()).await?;
 
-    stream.write_all(response.as_bytes()).await?;
+    tokio::io::copy_bidirectional(&mut stream, &mut upstream).await?;
     Ok(())
 }
 
-async fn handle_post_task(mut stream: TcpStream, request: String, store: SharedState) -> anyhow::Result<()> {
-    // check body (usually after new double line)
-    if let Some(body) = request.split("\r\n\r\n").last() {
-       // clean up any trailing null bytes from the TCP buffer
-        let body = body.trim_matches(char::from(0));
+/// `GET /nodes?labelSelector=`, each node with the resources its tasks claim.
+async fn handle_get_nodes(mut stream: TcpStream, request: &Request, store: SharedState) -> anyhow::Result<()> {
+    let selector = match label_selector(request) {
+        Ok(selector) => selector.unwrap_or_default(),
+        Err(e) => return respond_bad_request(&mut stream, e).await,
+    };
+
+    let mut nodes: Vec<Node> = store.list_nodes()?.into_iter().filter(|n| selector.matches(&n.labels)).collect();
+    nodes.sort_by(|a, b| a.id.cmp(&b.id));
+    let body = serde_json::to_string(&node_details(nodes, &store)?)?;
+
+    http::respond_json(&mut stream, "200 OK", &body).await
+}
+
+/// `GET /nodes/{id}`
+async fn handle_get_node(mut stream: TcpStream, id: &str, store: SharedState) -> anyhow::Result<()> {
+    let Some(node) = store.get_node(id)? else {
+        return respond_error(&mut stream, OrchError::NodeNotFound(id.to_string())).await;
+    };
 
-        if let Ok(task_req) = serde_json::from_str::<serde_json::Value>(body) {
-            let name = task_req["name"].as_str().unwrap_or("unnamed");
-            let image = task_req["image"].as_str().unwrap_or("unnamed");
+    let body = serde_json::to_string(&node_details(vec![node], &store)?[0])?;
+    http::respond_json(&mut stream, "200 OK", &body).await
+}
 
-            let new_task = Task::new(name.to_string(), image.to_string());
+/// Adds the allocated and available resources and the task count to each node.
+fn node_details(nodes: Vec<Node>, store: &SharedState) -> Result<Vec<NodeDetails>, OrchError> {
+    let allocations = scheduler::allocations(&store.list_tasks()?);
 
-            // acquire a write lock and save the task
-            store.add_task(new_task.clone())?;
+    Ok(nodes
+        .into_iter()
+        .map(|mut node| {
+            let allocated = allocations.get(&node.id).cloned().unwrap_or_default();
+            node.available_memory = node.total_memory - allocated.memory;
+            node.available_cpu = node.total_cpu - allocated.cpu;
+            NodeDetails {
+                node,
+                allocated_memory: allocated.memory,
+                allocated_cpu: allocated.cpu,
+                task_count: allocated.tasks,
+            }
+        })
+        .collect())
+}
 
-            let body = serde_json::to_string(&new_task)?;
-            let response = format!(
-                "HTTP/1.1 201 CREATED\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
-                body.len(),
-                body
-            );
-            stream.write_all(response.as_bytes()).await?;
-            return Ok(());
+/// `POST /nodes` registers the node, the worker repeats it as its heartbeat along with its usage.
+async fn handle_register_node(mut stream: TcpStream, request: &Request, store: SharedState) -> anyhow::Result<()> {
+    if let Ok(Heartbeat { mut node, usage }) = serde_json::from_str::<Heartbeat>(&request.body) {
+        node.status = NodeStatus::Ready;
+        if let Some(usage) = usage {
+            store.record_usage(&node.id, usage)?;
         }
+        store.register_node(node)?;
+        return http::respond_empty(&mut stream, "200 OK").await;
     }
 
-    let err_resp = "HTTP/1.1 400 BAD REQUEST\r\nContent-Length: 0\r\n\r\n";
-    stream.write_all(err_resp.as_bytes()).await?;
-    Ok(())
+    http::respond_empty(&mut stream, "400 BAD REQUEST").await
+}
+
+#[derive(Deserialize)]
+struct ScaleRequest {
+    replicas: u32,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct RollbackRequest {
+    /// The revision to go back to, the previous one when left out
+    revision: Option<u64>,
+}
+
+/// `GET /services?labelSelector=`
+async fn handle_get_services(mut stream: TcpStream, request: &Request, store: SharedState) -> anyhow::Result<()> {
+    let selector = match label_selector(request) {
+        Ok(selector) => selector.unwrap_or_default(),
+        Err(e) => return respond_bad_request(&mut stream, e).await,
+    };
+
+    let mut services: Vec<Service> = store
+        .list_resources::<Service>()?
+        .into_iter()
+        .filter(|s| selector.matches(&s.labels))
+        .collect();
+    services.sort_by(|a, b| a.name.cmp(&b.name));
+    let body = serde_json::to_string(&service_details(services, &store)?)?;
+
+    http::respond_json(&mut stream, "200 OK", &body).await
+}
+
+async fn handle_get_service(mut stream: TcpStream, name: &str, store: SharedState) -> anyhow::Result<()> {
+    let Some(service) = store.get_resource::<Service>(name)? else {
+        return respond_error(&mut stream, Service::not_found(name)).await;
+    };
+
+    let details = service_details(vec![service], &store)?.remove(0);
+    let body = serde_json::to_string(&details)?;
+    http::respond_json_versioned(&mut stream, "200 OK", details.service.resource_version, &body).await
+}
+
+/// Counts the current, running and ready tasks of each service.
+fn service_details(services: Vec<Service>, store: &SharedState) -> Result<Vec<ServiceDetails>, OrchError> {
+    let tasks = store.list_tasks()?;
+
+    Ok(services
+        .into_iter()
+        .map(|service| {
+            let owned: Vec<&Task> = tasks.iter().filter(|t| service.owns(t) && !t.status.is_terminal()).collect();
+            let updated: Vec<&&Task> = owned.iter().filter(|t| service.is_updated(t)).collect();
+            ServiceDetails {
+                current_replicas: owned.len() as u32,
+                running_replicas: owned.iter().filter(|t| t.status == TaskStatus::Running).count() as u32,
+                ready_replicas: owned.iter().filter(|t| t.is_ready()).count() as u32,
+                updated_replicas: updated.len() as u32,
+                updated_ready_replicas: updated.iter().filter(|t| t.is_ready()).count() as u32,
+                service,
+            }
+        })
+        .collect())
+}
+
+/// `PUT /services/{name}/scale` with `{"replicas": N}` changes only the replica count.
+async fn handle_scale_service(mut stream: TcpStream, name: &str, request: &Request, store: SharedState) -> anyhow::Result<()> {
+    let Ok(expected_version) = request.if_match() else {
+        return http::respond_empty(&mut stream, "400 BAD REQUEST").await;
+    };
+
+    let Ok(ScaleRequest { replicas }) = serde_json::from_str::<ScaleRequest>(&request.body) else {
+        return http::respond_empty(&mut stream, "400 BAD REQUEST").await;
+    };
+
+    respond_update(&mut stream, name, expected_version, &store, |service: &mut Service| service.replicas = replicas).await
+}
+
+/// `POST /services/{name}/rollback` with `{"revision": N}`, or an empty body for the previous revision.
+///
+/// The old template becomes a new revision and is rolled out like any other change.
+async fn handle_rollback_service(mut stream: TcpStream, name: &str, request: &Request, store: SharedState) -> anyhow::Result<()> {
+    let Ok(expected_version) = request.if_match() else {
+        return http::respond_empty(&mut stream, "400 BAD REQUEST").await;
+    };
+
+    let rollback = if request.body.trim().is_empty() {
+        RollbackRequest::default()
+    } else {
+        let Ok(rollback) = serde_json::from_str::<RollbackRequest>(&request.body) else {
+            return http::respond_empty(&mut stream, "400 BAD REQUEST").await;
+        };
+        rollback
+    };
+
+    let Some(mut service) = store.get_resource::<Service>(name)? else {
+        return respond_error(&mut stream, OrchError::ServiceNotFound(name.to_string())).await;
+    };
+    if let Err(e) = service.check_version(expected_version) {
+        return respond_error(&mut stream, e).await;
+    }
+    // rolled back on the copy, which replaces the stored service unless that changed meanwhile
+    let version = service.resource_version;
+    if let Err(message) = service.rollback(rollback.revision) {
+        let errors = vec![FieldError::new("revision", message)];
+        return respond_error(&mut stream, OrchError::ValidationFailed(errors)).await;
+    }
+
+    respond_update(&mut stream, name, Some(version), &store, |stored: &mut Service| *stored = service).await
+}
+
+#[derive(Deserialize)]
+struct SuspendRequest {
+    suspend: bool,
+}
+
+/// `PUT /cronjobs/{name}/suspend` with `{"suspend": true}` stops starting jobs, `false` resumes.
+async fn handle_suspend_cronjob(mut stream: TcpStream, name: &str, request: &Request, store: SharedState) -> anyhow::Result<()> {
+    let Ok(expected_version) = request.if_match() else {
+        return http::respond_empty(&mut stream, "400 BAD REQUEST").await;
+    };
+
+    let Ok(SuspendRequest { suspend }) = serde_json::from_str::<SuspendRequest>(&request.body) else {
+        return http::respond_empty(&mut stream, "400 BAD REQUEST").await;
+    };
+
+    respond_update(&mut stream, name, expected_version, &store, |cronjob: &mut CronJob| cronjob.suspend = suspend).await
+}
+
+/// `POST /cronjobs/{name}/trigger` starts a job right away, outside the schedule and its concurrency policy.
+///
+/// Responds with the new job.
+async fn handle_trigger_cronjob(mut stream: TcpStream, name: &str, store: SharedState) -> anyhow::Result<()> {
+    let Some(cronjob) = store.get_resource::<CronJob>(name)? else {
+        return respond_error(&mut stream, OrchError::CronJobNotFound(name.to_string())).await;
+    };
+
+    match store.add_resource(cronjob.new_manual_job()) {
+        Ok(job) => respond_resource(&mut stream, "201 CREATED", &job).await,
+        Err(e) => respond_error(&mut stream, e).await,
+    }
+}
+
+/// The spec a named resource is created from and replaced with.
+trait ResourceSpec: DeserializeOwned {
+    type Resource: NamedResource + Serialize;
+
+    fn name(&self) -> &str;
+    fn validate(&self) -> Vec<FieldError>;
+    /// Changes to the fields that are fixed once the resource exists
+    fn immutable_changes(&self, _current: &Self::Resource) -> Vec<FieldError> {
+        Vec::new()
+    }
+    fn into_resource(self) -> Self::Resource;
+    fn apply_to(self, resource: &mut Self::Resource);
+}
+
+impl ResourceSpec for ServiceSpec {
+    type Resource = Service;
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn validate(&self) -> Vec<FieldError> {
+        ServiceSpec::validate(self)
+    }
+
+    fn into_resource(self) -> Service {
+        self.into_service()
+    }
+
+    fn apply_to(self, service: &mut Service) {
+        ServiceSpec::apply_to(self, service)
+    }
+}
+
+impl ResourceSpec for JobSpec {
+    type Resource = Job;
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn validate(&self) -> Vec<FieldError> {
+        JobSpec::validate(self)
+    }
+
+    fn immutable_changes(&self, job: &Job) -> Vec<FieldError> {
+        JobSpec::immutable_changes(self, job)
+    }
+
+    fn into_resource(self) -> Job {
+        self.into_job()
+    }
+
+    fn apply_to(self, job: &mut Job) {
+        JobSpec::apply_to(self, job)
+    }
+}
+
+impl ResourceSpec for CronJobSpec {
+    type Resource = CronJob;
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn validate(&self) -> Vec<FieldError> {
+        CronJobSpec::validate(self)
+    }
+
+    fn into_resource(self) -> CronJob {
+        self.into_cronjob()
+    }
+
+    fn apply_to(self, cronjob: &mut CronJob) {
+        CronJobSpec::apply_to(self, cronjob)
+    }
+}
+
+impl ResourceSpec for WorkflowSpec {
+    type Resource = Workflow;
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn validate(&self) -> Vec<FieldError> {
+        WorkflowSpec::validate(self)
+    }
+
+    fn immutable_changes(&self, workflow: &Workflow) -> Vec<FieldError> {
+        WorkflowSpec::immutable_changes(self, workflow)
+    }
+
+    fn into_resource(self) -> Workflow {
+        self.into_workflow()
+    }
+
+    fn apply_to(self, workflow: &mut Workflow) {
+        WorkflowSpec::apply_to(self, workflow)
+    }
+}
+
+/// `GET /{jobs,cronjobs,workflows}?labelSelector=`, sorted by name.
+async fn handle_get_resources<R: NamedResource + Serialize>(mut stream: TcpStream, request: &Request, store: SharedState) -> anyhow::Result<()> {
+    let selector = match label_selector(request) {
+        Ok(selector) => selector.unwrap_or_default(),
+        Err(e) => return respond_bad_request(&mut stream, e).await,
+    };
+
+    let mut resources: Vec<R> = store.list_resources::<R>()?.into_iter().filter(|r| selector.matches(r.labels())).collect();
+    resources.sort_by(|a, b| a.name().cmp(b.name()));
+    let body = serde_json::to_string(&resources)?;
+
+    http::respond_json(&mut stream, "200 OK", &body).await
+}
+
+async fn handle_get_resource<R: NamedResource + Serialize>(mut stream: TcpStream, name: &str, store: SharedState) -> anyhow::Result<()> {
+    match store.get_resource::<R>(name)? {
+        Some(resource) => respond_resource(&mut stream, "200 OK", &resource).await,
+        None => respond_error(&mut stream, R::not_found(name)).await,
+    }
+}
+
+/// `POST /{services,jobs,cronjobs,workflows}` creates the resource, its controller then starts its tasks or jobs.
+async fn handle_post_resource<S: ResourceSpec>(mut stream: TcpStream, request: &Request, store: SharedState) -> anyhow::Result<()> {
+    let Ok(spec) = serde_json::from_str::<S>(&request.body) else {
+        return http::respond_empty(&mut stream, "400 BAD REQUEST").await;
+    };
+
+    let errors = spec.validate();
+    if !errors.is_empty() {
+        return respond_error(&mut stream, OrchError::ValidationFailed(errors)).await;
+    }
+
+    match store.add_resource(spec.into_resource()) {
+        Ok(resource) => respond_resource(&mut stream, "201 CREATED", &resource).await,
+        Err(e) => respond_error(&mut stream, e).await,
+    }
+}
+
+/// `PUT /{services,jobs,cronjobs,workflows}/{name}` replaces the spec, honouring `If-Match`.
+///
+/// A new service template is rolled out by the controller, jobs already started by a cron job keep
+/// their template. The fields of jobs and workflows that are fixed once they run can't change.
+async fn handle_update_resource<S: ResourceSpec>(mut stream: TcpStream, name: &str, request: &Request, store: SharedState) -> anyhow::Result<()> {
+    let Ok(expected_version) = request.if_match() else {
+        return http::respond_empty(&mut stream, "400 BAD REQUEST").await;
+    };
+
+    let Ok(spec) = serde_json::from_str::<S>(&request.body) else {
+        return http::respond_empty(&mut stream, "400 BAD REQUEST").await;
+    };
+
+    let Some(current) = store.get_resource::<S::Resource>(name)? else {
+        return respond_error(&mut stream, S::Resource::not_found(name)).await;
+    };
+
+    let mut errors = spec.validate();
+    if spec.name() != name {
+        let kind = S::Resource::KIND;
+        errors.push(FieldError::new("name", format!("must stay '{}', {}s can't be renamed", name, kind)));
+    }
+    errors.extend(spec.immutable_changes(&current));
+    if !errors.is_empty() {
+        return respond_error(&mut stream, OrchError::ValidationFailed(errors)).await;
+    }
+
+    // without If-Match, the resource must still be the one the spec was checked against
+    let version = expected_version.unwrap_or(current.resource_version());
+    respond_update(&mut stream, name, Some(version), &store, |resource| spec.apply_to(resource)).await
 }
 
-async fn handle_update_status(mut stream: TcpStream, request: String, store: SharedState) -> anyhow::Result<()> {
-    // Basic extraction of ID from PUT /tasks/<id>/status
-    let first_line = request.lines().next().unwrap_or("");
-    let parts: Vec<&str> = first_line.split_whitespace().collect();
-    if parts.len() < 2 { return Ok(()); }
+/// `DELETE /{services,jobs,cronjobs,workflows}/{name}` removes the resource, its controller then deletes its tasks or jobs.
+async fn handle_delete_resource<R: NamedResource + Serialize>(mut stream: TcpStream, name: &str, request: &Request, store: SharedState) -> anyhow::Result<()> {
+    let Ok(expected_version) = request.if_match() else {
+        return http::respond_empty(&mut stream, "400 BAD REQUEST").await;
+    };
 
-    let path = parts[1];
-    let id = path.split('/').nth(2).unwrap_or("");
-    let id_uuid = Uuid::parse_str(id)?;
+    match store.delete_resource::<R>(name, expected_version) {
+        Ok(Some(resource)) => respond_resource(&mut stream, "200 OK", &resource).await,
+        Ok(None) => respond_error(&mut stream, R::not_found(name)).await,
+        Err(e) => respond_error(&mut stream, e).await,
+    }
+}
 
-    if let Some(body) = request.split("\r\n\r\n").last() {
-        let body = body.trim_matches(char::from(0)).trim();
-        if let Ok(update_req) = serde_json::from_str::<UpdateStatusRequest>(body) {
-             store.update_status(id_uuid, update_req.status, update_req.container_id)?;
-            let response = "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n";
-            stream.write_all(response.as_bytes()).await?;
-            return Ok(());
+/// Changes the resource unless it moved past `expected_version`, and responds with the result.
+async fn respond_update<R: NamedResource + Serialize>(
+    stream: &mut TcpStream,
+    name: &str,
+    expected_version: Option<u64>,
+    store: &SharedState,
+    change: impl FnOnce(&mut R),
+) -> anyhow::Result<()> {
+    match store.update_resource(name, expected_version, change) {
+        Ok(Some(resource)) => respond_resource(stream, "200 OK", &resource).await,
+        Ok(None) => respond_error(stream, R::not_found(name)).await,
+        Err(e) => respond_error(stream, e).await,
+    }
+}
+
+async fn respond_resource<R: NamedResource + Serialize>(stream: &mut TcpStream, status: &str, resource: &R) -> anyhow::Result<()> {
+    let body = serde_json::to_string(resource)?;
+    http::respond_json_versioned(stream, status, resource.resource_version(), &body).await
+}
+
+/// `GET /metrics/nodes?labelSelector=`, the recent usage of each node.
+async fn handle_node_metrics(mut stream: TcpStream, request: &Request, store: SharedState) -> anyhow::Result<()> {
+    let selector = match label_selector(request) {
+        Ok(selector) => selector.unwrap_or_default(),
+        Err(e) => return respond_bad_request(&mut stream, e).await,
+    };
+
+    let mut metrics = Vec::new();
+    for node in store.list_nodes()?.into_iter().filter(|n| selector.matches(&n.labels)) {
+        metrics.push(NodeMetrics {
+            samples: store.node_usage(&node.id)?,
+            node_id: node.id,
+            total_cpu: node.total_cpu,
+            total_memory: node.total_memory,
+        });
+    }
+    metrics.sort_by(|a, b| a.node_id.cmp(&b.node_id));
+    let body = serde_json::to_string(&metrics)?;
+
+    http::respond_json(&mut stream, "200 OK", &body).await
+}
+
+/// `GET /metrics/tasks?node=&labelSelector=`, the recent usage of each task a worker measured.
+async fn handle_task_metrics(mut stream: TcpStream, request: &Request, store: SharedState) -> anyhow::Result<()> {
+    let selector = match label_selector(request) {
+        Ok(selector) => selector,
+        Err(e) => return respond_bad_request(&mut stream, e).await,
+    };
+    let node = request.query_param("node");
+
+    let tasks = match &selector {
+        Some(selector) => store.select_tasks(selector)?,
+        None => store.list_tasks()?,
+    };
+    let mut metrics = Vec::new();
+    for task in tasks {
+        let Some((node_id, samples)) = store.task_usage(&task.id)? else {
+            continue;
+        };
+        if node.is_some_and(|n| n != node_id) {
+            continue;
         }
+        metrics.push(TaskMetrics {
+            task_id: task.id,
+            name: task.name,
+            node_id,
+            cpu: task.cpu,
+            memory: task.memory,
+            samples,
+        });
     }
-    stream.write_all("HTTP/1.1 404 NOT FOUND\r\n\r\n".as_bytes()).await?;
-    Ok(())
-}
\ No newline at end of file
+    metrics.sort_by(|a, b| a.name.cmp(&b.name));
+    let body = serde_json::to_string(&metrics)?;
+
+    http::respond_json(&mut stream, "200 OK", &body).await
+}
+
+/// `POST /nodes/{id}/taints` adds the taint, replacing one with the same key and effect.
+async fn handle_add_taint(mut stream: TcpStream, id: &str, request: &Request, store: SharedState) -> anyhow::Result<()> {
+    let Ok(taint) = serde_json::from_str::<Taint>(&request.body) else {
+        return http::respond_empty(&mut stream, "400 BAD REQUEST").await;
+    };
+
+    let result = store.update_taints(id, |taints| {
+        taints.retain(|t| t.key != taint.key || t.effect != taint.effect);
+        taints.push(taint);
+    });
+    respond_node_update(&mut stream, id, result).await
+}
+
+/// `DELETE /nodes/{id}/taints/{key}` removes every taint with that key.
+async fn handle_remove_taint(mut stream: TcpStream, id: &str, key: &str, store: SharedState) -> anyhow::Result<()> {
+    let result = store.update_taints(id, |taints| taints.retain(|t| t.key != key));
+    respond_node_update(&mut stream, id, result).await
+}
+
+/// `POST /nodes/{id}/cordon` and `POST /nodes/{id}/uncordon`
+async fn handle_cordon(mut stream: TcpStream, id: &str, unschedulable: bool, store: SharedState) -> anyhow::Result<()> {
+    let result = store
+        .set_unschedulable(id, unschedulable)
+        .map(|node| node.map(|node| (node, Vec::new())));
+    respond_node_update(&mut stream, id, result).await
+}
+
+/// `POST /nodes/{id}/drain` cordons the node and sends its tasks back to the scheduler once their
+/// containers stopped, or after `?gracePeriodSecs=N` (30 by default).
+async fn handle_drain(mut stream: TcpStream, id: &str, request: &Request, store: SharedState) -> anyhow::Result<()> {
+    let grace_period = match request.query_param("gracePeriodSecs").map(|v| v.parse::<u64>()) {
+        Some(Ok(secs)) => secs,
+        Some(Err(_)) => return http::respond_empty(&mut stream, "400 BAD REQUEST").await,
+        None => DEFAULT_GRACE_PERIOD_SECS,
+    };
+
+    let result = store.drain_node(id, Duration::from_secs(grace_period));
+    respond_node_update(&mut stream, id, result).await
+}
+
+async fn respond_node_update(
+    stream: &mut TcpStream,
+    id: &str,
+    result: Result<Option<(Node, Vec<Task>)>, OrchError>,
+) -> anyhow::Result<()> {
+    match result {
+        Ok(Some((node, evicted))) => {
+            for task in &evicted {
+                match &task.rescheduling {
+                    Some(rescheduling) => println!("task {} is rescheduled: {}", task.id, rescheduling.reason),
+                    None => println!("task {} is evicted from {}", task.id, id),
+                }
+            }
+            let body = serde_json::to_string(&NodeUpdate { node, evicted })?;
+            http::respond_json(stream, "200 OK", &body).await
+        }
+        Ok(None) => respond_error(stream, OrchError::NodeNotFound(id.to_string())).await,
+        Err(e) => respond_error(stream, e).await,
+    }
+}
+
+/// Streams task and node changes as newline-delimited JSON `WatchEvent`s.
+///
+/// `GET /watch` starts with the current state of the cluster, `GET /watch?resourceVersion=N`
+/// resumes after version N and fails with `410 Gone` if the feed no longer holds it.
+async fn handle_watch(mut stream: TcpStream, request: &Request, store: SharedState) -> anyhow::Result<()> {
+    let since = match request.query_param("resourceVersion").map(|v| v.parse::<u64>()) {
+        Some(Ok(version)) => Some(version),
+        Some(Err(_)) => return http::respond_empty(&mut stream, "400 BAD REQUEST").await,
+        None => None,
+    };
+
+    let (backlog, mut changes) = match store.watch(since) {
+        Ok(subscription) => subscription,
+        Err(e) => return respond_error(&mut stream, e).await,
+    };
+
+    http::start_chunked(&mut stream, "200 OK", "application/x-ndjson").await?;
+
+    for event in backlog {
+        let mut line = serde_json::to_vec(&event)?;
+        line.push(b'\n');
+        http::write_chunk(&mut stream, &line).await?;
+    }
+
+    // a watcher that fell too far behind is cut off, it resumes from its last version
+    while let Ok(event) = changes.recv().await {
+        let mut line = serde_json::to_vec(&event)?;
+        line.push(b'\n');
+        http::write_chunk(&mut stream, &line).await?;
+    }
+
+    http::write_chunk(&mut stream, b"").await
+}
+
+/// The `labelSelector` query parameter, e.g. `app=web,env in (prod,staging)`.
+fn label_selector(request: &Request) -> Result<Option<Selector>, OrchError> {
+    request
+        .query_param("labelSelector")
+        .map(|s| s.parse::<Selector>())
+        .transpose()
+        .map_err(|e| OrchError::ValidationFailed(vec![FieldError::new("labelSelector", e)]))
+}
+
+/// Responds `400 Bad Request` with the error as JSON body.
+async fn respond_bad_request(stream: &mut TcpStream, err: OrchError) -> anyhow::Result<()> {
+    let body = serde_json::to_string(&err)?;
+    http::respond_json(stream, "400 BAD REQUEST", &body).await
+}
+
+fn is_dry_run(request: &Request) -> bool {
+    request.query_param("dryRun").is_some_and(|v| v == "true" || v.is_empty())
+}
+
+/// Responds with the task as it would be stored and the node the scheduler would currently pick.
+async fn respond_dry_run(stream: &mut TcpStream, task: Task, store: &SharedState) -> anyhow::Result<()> {
+    let others: Vec<Task> = store.list_tasks()?.into_iter().filter(|t| t.id != task.id).collect();
+    let placement = scheduler::place(&task, &store.list_nodes()?, &scheduler::allocations(&others));
+
+    let (node_id, unschedulable) = match placement {
+        Ok(node_id) => (Some(node_id), None),
+        Err(OrchError::SchedulerError(reason)) => (None, Some(reason)),
+        Err(e) => (None, Some(e.to_string())),
+    };
+    let body = serde_json::to_string(&DryRun { task, node_id, unschedulable })?;
+    http::respond_json(stream, "200 OK", &body).await
+}
+
+/// Responds with the task as JSON body and its resource version as `ETag`.
+async fn respond_task(stream: &mut TcpStream, status: &str, task: &Task) -> anyhow::Result<()> {
+    let body = serde_json::to_string(task)?;
+    http::respond_json_versioned(stream, status, task.resource_version, &body).await
+}
+
+/// Responds with the error as JSON body and a status code matching its kind.
+async fn respond_error(stream: &mut TcpStream, err: OrchError) -> anyhow::Result<()> {
+    let status = match &err {
+        OrchError::TaskNotFound(_)
+        | OrchError::NodeNotFound(_)
+        | OrchError::ServiceNotFound(_)
+        | OrchError::JobNotFound(_)
+        | OrchError::CronJobNotFound(_)
+        | OrchError::WorkflowNotFound(_) => "404 NOT FOUND",
+        OrchError::InvalidTransition { .. } | OrchError::VersionConflict { .. } | OrchError::Conflict(_) => "409 CONFLICT",
+        OrchError::ValidationFailed(_) => "422 UNPROCESSABLE ENTITY",
+        OrchError::NetworkError(_) => "502 BAD GATEWAY",
+        OrchError::ResourceVersionTooOld(_) => "410 GONE",
+        OrchError::DockerError(_) | OrchError::SchedulerError(_) | OrchError::TaskStoreError(_) => "500 INTERNAL SERVER ERROR",
+    };
+
+    let body = serde_json::to_string(&err)?;
+    http::respond_json(stream, status, &body).await
+}
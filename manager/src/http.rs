@@ -0,0 +1,99 @@
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use common::http::RequestHead;
+
+/// A parsed HTTP/1.1 request.
+///
+/// We only parse what the API needs: the request line, the query string, headers and a `Content-Length` body.
+pub struct Request {
+    pub head: RequestHead,
+    pub body: String,
+}
+
+impl Request {
+    /// Path split into its non-empty segments, e.g. `/tasks/1/logs` -> `["tasks", "1", "logs"]`
+    pub fn segments(&self) -> Vec<&str> {
+        self.head.segments()
+    }
+
+    pub fn query_param(&self, key: &str) -> Option<&str> {
+        self.head.query.get(key).map(|v| v.as_str())
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.head.header(name)
+    }
+
+    /// The resource version from an `If-Match` header, e.g. `If-Match: "42"`.
+    ///
+    /// `Ok(None)` if the header is absent, `Err` if it isn't a version we handed out.
+    pub fn if_match(&self) -> Result<Option<u64>, String> {
+        let Some(value) = self.header("if-match") else {
+            return Ok(None);
+        };
+
+        let version = value.trim().trim_start_matches("W/").trim_matches('"');
+        version
+            .parse::<u64>()
+            .map(Some)
+            .map_err(|_| format!("invalid If-Match header: {}", value))
+    }
+}
+
+/// Reads a single request from the stream. Returns `None` if the client closed the connection.
+pub async fn read_request(stream: &mut TcpStream) -> anyhow::Result<Option<Request>> {
+    let Some((head, body)) = common::http::read_request(stream).await? else {
+        return Ok(None);
+    };
+    Ok(Some(Request { head, body: String::from_utf8_lossy(&body).to_string() }))
+}
+
+/// Writes a complete response with a JSON body.
+pub async fn respond_json(stream: &mut TcpStream, status: &str, body: &str) -> anyhow::Result<()> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    Ok(())
+}
+
+/// Like `respond_json`, with the resource version as `ETag` so clients can send it back in `If-Match`.
+pub async fn respond_json_versioned(stream: &mut TcpStream, status: &str, version: u64, body: &str) -> anyhow::Result<()> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nETag: \"{}\"\r\nContent-Length: {}\r\n\r\n{}",
+        status,
+        version,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    Ok(())
+}
+
+/// Writes a response without body.
+pub async fn respond_empty(stream: &mut TcpStream, status: &str) -> anyhow::Result<()> {
+    let response = format!("HTTP/1.1 {}\r\nContent-Length: 0\r\n\r\n", status);
+    stream.write_all(response.as_bytes()).await?;
+    Ok(())
+}
+
+/// Starts a streamed response, the body follows as chunks written by `write_chunk`.
+pub async fn start_chunked(stream: &mut TcpStream, status: &str, content_type: &str) -> anyhow::Result<()> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nTransfer-Encoding: chunked\r\n\r\n",
+        status, content_type
+    );
+    stream.write_all(response.as_bytes()).await?;
+    Ok(())
+}
+
+/// Writes one chunk of a chunked response. An empty chunk ends the response.
+pub async fn write_chunk(stream: &mut TcpStream, data: &[u8]) -> anyhow::Result<()> {
+    stream.write_all(format!("{:x}\r\n", data.len()).as_bytes()).await?;
+    stream.write_all(data).await?;
+    stream.write_all(b"\r\n").await?;
+    Ok(())
+}
@@ -0,0 +1,217 @@
+use chrono::{DateTime, Utc};
+use common::{IndexResult, IndexState, Job, JobState, JobStatus, OrchError, OwnerKind, Task, TaskStatus};
+use crate::reconcile::{self, delete, retry_delay};
+use crate::store::SharedState;
+
+const CONTROLLER: &str = "Job controller";
+
+/// What the job controller does to move a job along.
+struct Plan<'a> {
+    /// Indexes to start a task for
+    create: Vec<u32>,
+    /// Tasks stopped because the job failed, or cleaned up after the TTL
+    delete: Vec<&'a Task>,
+    /// The job's status as of this round
+    status: JobStatus,
+}
+
+/// Works out the result of each index from the job's tasks and what to do next.
+///
+/// An index succeeds with its first task that completes. Failed tasks count against the
+/// backoff limit, once it's exceeded the job fails and its unfinished tasks are deleted.
+/// Otherwise new tasks are started for the open indexes, lowest first, so that at most
+/// `parallelism` run at once, retrying failed indexes after an exponential backoff.
+/// Finished jobs only wait for their TTL to delete their tasks.
+fn plan<'a>(job: &Job, owned: &[&'a Task], now: DateTime<Utc>) -> Plan<'a> {
+    let mut plan = Plan { create: Vec::new(), delete: Vec::new(), status: job.status.clone() };
+
+    if job.is_finished() {
+        if let (Some(ttl), Some(finished_at)) = (job.ttl_secs_after_finished, job.status.finished_at)
+            && !job.status.cleaned_up
+            && finished_at + chrono::Duration::seconds(ttl as i64) <= now
+        {
+            plan.delete = owned.to_vec();
+            plan.status.cleaned_up = true;
+        }
+        return plan;
+    }
+
+    let results: Vec<IndexResult> = (0..job.completions).map(|index| index_result(job, owned, index)).collect();
+    let status = &mut plan.status;
+    status.active = owned.iter().filter(|t| !t.status.is_terminal()).count() as u32;
+    status.succeeded = results.iter().filter(|r| r.state == IndexState::Succeeded).count() as u32;
+    status.failed = results.iter().map(|r| r.failures).sum();
+
+    if status.failed > job.backoff_limit {
+        status.state = JobState::Failed;
+        status.message = Some(format!("{} tasks failed, more than the backoff limit of {}", status.failed, job.backoff_limit));
+        status.finished_at = Some(now);
+        plan.delete = owned.iter().copied().filter(|t| !t.status.is_terminal()).collect();
+    } else if status.succeeded == job.completions {
+        status.state = JobState::Complete;
+        status.finished_at = Some(now);
+    } else {
+        let slots = (job.parallelism as usize).saturating_sub(status.active as usize);
+        plan.create = results
+            .iter()
+            .filter(|r| match r.state {
+                IndexState::Pending => true,
+                IndexState::Failed => r.finished_at.is_none_or(|at| at + retry_delay(r.failures) <= now),
+                IndexState::Running | IndexState::Succeeded => false,
+            })
+            .map(|r| r.index)
+            .take(slots)
+            .collect();
+    }
+
+    plan.status.results = results;
+    plan
+}
+
+/// The state of one index, taken from its latest task.
+fn index_result(job: &Job, owned: &[&Task], index: u32) -> IndexResult {
+    let tasks: Vec<&Task> = owned.iter().copied().filter(|t| job.index_of(t) == Some(index)).collect();
+    let failures = tasks.iter().filter(|t| t.status == TaskStatus::Failed).count() as u32;
+
+    let state = if tasks.iter().any(|t| t.status == TaskStatus::Complete) {
+        IndexState::Succeeded
+    } else if tasks.iter().any(|t| !t.status.is_terminal()) {
+        IndexState::Running
+    } else if failures > 0 {
+        IndexState::Failed
+    } else {
+        IndexState::Pending
+    };
+
+    // the successful task if there is one, the newest otherwise
+    let latest = tasks
+        .iter()
+        .find(|t| t.status == TaskStatus::Complete)
+        .or_else(|| tasks.iter().max_by_key(|t| t.created_at));
+    IndexResult {
+        index,
+        state,
+        failures,
+        task: latest.map(|t| t.name.clone()),
+        reason: latest.filter(|t| t.status.is_terminal()).and_then(|t| t.history.last()).and_then(|h| h.reason.clone()),
+        finished_at: latest.and_then(|t| t.finished_at),
+    }
+}
+
+/// Runs the tasks of every job to completion, and deletes the tasks of deleted jobs.
+pub async fn run_job_controller(store: SharedState) -> Result<(), OrchError> {
+    reconcile::run(CONTROLLER, store, reconcile).await
+}
+
+fn reconcile(store: &SharedState) -> Result<(), OrchError> {
+    let jobs = store.list_resources::<Job>()?;
+    let tasks = store.list_tasks()?;
+    reconcile::delete_orphans(CONTROLLER, store, &jobs, &tasks, OwnerKind::Job, Job::owns);
+
+    let now = Utc::now();
+    for job in &jobs {
+        let owned: Vec<&Task> = tasks.iter().filter(|t| job.owns(t)).collect();
+        let plan = plan(job, &owned, now);
+
+        for task in &plan.delete {
+            let reason = if task.status.is_terminal() { "the job's TTL expired" } else { "the job failed" };
+            delete(CONTROLLER, store, *task, reason);
+        }
+        for index in &plan.create {
+            match store.add_task(job.new_task(*index)) {
+                Ok(task) => println!("{}: job/{} created task {}", CONTROLLER, job.name, task.name),
+                Err(e) => eprintln!("{}: job/{}: {}", CONTROLLER, job.name, e),
+            }
+        }
+
+        if plan.status != job.status {
+            if plan.status.state != job.status.state {
+                println!("{}: job/{} is {:?}", CONTROLLER, job.name, plan.status.state);
+            }
+            // a changed job is looked at again next round
+            if let Err(e) = store.update_resource(&job.name, Some(job.resource_version), |j: &mut Job| j.status = plan.status) {
+                eprintln!("{}: {}", CONTROLLER, e);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::{JobSpec, TaskSpec};
+
+    fn job(completions: u32, parallelism: u32, backoff_limit: u32) -> Job {
+        JobSpec {
+            name: "batch".to_string(),
+            labels: Default::default(),
+            completions,
+            parallelism,
+            backoff_limit,
+            ttl_secs_after_finished: Some(60),
+            template: TaskSpec::from_task(&Task::new(String::new(), "busybox".to_string())),
+        }
+        .into_job()
+    }
+
+    fn task(job: &Job, index: u32, status: TaskStatus, finished_at: Option<DateTime<Utc>>) -> Task {
+        let mut task = job.new_task(index);
+        task.status = status;
+        task.finished_at = finished_at;
+        task
+    }
+
+    #[test]
+    fn test_plan_respects_parallelism() {
+        let job = job(4, 2, 6);
+        let now = Utc::now();
+        assert_eq!(plan(&job, &[], now).create, [0, 1]);
+
+        let done = task(&job, 0, TaskStatus::Complete, Some(now));
+        let running = task(&job, 1, TaskStatus::Running, None);
+        let plan = super::plan(&job, &[&done, &running], now);
+        assert_eq!(plan.create, [2]);
+        assert_eq!((plan.status.succeeded, plan.status.active), (1, 1));
+        assert_eq!(plan.status.results[0].state, IndexState::Succeeded);
+        assert_eq!(plan.status.results[1].state, IndexState::Running);
+    }
+
+    #[test]
+    fn test_plan_retries_after_backoff_and_fails() {
+        let job = job(2, 2, 1);
+        let now = Utc::now();
+        let failed = task(&job, 0, TaskStatus::Failed, Some(now - chrono::Duration::seconds(5)));
+        let running = task(&job, 1, TaskStatus::Running, None);
+
+        // the first retry waits 10s
+        let plan = plan(&job, &[&failed, &running], now);
+        assert!(plan.create.is_empty());
+        assert_eq!(plan.status.results[0].failures, 1);
+        let later = now + chrono::Duration::seconds(6);
+        assert_eq!(super::plan(&job, &[&failed, &running], later).create, [0]);
+
+        let again = task(&job, 0, TaskStatus::Failed, Some(now));
+        let plan = super::plan(&job, &[&failed, &again, &running], now);
+        assert_eq!(plan.status.state, JobState::Failed);
+        assert_eq!(plan.delete.iter().map(|t| t.id).collect::<Vec<_>>(), [running.id]);
+    }
+
+    #[test]
+    fn test_plan_cleans_up_after_ttl() {
+        let job = job(1, 1, 0);
+        let now = Utc::now();
+        let done = task(&job, 0, TaskStatus::Complete, Some(now));
+
+        let plan = plan(&job, &[&done], now);
+        assert_eq!(plan.status.state, JobState::Complete);
+
+        let mut finished = job.clone();
+        finished.status = plan.status;
+        assert!(super::plan(&finished, &[&done], now).delete.is_empty());
+        let plan = super::plan(&finished, &[&done], now + chrono::Duration::seconds(60));
+        assert_eq!(plan.delete.len(), 1);
+        assert!(plan.status.cleaned_up);
+    }
+}
@@ -2,9 +2,16 @@ use std::sync::Arc;
 use tokio::net::TcpListener;
 use crate::store::TaskStore;
 
+mod controller;
+mod cronjob_controller;
 mod handlers;
+mod http;
+mod job_controller;
+mod metrics;
+mod reconcile;
 mod store;
 mod scheduler;
+mod workflow_controller;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -23,6 +30,38 @@ async fn main() -> anyhow::Result<()> {
         }
     });
 
+    // keep the services at their replica count
+    let controller_store = Arc::clone(&shared_store);
+    tokio::spawn(async move {
+        if let Err(e) = controller::run_service_controller(controller_store).await {
+            println!("Service controller error: {}", e);
+        }
+    });
+
+    // run the jobs to completion
+    let job_store = Arc::clone(&shared_store);
+    tokio::spawn(async move {
+        if let Err(e) = job_controller::run_job_controller(job_store).await {
+            println!("Job controller error: {}", e);
+        }
+    });
+
+    // start the cron jobs' runs on schedule
+    let cronjob_store = Arc::clone(&shared_store);
+    tokio::spawn(async move {
+        if let Err(e) = cronjob_controller::run_cronjob_controller(cronjob_store).await {
+            println!("Cron job controller error: {}", e);
+        }
+    });
+
+    // release the workflows' steps as their dependencies complete
+    let workflow_store = Arc::clone(&shared_store);
+    tokio::spawn(async move {
+        if let Err(e) = workflow_controller::run_workflow_controller(workflow_store).await {
+            println!("Workflow controller error: {}", e);
+        }
+    });
+
     let listener = TcpListener::bind(addr).await?;
 
     loop {
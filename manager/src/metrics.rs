@@ -0,0 +1,132 @@
+use std::collections::{HashMap, VecDeque};
+use uuid::Uuid;
+use common::{UsageReport, UsageSample};
+
+/// Samples kept per node and task, about 5 minutes of heartbeats.
+const METRICS_HISTORY: usize = 60;
+
+/// Recent usage of a task, keyed by the node that measured it.
+struct TaskHistory {
+    node_id: String,
+    samples: VecDeque<UsageSample>,
+}
+
+/// Short ring buffers of the usage reported with the worker heartbeats.
+///
+/// Nothing here is versioned or watched, a manager restart starts over empty.
+#[derive(Default)]
+pub struct Metrics {
+    nodes: HashMap<String, VecDeque<UsageSample>>,
+    tasks: HashMap<Uuid, TaskHistory>,
+}
+
+impl Metrics {
+    /// Adds the report of a node, forgetting its tasks that no longer run there.
+    ///
+    /// A report the node already sent, e.g. because no newer sample was ready by its next
+    /// heartbeat, is ignored.
+    pub fn record(&mut self, node_id: &str, report: UsageReport) {
+        let samples = self.nodes.entry(node_id.to_string()).or_default();
+        if samples.back().is_some_and(|last| last.timestamp >= report.timestamp) {
+            return;
+        }
+        push(samples, UsageSample { timestamp: report.timestamp, usage: report.node });
+
+        self.tasks
+            .retain(|id, history| history.node_id != node_id || report.tasks.iter().any(|t| t.task_id == *id));
+        for task in report.tasks {
+            let history = self.tasks.entry(task.task_id).or_insert_with(|| TaskHistory {
+                node_id: node_id.to_string(),
+                samples: VecDeque::new(),
+            });
+            // moved to another node, its old samples are of another container
+            if history.node_id != node_id {
+                history.node_id = node_id.to_string();
+                history.samples.clear();
+            }
+            push(&mut history.samples, UsageSample { timestamp: report.timestamp, usage: task.usage });
+        }
+    }
+
+    /// Forgets the tasks `keep` returns false for, e.g. the ones deleted from the store.
+    pub fn retain_tasks(&mut self, mut keep: impl FnMut(&Uuid) -> bool) {
+        self.tasks.retain(|id, _| keep(id));
+    }
+
+    /// Samples of the node, oldest first.
+    pub fn node(&self, node_id: &str) -> Vec<UsageSample> {
+        self.nodes.get(node_id).map(|s| s.iter().cloned().collect()).unwrap_or_default()
+    }
+
+    /// The node the task was last measured on and its samples, oldest first.
+    pub fn task(&self, id: &Uuid) -> Option<(String, Vec<UsageSample>)> {
+        self.tasks
+            .get(id)
+            .map(|history| (history.node_id.clone(), history.samples.iter().cloned().collect()))
+    }
+}
+
+fn push(samples: &mut VecDeque<UsageSample>, sample: UsageSample) {
+    if samples.len() == METRICS_HISTORY {
+        samples.pop_front();
+    }
+    samples.push_back(sample);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::{TaskUsage, Usage};
+
+    /// A report taken `cpu` seconds into the test, so later reports have larger values.
+    fn report(cpu: f32, tasks: &[Uuid]) -> UsageReport {
+        UsageReport {
+            timestamp: chrono::DateTime::UNIX_EPOCH + chrono::Duration::milliseconds((cpu * 1000.0) as i64),
+            node: Usage { cpu, memory: 1024 },
+            tasks: tasks
+                .iter()
+                .map(|id| TaskUsage { task_id: *id, usage: Usage { cpu: cpu / 2.0, memory: 64 } })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_history_is_bounded() {
+        let mut metrics = Metrics::default();
+        for i in 0..METRICS_HISTORY + 5 {
+            metrics.record("worker-1", report(i as f32, &[]));
+        }
+
+        // the same report again
+        let last = metrics.nodes["worker-1"].back().unwrap().timestamp;
+        metrics.record("worker-1", UsageReport { timestamp: last, ..report(99.0, &[]) });
+
+        let samples = metrics.node("worker-1");
+        assert_eq!(samples.len(), METRICS_HISTORY);
+        assert_eq!(samples[0].usage.cpu, 5.0);
+        assert!(metrics.node("worker-2").is_empty());
+    }
+
+    #[test]
+    fn test_stopped_and_moved_tasks() {
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut metrics = Metrics::default();
+        metrics.record("worker-1", report(1.0, &[a, b]));
+        metrics.record("worker-1", report(2.0, &[a, b]));
+
+        // b stopped on worker-1 and started on worker-2
+        metrics.record("worker-2", report(3.0, &[b]));
+        metrics.record("worker-1", report(4.0, &[a]));
+
+        let (node, samples) = metrics.task(&a).unwrap();
+        assert_eq!((node.as_str(), samples.len()), ("worker-1", 3));
+        let (node, samples) = metrics.task(&b).unwrap();
+        assert_eq!((node.as_str(), samples.len()), ("worker-2", 1));
+
+        metrics.record("worker-1", report(5.0, &[]));
+        assert!(metrics.task(&a).is_none());
+
+        metrics.retain_tasks(|id| *id != b);
+        assert!(metrics.task(&b).is_none());
+    }
+}
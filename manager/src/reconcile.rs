@@ -0,0 +1,139 @@
+use std::time::Duration;
+use tokio::time::sleep;
+use common::{Job, OrchError, Owner, OwnerKind, Task};
+use crate::store::{NamedResource, SharedState};
+
+/// How often the controllers compare their resources with the tasks and jobs they own.
+pub const RECONCILE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Wait before retrying after the first failure, doubled with every further one.
+const RETRY_DELAY: chrono::Duration = chrono::Duration::seconds(10);
+
+/// Longest wait before retrying.
+const MAX_RETRY_DELAY: chrono::Duration = chrono::Duration::minutes(5);
+
+/// How long to wait after `failures` failures in a row before starting another task, e.g. for a
+/// job's index or a service's replica.
+pub fn retry_delay(failures: u32) -> chrono::Duration {
+    let doublings = failures.saturating_sub(1).min(10);
+    (RETRY_DELAY * 2i32.pow(doublings)).min(MAX_RETRY_DELAY)
+}
+
+/// Runs a round of `reconcile` every [`RECONCILE_INTERVAL`], logging failed rounds as `controller`.
+pub async fn run(
+    controller: &str,
+    store: SharedState,
+    reconcile: fn(&SharedState) -> Result<(), OrchError>,
+) -> Result<(), OrchError> {
+    println!("Starting {}...", controller.to_lowercase());
+
+    loop {
+        if let Err(e) = reconcile(&store) {
+            eprintln!("{}: {}", controller, e);
+        }
+        sleep(RECONCILE_INTERVAL).await;
+    }
+}
+
+/// What the controllers create for their resources and delete again: tasks, and the jobs of cron jobs.
+pub trait Owned {
+    fn owner(&self) -> Option<&Owner>;
+    /// How the logs call it, e.g. `task web-1`
+    fn describe(&self) -> String;
+    /// Deletes it unless it changed since it was listed.
+    fn delete(&self, store: &SharedState) -> Result<(), OrchError>;
+}
+
+impl Owned for Task {
+    fn owner(&self) -> Option<&Owner> {
+        self.owner.as_ref()
+    }
+
+    fn describe(&self) -> String {
+        format!("task {}", self.name)
+    }
+
+    fn delete(&self, store: &SharedState) -> Result<(), OrchError> {
+        store.delete_task(self.id, Some(self.resource_version)).map(|_| ())
+    }
+}
+
+impl Owned for Job {
+    fn owner(&self) -> Option<&Owner> {
+        self.owner.as_ref()
+    }
+
+    fn describe(&self) -> String {
+        format!("job {}", self.name)
+    }
+
+    fn delete(&self, store: &SharedState) -> Result<(), OrchError> {
+        store.delete_resource::<Job>(&self.name, Some(self.resource_version)).map(|_| ())
+    }
+}
+
+/// Deletes the task or job unless it changed since it was listed, then the next round decides again.
+pub fn delete(controller: &str, store: &SharedState, owned: &impl Owned, reason: &str) {
+    match owned.delete(store) {
+        Ok(()) => println!("{}: deleted {}, {}", controller, owned.describe(), reason),
+        Err(e) => eprintln!("{}: {}", controller, e),
+    }
+}
+
+/// Deletes what an owner of `kind` created once none of the `owners` owns it anymore.
+pub fn delete_orphans<R: NamedResource, T: Owned>(
+    controller: &str,
+    store: &SharedState,
+    owners: &[R],
+    owned: &[T],
+    kind: OwnerKind,
+    owns: impl Fn(&R, &T) -> bool,
+) {
+    let reason = format!("its {} was deleted", R::KIND);
+    for item in owned {
+        if item.owner().is_some_and(|owner| owner.kind == kind) && !owners.iter().any(|owner| owns(owner, item)) {
+            delete(controller, store, item, &reason);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::{JobSpec, TaskSpec};
+    use crate::store::TaskStore;
+
+    fn job(name: &str) -> Job {
+        JobSpec {
+            name: name.to_string(),
+            labels: Default::default(),
+            completions: 1,
+            parallelism: 1,
+            backoff_limit: 0,
+            ttl_secs_after_finished: None,
+            template: TaskSpec::from_task(&Task::new(String::new(), "busybox".to_string())),
+        }
+        .into_job()
+    }
+
+    #[test]
+    fn test_delete_orphans() {
+        let store = std::sync::Arc::new(TaskStore::new());
+        let kept = store.add_resource(job("kept")).unwrap();
+        let gone = store.add_resource(job("gone")).unwrap();
+        let kept_task = store.add_task(kept.new_task(0)).unwrap();
+        let orphan = store.add_task(gone.new_task(0)).unwrap();
+        let unowned = store.add_task(Task::new("web".to_string(), "nginx".to_string())).unwrap();
+        store.delete_resource::<Job>("gone", None).unwrap();
+
+        let jobs = store.list_resources::<Job>().unwrap();
+        delete_orphans("Job controller", &store, &jobs, &store.list_tasks().unwrap(), OwnerKind::Job, Job::owns);
+
+        let mut left: Vec<_> = store.list_tasks().unwrap().into_iter().map(|t| t.id).collect();
+        left.sort();
+        let mut expected = vec![kept_task.id, unowned.id];
+        expected.sort();
+        assert_eq!(left, expected);
+        assert!(store.get_task(orphan.id).unwrap().is_none());
+    }
+}
@@ -1,26 +1,565 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
 use std::time::Duration;
+use chrono::Utc;
 use tokio::time::sleep;
-use common::{OrchError, TaskStatus};
+use uuid::Uuid;
+use common::taint::tolerated;
+use common::task::DEFAULT_GRACE_PERIOD_SECS;
+use common::{Labels, Node, NodeStatus, OrchError, Selector, TaintEffect, Task, TaskStatus};
 use crate::store::SharedState;
 
+/// Resources claimed on a node by the tasks assigned to it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Allocation {
+    pub memory: i32,
+    pub cpu: f32,
+    /// Number of these tasks
+    pub tasks: usize,
+    /// Labels of each of these tasks, for the affinity rules
+    pub labels: Vec<Labels>,
+}
+
+/// Sums up the resources of all unfinished tasks per node.
+///
+/// A pending task counts on its nominated node, so the room freed by preemption is kept for it.
+pub fn allocations<'a>(tasks: impl IntoIterator<Item = &'a Task>) -> HashMap<String, Allocation> {
+    let mut allocations: HashMap<String, Allocation> = HashMap::new();
+    for task in tasks.into_iter().filter(|t| !t.status.is_terminal()) {
+        if let Some(node_id) = task.node_id.as_ref().or(task.nominated_node.as_ref()) {
+            allocations.entry(node_id.clone()).or_default().add(task);
+        }
+    }
+    allocations
+}
+
+impl Allocation {
+    fn add(&mut self, task: &Task) {
+        self.memory += task.memory;
+        self.cpu += task.cpu;
+        self.tasks += 1;
+        self.labels.push(task.labels.clone());
+    }
+
+    /// How many of the node's tasks match the selector.
+    fn count(&self, selector: &Selector) -> i64 {
+        self.labels.iter().filter(|labels| selector.matches(labels)).count() as i64
+    }
+}
+
+/// Picks the node the task should run on.
+///
+/// Filters out nodes that aren't ready, are cordoned, have taints the task doesn't tolerate,
+/// don't satisfy its node selector or required affinity rules, or don't have enough memory
+/// or CPU left. The remaining nodes are scored by the preferred affinity rules and
+/// `PreferNoSchedule` taints, ties go to the node with the most free memory. Fails with
+/// `SchedulerError` explaining why each node was rejected if the task fits nowhere.
+pub fn place(task: &Task, nodes: &[Node], allocations: &HashMap<String, Allocation>) -> Result<String, OrchError> {
+    let mut rejected = Vec::new();
+    let mut best: Option<(&Node, i64, i32)> = None;
+
+    for node in nodes {
+        let allocated = allocations.get(&node.id).cloned().unwrap_or_default();
+        let free_memory = node.total_memory - allocated.memory;
+        let free_cpu = node.total_cpu - allocated.cpu;
+
+        if node.status != NodeStatus::Ready {
+            rejected.push(format!("{} is not ready", node.id));
+        } else if node.unschedulable {
+            rejected.push(format!("{} is cordoned", node.id));
+        } else if let Some(reason) = unsatisfied_rule(task, node, &allocated, allocations) {
+            rejected.push(format!("{} {}", node.id, reason));
+        } else if free_memory < task.memory {
+            rejected.push(format!("{} has {}MB memory free, needs {}MB", node.id, free_memory, task.memory));
+        } else if free_cpu < task.cpu {
+            rejected.push(format!("{} has {} cpu free, needs {}", node.id, free_cpu, task.cpu));
+        } else {
+            let score = score(task, node, &allocated);
+            if best.is_none_or(|(_, top, most)| (score, free_memory) > (top, most)) {
+                best = Some((node, score, free_memory));
+            }
+        }
+    }
+
+    match best {
+        Some((node, _, _)) => Ok(node.id.clone()),
+        None if nodes.is_empty() => Err(OrchError::SchedulerError("no nodes are registered".to_string())),
+        None => Err(OrchError::SchedulerError(format!(
+            "0/{} nodes are available: {}",
+            nodes.len(),
+            rejected.join(", ")
+        ))),
+    }
+}
+
+/// Picks a node for a task that fits nowhere, and the tasks to preempt there to make room for it.
+///
+/// Only nodes the task could run on if they had the resources are looked at, judged by the tasks
+/// on them now. On each, unfinished tasks of a lower priority are picked, lowest priority and then
+/// newest first, until enough memory and CPU would be free. Picked tasks that turn out not to be
+/// needed, e.g. a small one picked before a large one, are spared again, most important first.
+/// Tasks already being rescheduled are never picked, their room is spoken for. The node where the
+/// most important of the picked tasks matters least wins, then the one with the fewest of them.
+/// `None` if no node can be freed up that way.
+pub fn preemption<'a>(
+    task: &Task,
+    nodes: &[Node],
+    tasks: &'a [Task],
+    allocations: &HashMap<String, Allocation>,
+) -> Option<(String, Vec<&'a Task>)> {
+    let cost = |victims: &[&Task]| (victims.iter().map(|t| t.priority_class).max(), victims.len());
+    let mut best: Option<(&Node, Vec<&Task>)> = None;
+
+    for node in nodes {
+        let allocated = allocations.get(&node.id).cloned().unwrap_or_default();
+        if node.status != NodeStatus::Ready
+            || node.unschedulable
+            || unsatisfied_rule(task, node, &allocated, allocations).is_some()
+        {
+            continue;
+        }
+
+        let mut candidates: Vec<&Task> = tasks
+            .iter()
+            .filter(|t| t.node_id.as_ref() == Some(&node.id) && !t.status.is_terminal())
+            .filter(|t| t.priority_class < task.priority_class && t.rescheduling.is_none())
+            .collect();
+        candidates.sort_by_key(|t| (t.priority_class, Reverse(t.created_at)));
+
+        let mut free_memory = node.total_memory - allocated.memory;
+        let mut free_cpu = node.total_cpu - allocated.cpu;
+        let mut victims = Vec::new();
+        for candidate in candidates {
+            if free_memory >= task.memory && free_cpu >= task.cpu {
+                break;
+            }
+            free_memory += candidate.memory;
+            free_cpu += candidate.cpu;
+            victims.push(candidate);
+        }
+        for i in (0..victims.len()).rev() {
+            let victim = victims[i];
+            if free_memory - victim.memory >= task.memory && free_cpu - victim.cpu >= task.cpu {
+                free_memory -= victim.memory;
+                free_cpu -= victim.cpu;
+                victims.remove(i);
+            }
+        }
+
+        if victims.is_empty() || free_memory < task.memory || free_cpu < task.cpu {
+            continue;
+        }
+        if best.as_ref().is_none_or(|(_, chosen)| cost(&victims) < cost(chosen)) {
+            best = Some((node, victims));
+        }
+    }
+
+    best.map(|(node, victims)| (node.id.clone(), victims))
+}
+
+/// Why a taint, the node selector or a required affinity rule keeps the task off the node, if one does.
+fn unsatisfied_rule(
+    task: &Task,
+    node: &Node,
+    allocated: &Allocation,
+    allocations: &HashMap<String, Allocation>,
+) -> Option<String> {
+    if let Some(taint) = node
+        .taints
+        .iter()
+        .find(|t| t.effect != TaintEffect::PreferNoSchedule && !tolerated(&task.tolerations, t))
+    {
+        return Some(format!("has the untolerated taint {}", taint));
+    }
+
+    if let Some((key, value)) = task.node_selector.iter().find(|(k, v)| node.labels.get(*k) != Some(*v)) {
+        return Some(format!("doesn't match the node selector {}={}", key, value));
+    }
+
+    let required = &task.affinity.node_affinity.required;
+    if !required.is_empty() && !required.iter().any(|s| s.matches(&node.labels)) {
+        return Some("doesn't match the required node affinity".to_string());
+    }
+
+    for selector in &task.affinity.task_affinity.required {
+        // the first of a group of tasks that want to run together has nobody to join
+        let first = selector.matches(&task.labels) && allocations.values().all(|a| a.count(selector) == 0);
+        if !first && allocated.count(selector) == 0 {
+            return Some(format!("runs no task matching '{}'", selector));
+        }
+    }
+
+    for selector in &task.affinity.task_anti_affinity.required {
+        if allocated.count(selector) > 0 {
+            return Some(format!("already runs a task matching '{}'", selector));
+        }
+    }
+
+    None
+}
+
+/// Adds up the weights of the preferred affinity rules, anti-affinity counts against the node.
+///
+/// Task rules count once per matching task, so replicas avoiding each other spread evenly.
+/// Each untolerated `PreferNoSchedule` taint costs as much as the heaviest preference.
+fn score(task: &Task, node: &Node, allocated: &Allocation) -> i64 {
+    let taint_score = -PREFER_NO_SCHEDULE_PENALTY
+        * node
+            .taints
+            .iter()
+            .filter(|t| t.effect == TaintEffect::PreferNoSchedule && !tolerated(&task.tolerations, t))
+            .count() as i64;
+
+    let affinity = &task.affinity;
+
+    let node_score: i64 = affinity
+        .node_affinity
+        .preferred
+        .iter()
+        .filter(|p| p.selector.matches(&node.labels))
+        .map(|p| p.weight as i64)
+        .sum();
+    let task_score: i64 = affinity
+        .task_affinity
+        .preferred
+        .iter()
+        .map(|p| p.weight as i64 * allocated.count(&p.selector))
+        .sum();
+    let anti_score: i64 = affinity
+        .task_anti_affinity
+        .preferred
+        .iter()
+        .map(|p| p.weight as i64 * allocated.count(&p.selector))
+        .sum();
+
+    taint_score + node_score + task_score - anti_score
+}
+
+/// Score lost for every `PreferNoSchedule` taint the task doesn't tolerate, the highest preference weight.
+const PREFER_NO_SCHEDULE_PENALTY: i64 = 100;
+
+/// How long a node may go without a heartbeat before it's `NotReady`, workers send one every 5s.
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(30);
+
 pub async fn run_scheduler_task(store: SharedState) -> Result<(), OrchError> {
     println!("Starting scheduler...");
+
+    // the last reason each task couldn't be placed, so we only log when it changes
+    let mut unschedulable: HashMap<Uuid, String> = HashMap::new();
+
     loop {
-        // find all tasks currently `pending`
-        let tasks = store.list_tasks()?;
-        let pending_tasks: Vec<_> = tasks
-            .into_iter()
+        for node in store.mark_unresponsive(Utc::now(), HEARTBEAT_TIMEOUT)? {
+            println!("node {} sent no heartbeat for {}s, it's not ready", node.id, HEARTBEAT_TIMEOUT.as_secs());
+        }
+        for task in store.release_overdue(Utc::now())? {
+            println!("task {} wasn't confirmed stopped within its grace period, rescheduling it", task.id);
+        }
+
+        let mut tasks = store.list_tasks()?;
+        let nodes = store.list_nodes()?;
+        let mut allocated = allocations(&tasks);
+
+        // most important first and oldest first among equals, so they get the free capacity
+        let mut pending_tasks: Vec<Task> = tasks
+            .iter()
             .filter(|t| t.status == TaskStatus::Pending)
+            .cloned()
             .collect();
+        pending_tasks.sort_by_key(|t| (Reverse(t.priority_class), t.created_at));
+        unschedulable.retain(|id, _| pending_tasks.iter().any(|t| &t.id == id));
 
-        // run a loop check each task and assign to a node
         for task in pending_tasks {
-            let target_node = "worker-1".to_owned();
+            // its reservation is what it gets to use, not something in its way
+            let own_allocated;
+            let allocated_for_task = match &task.nominated_node {
+                Some(node_id) => {
+                    // the tasks it preempted there are still stopping
+                    if tasks.iter().any(|t| t.node_id.as_ref() == Some(node_id) && t.rescheduling.is_some()) {
+                        continue;
+                    }
+                    own_allocated = allocations(tasks.iter().filter(|t| t.id != task.id));
+                    &own_allocated
+                }
+                None => &allocated,
+            };
+
+            let target_node = match place(&task, &nodes, allocated_for_task) {
+                Ok(node_id) => node_id,
+                Err(e) => {
+                    if let Some((node_id, victims)) = preemption(&task, &nodes, &tasks, allocated_for_task) {
+                        let victims: Vec<(Uuid, u64)> = victims.iter().map(|t| (t.id, t.resource_version)).collect();
+                        // all or nothing against the versions we listed, like `assign_node`
+                        let grace_period = Duration::from_secs(DEFAULT_GRACE_PERIOD_SECS);
+                        match store.preempt(task.id, &node_id, task.resource_version, &victims, grace_period) {
+                            Ok(preempted) => {
+                                for victim in &preempted {
+                                    println!("task {} is preempted by task {} on {}", victim.id, task.id, node_id);
+                                }
+                                println!("task {} is nominated to {}, waiting for the preempted tasks to stop", task.id, node_id);
+                                unschedulable.remove(&task.id);
+                                // the node is held for this task, the tasks after it look elsewhere
+                                for listed in tasks.iter_mut() {
+                                    if listed.id == task.id {
+                                        listed.nominated_node = Some(node_id.clone());
+                                    } else if let Some(victim) = preempted.iter().find(|p| p.id == listed.id) {
+                                        *listed = victim.clone();
+                                    }
+                                }
+                                allocated = allocations(&tasks);
+                            }
+                            Err(e) => eprintln!("Scheduler: {}", e),
+                        }
+                        continue;
+                    }
+
+                    // the room it was waiting for went elsewhere, e.g. the node was cordoned
+                    if task.nominated_node.is_some() {
+                        match store.clear_nomination(task.id, task.resource_version) {
+                            Ok(()) => {
+                                if let Some(listed) = tasks.iter_mut().find(|t| t.id == task.id) {
+                                    listed.nominated_node = None;
+                                }
+                                allocated = allocations(&tasks);
+                            }
+                            Err(e) => eprintln!("Scheduler: {}", e),
+                        }
+                    }
 
-            store.assign_node(task.id, target_node.clone())?;
-            println!("task {} is assigned to {}", task.id, target_node);
+                    let reason = match e {
+                        OrchError::SchedulerError(reason) => reason,
+                        other => other.to_string(),
+                    };
+                    if unschedulable.get(&task.id) != Some(&reason) {
+                        eprintln!("Scheduler: task {} is unschedulable: {}", task.id, reason);
+                        unschedulable.insert(task.id, reason);
+                    }
+                    continue;
+                }
+            };
+
+            // compare-and-set against the version we listed, if the task moved on
+            // (or another scheduler got to it first) we just skip it
+            match store.assign_node(task.id, target_node.clone(), task.resource_version) {
+                Ok(()) => {
+                    println!("task {} is assigned to {}", task.id, target_node);
+                    unschedulable.remove(&task.id);
+                    if let Some(listed) = tasks.iter_mut().find(|t| t.id == task.id) {
+                        listed.node_id = Some(target_node.clone());
+                        listed.nominated_node = None;
+                    }
+                    allocated = allocations(&tasks);
+                }
+                Err(e) => eprintln!("Scheduler: {}", e),
+            }
         }
 
         sleep(Duration::from_secs(5)).await;
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::PriorityClass;
+
+    fn ready_node(id: &str, memory: i32, cpu: f32) -> Node {
+        let mut node = Node::new(id.to_string(), memory, cpu);
+        node.status = NodeStatus::Ready;
+        node
+    }
+
+    #[test]
+    fn test_place_prefers_most_free_memory() {
+        let nodes = vec![ready_node("small", 1024, 2.0), ready_node("big", 4096, 2.0)];
+        let mut running = Task::new("db".to_string(), "postgres".to_string());
+        running.memory = 3584;
+        running.node_id = Some("big".to_string());
+
+        let task = Task::new("web".to_string(), "nginx".to_string());
+        assert_eq!(place(&task, &nodes, &HashMap::new()).unwrap(), "big");
+        let allocated = allocations(&[running]);
+        assert_eq!((allocated["big"].memory, allocated["big"].tasks), (3584, 1));
+        assert_eq!(place(&task, &nodes, &allocated).unwrap(), "small");
+    }
+
+    fn labeled_node(id: &str, labels: &[(&str, &str)]) -> Node {
+        let mut node = ready_node(id, 4096, 4.0);
+        node.labels = labels.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        node
+    }
+
+    fn replica(name: &str, app: &str, node_id: Option<&str>) -> Task {
+        let mut task = Task::new(name.to_string(), "nginx".to_string());
+        task.labels.insert("app".to_string(), app.to_string());
+        task.node_id = node_id.map(str::to_string);
+        task
+    }
+
+    #[test]
+    fn test_place_node_selector_and_affinity() {
+        let nodes = vec![
+            labeled_node("hdd", &[("disk", "hdd"), ("zone", "eu-1")]),
+            labeled_node("ssd", &[("disk", "ssd"), ("zone", "eu-2")]),
+        ];
+
+        let mut task = Task::new("db".to_string(), "postgres".to_string());
+        task.node_selector.insert("disk".to_string(), "ssd".to_string());
+        assert_eq!(place(&task, &nodes, &HashMap::new()).unwrap(), "ssd");
+
+        task.affinity.node_affinity.required = vec!["zone in (eu-1)".parse().unwrap()];
+        let Err(OrchError::SchedulerError(reason)) = place(&task, &nodes, &HashMap::new()) else {
+            panic!("task must not fit");
+        };
+        assert_eq!(
+            reason,
+            "0/2 nodes are available: hdd doesn't match the node selector disk=ssd, \
+             ssd doesn't match the required node affinity"
+        );
+
+        // a preference outweighs free memory
+        let mut task = Task::new("cache".to_string(), "redis".to_string());
+        task.affinity.node_affinity.preferred = vec![common::Preference { weight: 10, selector: "zone=eu-1".parse().unwrap() }];
+        let busy = replica("web", "web", Some("hdd"));
+        assert_eq!(place(&task, &nodes, &allocations(&[busy])).unwrap(), "hdd");
+    }
+
+    #[test]
+    fn test_place_task_affinity() {
+        let nodes = vec![labeled_node("a", &[]), labeled_node("b", &[])];
+        let selector: Selector = "app=web".parse().unwrap();
+
+        // replicas that prefer to avoid each other spread evenly
+        let mut placed: Vec<Task> = Vec::new();
+        for i in 0..4 {
+            let mut task = replica(&format!("web-{}", i), "web", None);
+            task.affinity.task_anti_affinity.preferred = vec![common::Preference { weight: 100, selector: selector.clone() }];
+            task.node_id = Some(place(&task, &nodes, &allocations(&placed)).unwrap());
+            placed.push(task);
+        }
+        assert_eq!(placed.iter().filter(|t| t.node_id.as_deref() == Some("a")).count(), 2);
+
+        // required anti-affinity allows one replica per node
+        let mut task = replica("web-4", "web", None);
+        task.affinity.task_anti_affinity.required = vec![selector.clone()];
+        assert!(place(&task, &nodes, &allocations(&placed[..1])).is_ok());
+        assert!(place(&task, &nodes, &allocations(&placed[..2])).is_err());
+
+        // the first task of a co-located group can go anywhere, the others follow it
+        let mut sidecar = replica("log-0", "log", None);
+        sidecar.affinity.task_affinity.required = vec!["app=log".parse().unwrap()];
+        assert!(place(&sidecar, &nodes, &HashMap::new()).is_ok());
+        let first = replica("log-1", "log", Some("b"));
+        assert_eq!(place(&sidecar, &nodes, &allocations(&[first])).unwrap(), "b");
+    }
+
+    #[test]
+    fn test_place_respects_taints() {
+        let mut db = labeled_node("db", &[]);
+        db.taints = vec!["dedicated=db:NoSchedule".parse().unwrap()];
+        let mut build = labeled_node("build", &[]);
+        build.taints = vec!["build:PreferNoSchedule".parse().unwrap()];
+        let nodes = vec![db, build];
+
+        // the only node without a hard taint is avoided, but still used
+        let task = Task::new("web".to_string(), "nginx".to_string());
+        assert_eq!(place(&task, &nodes, &HashMap::new()).unwrap(), "build");
+        let Err(OrchError::SchedulerError(reason)) = place(&task, &nodes[..1], &HashMap::new()) else {
+            panic!("task must not fit");
+        };
+        assert_eq!(reason, "0/1 nodes are available: db has the untolerated taint dedicated=db:NoSchedule");
+
+        let mut task = Task::new("postgres".to_string(), "postgres".to_string());
+        task.tolerations.push(common::Toleration {
+            key: Some("dedicated".to_string()),
+            operator: common::TolerationOperator::Equal,
+            value: "db".to_string(),
+            effect: Some(TaintEffect::NoSchedule),
+        });
+        assert_eq!(place(&task, &nodes, &HashMap::new()).unwrap(), "db");
+    }
+
+    #[test]
+    fn test_place_explains_rejections() {
+        let mut nodes = vec![ready_node("worker-1", 512, 0.25)];
+        nodes.push(Node::new("worker-2".to_string(), 4096, 4.0));
+        nodes.push(ready_node("worker-3", 4096, 4.0));
+        nodes[2].unschedulable = true;
+
+        let task = Task::new("web".to_string(), "nginx".to_string());
+        let Err(OrchError::SchedulerError(reason)) = place(&task, &nodes, &HashMap::new()) else {
+            panic!("task must not fit");
+        };
+        assert_eq!(
+            reason,
+            "0/3 nodes are available: worker-1 has 0.25 cpu free, needs 0.5, worker-2 is not ready, worker-3 is cordoned"
+        );
+    }
+
+    fn running(name: &str, node_id: &str, memory: i32, priority_class: PriorityClass) -> Task {
+        let mut task = replica(name, name, Some(node_id));
+        task.memory = memory;
+        task.priority_class = priority_class;
+        task.status = TaskStatus::Running;
+        task
+    }
+
+    #[test]
+    fn test_preemption_picks_fewest_least_important_victims() {
+        let nodes = vec![ready_node("a", 1024, 4.0), ready_node("b", 1024, 4.0)];
+        let mut batch = running("batch-0", "a", 512, PriorityClass::Low);
+        batch.created_at -= chrono::Duration::minutes(1);
+        let tasks = vec![
+            batch,
+            running("batch-1", "a", 512, PriorityClass::Low),
+            running("web", "b", 512, PriorityClass::Normal),
+            running("cache", "b", 512, PriorityClass::Low),
+        ];
+        let allocated = allocations(&tasks);
+
+        let mut api = Task::new("api".to_string(), "nginx".to_string());
+        api.memory = 512;
+        api.priority_class = PriorityClass::High;
+        assert!(place(&api, &nodes, &allocated).is_err());
+
+        // one low task on either node, the newest one on the node that comes first
+        let (node, victims) = preemption(&api, &nodes, &tasks, &allocated).unwrap();
+        assert_eq!((node.as_str(), victims[0].name.as_str(), victims.len()), ("a", "batch-1", 1));
+
+        // b would have to give up its normal task too
+        api.memory = 1024;
+        let (node, victims) = preemption(&api, &nodes, &tasks, &allocated).unwrap();
+        assert_eq!((node.as_str(), victims.len()), ("a", 2));
+
+        // nothing of lower priority to make room with
+        api.priority_class = PriorityClass::Low;
+        assert!(preemption(&api, &nodes, &tasks, &allocated).is_none());
+    }
+
+    #[test]
+    fn test_preemption_spares_victims_it_does_not_need() {
+        let nodes = vec![ready_node("a", 1024, 4.0)];
+        let mut large = running("large", "a", 768, PriorityClass::Low);
+        large.created_at -= chrono::Duration::minutes(1);
+        let mut tasks = vec![large, running("small", "a", 256, PriorityClass::Low)];
+        let mut api = Task::new("api".to_string(), "nginx".to_string());
+        api.memory = 768;
+        api.priority_class = PriorityClass::High;
+
+        // the newer small task is picked first, but the large one alone makes enough room
+        let (_, victims) = preemption(&api, &nodes, &tasks, &allocations(&tasks)).unwrap();
+        assert_eq!(victims.iter().map(|t| t.name.as_str()).collect::<Vec<_>>(), ["large"]);
+
+        // while the large task stops, its room is held for api and can't be preempted again
+        let deadline = Utc::now() + chrono::Duration::seconds(30);
+        tasks[0].rescheduling = Some(common::Rescheduling { reason: "Preempted by task api on node a".to_string(), deadline });
+        api.nominated_node = Some("a".to_string());
+        tasks.push(api);
+        let allocated = allocations(&tasks);
+        assert_eq!((allocated["a"].memory, allocated["a"].tasks), (1792, 3));
+
+        let mut cache = Task::new("cache".to_string(), "redis".to_string());
+        cache.memory = 768;
+        cache.priority_class = PriorityClass::High;
+        assert!(preemption(&cache, &nodes, &tasks, &allocated).is_none());
+    }
+}
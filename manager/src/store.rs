@@ -1,28 +1,407 @@
-use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use tokio::sync::broadcast;
 use uuid::Uuid;
-use common::{OrchError, Task, TaskStatus};
+use common::labels::Operator;
+use common::task::{DEFAULT_GRACE_PERIOD_SECS, EVICTED};
+use common::taint::tolerated;
+use common::{
+    CronJob, EventType, Job, Labels, Node, NodeStatus, OrchError, Rescheduling, Selector, Service, Taint, TaintEffect, Task, TaskHealth, TaskSpec, TaskStatus, WatchEvent,
+    UsageReport, UsageSample, WatchObject, Workflow,
+};
+use crate::metrics::Metrics;
 
 pub type SharedState = Arc<TaskStore>;
 
+/// How many changes the feed keeps around for watchers resuming from an older version.
+const WATCH_HISTORY: usize = 1000;
+
+/// The store's change feed.
+///
+/// Handing out versions and appending events happens under one lock, so the feed is
+/// ordered by resource version and watchers never see a gap.
+struct ChangeFeed {
+    /// Store-wide counter handing out resource versions, so versions only ever grow
+    revision: u64,
+    history: VecDeque<WatchEvent>,
+    sender: broadcast::Sender<WatchEvent>,
+}
+
+/// The stored tasks, plus the indexes kept in sync with them.
+///
+/// Names, labels and ids only change through `insert`, `rename`, `relabel` and `remove`,
+/// so the indexes can't drift.
+#[derive(Default)]
+pub struct TaskTable {
+    tasks: HashMap<Uuid, Task>,
+    /// Task names are unique across the cluster, name -> id
+    names: HashMap<String, Uuid>,
+    /// label key -> label value -> ids of the tasks carrying that label
+    labels: HashMap<String, HashMap<String, HashSet<Uuid>>>,
+}
+
+impl TaskTable {
+    pub fn get(&self, id: &Uuid) -> Option<&Task> {
+        self.tasks.get(id)
+    }
+
+    /// The task for changes that keep its name and labels, see `rename` and `relabel` otherwise.
+    pub fn get_mut(&mut self, id: &Uuid) -> Option<&mut Task> {
+        self.tasks.get_mut(id)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Task> {
+        self.tasks.values()
+    }
+
+    pub fn get_by_name(&self, name: &str) -> Option<&Task> {
+        self.names.get(name).and_then(|id| self.tasks.get(id))
+    }
+
+    /// Fails with `Conflict` if a task other than `id` already uses `name`.
+    pub fn check_name(&self, name: &str, id: Uuid) -> Result<(), OrchError> {
+        match self.names.get(name) {
+            Some(owner) if *owner != id => Err(OrchError::Conflict(format!(
+                "Task name '{}' is already used by task {}",
+                name, owner
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn insert(&mut self, task: Task) -> Result<(), OrchError> {
+        self.check_name(&task.name, task.id)?;
+        self.names.insert(task.name.clone(), task.id);
+        self.index_labels(task.id, &task.labels);
+        self.tasks.insert(task.id, task);
+        Ok(())
+    }
+
+    pub fn rename(&mut self, id: Uuid, name: &str) -> Result<(), OrchError> {
+        self.check_name(name, id)?;
+        if let Some(task) = self.tasks.get_mut(&id)
+            && task.name != name
+        {
+            self.names.remove(&task.name);
+            self.names.insert(name.to_string(), id);
+            task.name = name.to_string();
+        }
+        Ok(())
+    }
+
+    pub fn relabel(&mut self, id: Uuid, labels: Labels) {
+        let Some(previous) = self.tasks.get_mut(&id).map(|task| std::mem::replace(&mut task.labels, labels.clone())) else {
+            return;
+        };
+        self.unindex_labels(id, &previous);
+        self.index_labels(id, &labels);
+    }
+
+    pub fn remove(&mut self, id: &Uuid) -> Option<Task> {
+        let task = self.tasks.remove(id)?;
+        self.names.remove(&task.name);
+        self.unindex_labels(task.id, &task.labels);
+        Some(task)
+    }
+
+    /// The tasks matching the selector.
+    ///
+    /// `=`, `in` and exists requirements narrow the candidates down through the label index,
+    /// only the remaining tasks are checked against the full selector.
+    pub fn select(&self, selector: &Selector) -> Vec<&Task> {
+        let mut candidates: Option<HashSet<Uuid>> = None;
+
+        for requirement in &selector.requirements {
+            let Some(values) = self.labels.get(&requirement.key) else {
+                match requirement.operator {
+                    Operator::Equals(_) | Operator::In(_) | Operator::Exists => return Vec::new(),
+                    _ => continue,
+                }
+            };
+
+            let ids: HashSet<Uuid> = match &requirement.operator {
+                Operator::Equals(value) => values.get(value).cloned().unwrap_or_default(),
+                Operator::In(allowed) => allowed.iter().filter_map(|v| values.get(v)).flatten().copied().collect(),
+                Operator::Exists => values.values().flatten().copied().collect(),
+                _ => continue,
+            };
+            candidates = Some(match candidates {
+                Some(previous) => previous.intersection(&ids).copied().collect(),
+                None => ids,
+            });
+        }
+
+        match candidates {
+            Some(ids) => ids
+                .iter()
+                .filter_map(|id| self.tasks.get(id))
+                .filter(|t| selector.matches(&t.labels))
+                .collect(),
+            None => self.tasks.values().filter(|t| selector.matches(&t.labels)).collect(),
+        }
+    }
+
+    fn index_labels(&mut self, id: Uuid, labels: &Labels) {
+        for (key, value) in labels {
+            self.labels.entry(key.clone()).or_default().entry(value.clone()).or_default().insert(id);
+        }
+    }
+
+    fn unindex_labels(&mut self, id: Uuid, labels: &Labels) {
+        for (key, value) in labels {
+            let Some(values) = self.labels.get_mut(key) else {
+                continue;
+            };
+            if let Some(ids) = values.get_mut(value) {
+                ids.remove(&id);
+                if ids.is_empty() {
+                    values.remove(value);
+                }
+            }
+            if values.is_empty() {
+                self.labels.remove(key);
+            }
+        }
+    }
+}
+
+/// A resource the store keeps by its unique name: services, jobs, cron jobs and workflows.
+pub trait NamedResource: Clone {
+    /// How the resource is called in messages, e.g. "cron job"
+    const KIND: &'static str;
+
+    fn name(&self) -> &str;
+    fn labels(&self) -> &Labels;
+    fn resource_version(&self) -> u64;
+    fn set_resource_version(&mut self, version: u64);
+    /// The resource as published on the change feed
+    fn watch_object(&self) -> WatchObject;
+    /// The error for a name no resource of this kind has
+    fn not_found(name: &str) -> OrchError;
+    /// Where the store keeps the resources of this kind
+    fn table(store: &TaskStore) -> &ResourceTable<Self>;
+
+    /// Fails with `Conflict` if the caller's view of the resource is outdated.
+    fn check_version(&self, expected_version: Option<u64>) -> Result<(), OrchError> {
+        match expected_version {
+            Some(expected) if expected != self.resource_version() => Err(OrchError::Conflict(format!(
+                "{} {} was modified concurrently: expected version {}, found {}",
+                Self::KIND,
+                self.name(),
+                expected,
+                self.resource_version()
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The resources of one kind, keyed by name.
+pub struct ResourceTable<R>(RwLock<HashMap<String, R>>);
+
+impl<R> Default for ResourceTable<R> {
+    fn default() -> Self {
+        Self(RwLock::new(HashMap::new()))
+    }
+}
+
+impl<R: NamedResource> ResourceTable<R> {
+    pub fn read(&self) -> Result<RwLockReadGuard<'_, HashMap<String, R>>, OrchError> {
+        self.0.read().map_err(|e| OrchError::TaskStoreError(format!("Failed to unlock the {}: {}", R::KIND, e)))
+    }
+
+    pub fn write(&self) -> Result<RwLockWriteGuard<'_, HashMap<String, R>>, OrchError> {
+        self.0.write().map_err(|e| OrchError::TaskStoreError(format!("Failed to unlock the {}: {}", R::KIND, e)))
+    }
+}
+
+macro_rules! named_resource {
+    ($type:ty, $kind:literal, $variant:ident, $not_found:ident, $table:ident) => {
+        impl NamedResource for $type {
+            const KIND: &'static str = $kind;
+
+            fn name(&self) -> &str {
+                &self.name
+            }
+
+            fn labels(&self) -> &Labels {
+                &self.labels
+            }
+
+            fn resource_version(&self) -> u64 {
+                self.resource_version
+            }
+
+            fn set_resource_version(&mut self, version: u64) {
+                self.resource_version = version;
+            }
+
+            fn watch_object(&self) -> WatchObject {
+                WatchObject::$variant(Box::new(self.clone()))
+            }
+
+            fn not_found(name: &str) -> OrchError {
+                OrchError::$not_found(name.to_string())
+            }
+
+            fn table(store: &TaskStore) -> &ResourceTable<Self> {
+                &store.$table
+            }
+        }
+    };
+}
+
+named_resource!(Service, "service", Service, ServiceNotFound, services);
+named_resource!(Job, "job", Job, JobNotFound, jobs);
+named_resource!(CronJob, "cron job", CronJob, CronJobNotFound, cronjobs);
+named_resource!(Workflow, "workflow", Workflow, WorkflowNotFound, workflows);
+
 pub struct TaskStore {
-    pub tasks: RwLock<HashMap<String, Task>>,
+    pub tasks: RwLock<TaskTable>,
+    /// Worker nodes that registered with the manager, keyed by node id
+    pub nodes: RwLock<HashMap<String, Node>>,
+    /// Replicated services
+    pub services: ResourceTable<Service>,
+    /// Batch jobs
+    pub jobs: ResourceTable<Job>,
+    /// Cron jobs
+    pub cronjobs: ResourceTable<CronJob>,
+    /// Workflows
+    pub workflows: ResourceTable<Workflow>,
+    feed: Mutex<ChangeFeed>,
+    /// Usage reported with the heartbeats, kept apart from the versioned state
+    metrics: Mutex<Metrics>,
 }
 
 impl TaskStore {
     pub fn new() -> Self {
+       let (sender, _) = broadcast::channel(WATCH_HISTORY);
        Self {
-           tasks: RwLock::new(HashMap::new()),
+           tasks: RwLock::new(TaskTable::default()),
+           nodes: RwLock::new(HashMap::new()),
+           services: ResourceTable::default(),
+           jobs: ResourceTable::default(),
+           cronjobs: ResourceTable::default(),
+           workflows: ResourceTable::default(),
+           feed: Mutex::new(ChangeFeed {
+               revision: 0,
+               history: VecDeque::new(),
+               sender,
+           }),
+           metrics: Mutex::new(Metrics::default()),
        }
     }
 
-    pub fn add_task(&self, task: Task) -> Result<(), OrchError> {
+    /// Hands out the next resource version and publishes the change built by `object`.
+    ///
+    /// Callers hold the write lock of the changed collection, so the feed lock is always taken last.
+    fn record(&self, event_type: EventType, object: impl FnOnce(u64) -> WatchObject) -> Result<u64, OrchError> {
+        let mut feed = self.feed.lock()
+            .map_err(|e| OrchError::TaskStoreError(format!("Failed to unlock the change feed: {}", e)))?;
+
+        feed.revision += 1;
+        let event = WatchEvent {
+            event_type,
+            resource_version: feed.revision,
+            object: object(feed.revision),
+        };
+
+        if feed.history.len() == WATCH_HISTORY {
+            feed.history.pop_front();
+        }
+        feed.history.push_back(event.clone());
+        // nobody watching is fine
+        let _ = feed.sender.send(event);
+
+        Ok(feed.revision)
+    }
+
+    /// Subscribes to the change feed.
+    ///
+    /// Without `since`, the returned backlog starts with an `Added` event for every current task,
+    /// node, service, job, cron job and workflow. With `since`, it holds all changes after that version, or fails with
+    /// `ResourceVersionTooOld` if they were already dropped from the history, or if `since` is ahead of
+    /// the feed, e.g. a version from before the manager restarted.
+    pub fn watch(&self, since: Option<u64>) -> Result<(Vec<WatchEvent>, broadcast::Receiver<WatchEvent>), OrchError> {
+        let task_read = self.tasks.read()
+            .map_err(|e| OrchError::TaskStoreError(format!("Failed to unlock the task: {}", e)))?;
+        let node_read = self.nodes.read()
+            .map_err(|e| OrchError::TaskStoreError(format!("Failed to unlock the node: {}", e)))?;
+        let service_read = self.services.read()?;
+        let job_read = self.jobs.read()?;
+        let cronjob_read = self.cronjobs.read()?;
+        let workflow_read = self.workflows.read()?;
+        let feed = self.feed.lock()
+            .map_err(|e| OrchError::TaskStoreError(format!("Failed to unlock the change feed: {}", e)))?;
+
+        let backlog = match since {
+            None => {
+                let mut events: Vec<WatchEvent> = task_read
+                    .values()
+                    .map(|t| (t.resource_version, WatchObject::Task(Box::new(t.clone()))))
+                    .chain(node_read.values().map(|n| (n.resource_version, WatchObject::Node(Box::new(n.clone())))))
+                    .chain(service_read.values().map(|s| (s.resource_version, s.watch_object())))
+                    .chain(job_read.values().map(|j| (j.resource_version, j.watch_object())))
+                    .chain(cronjob_read.values().map(|c| (c.resource_version, c.watch_object())))
+                    .chain(workflow_read.values().map(|w| (w.resource_version, w.watch_object())))
+                    .map(|(resource_version, object)| WatchEvent {
+                        event_type: EventType::Added,
+                        resource_version,
+                        object,
+                    })
+                    .collect();
+                events.sort_by_key(|e| e.resource_version);
+                events
+            }
+            Some(since) => {
+                if since > feed.revision {
+                    return Err(OrchError::ResourceVersionTooOld(since));
+                }
+                if let Some(oldest) = feed.history.front()
+                    && since + 1 < oldest.resource_version
+                {
+                    return Err(OrchError::ResourceVersionTooOld(since));
+                }
+                feed.history
+                    .iter()
+                    .filter(|e| e.resource_version > since)
+                    .cloned()
+                    .collect()
+            }
+        };
+
+        Ok((backlog, feed.sender.subscribe()))
+    }
+
+    /// Fails with `VersionConflict` if the caller's view of the task is outdated.
+    fn check_version(task: &Task, expected_version: Option<u64>) -> Result<(), OrchError> {
+        match expected_version {
+            Some(expected) if expected != task.resource_version => Err(OrchError::VersionConflict {
+                task_id: task.id.to_string(),
+                expected,
+                actual: task.resource_version,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Stores a new task, returns it with its first resource version.
+    ///
+    /// Fails with `Conflict` if another task already has the same name.
+    pub fn add_task(&self, mut task: Task) -> Result<Task, OrchError> {
         let mut task_write = self.tasks.write()
             .map_err(|e| OrchError::TaskStoreError(format!("Failed to unlock the task: {}", e)))?;
 
-        task_write.insert(task.id.to_string(), task);
+        task_write.check_name(&task.name, task.id)?;
+        self.record(EventType::Added, |version| {
+            task.resource_version = version;
+            WatchObject::Task(Box::new(task.clone()))
+        })?;
+        task_write.insert(task.clone())?;
 
-        Ok(())
+        Ok(task)
     }
 
     pub fn list_tasks(&self) -> Result<Vec<Task>, OrchError> {
@@ -37,41 +416,989 @@ impl TaskStore {
         let task_read = self.tasks.read()
             .map_err(|e| OrchError::TaskStoreError(format!("Failed to unlock the task: {}", e)))?;
 
-        let task = task_read.get(&id.to_string()).cloned();
+        let task = task_read.get(&id).cloned();
 
         Ok(task)
     }
 
-    pub fn assign_node(&self, id: Uuid, node_id: String) -> Result<(), OrchError> {
+    /// The tasks whose labels match the selector, served from the label index.
+    pub fn select_tasks(&self, selector: &Selector) -> Result<Vec<Task>, OrchError> {
+        let task_read = self.tasks.read()
+            .map_err(|e| OrchError::TaskStoreError(format!("Failed to unlock the task: {}", e)))?;
+
+        Ok(task_read.select(selector).into_iter().cloned().collect())
+    }
+
+    pub fn get_task_by_name(&self, name: &str) -> Result<Option<Task>, OrchError> {
+        let task_read = self.tasks.read()
+            .map_err(|e| OrchError::TaskStoreError(format!("Failed to unlock the task: {}", e)))?;
+
+        Ok(task_read.get_by_name(name).cloned())
+    }
+
+    /// Assigns the task to a node, but only if it's still at `expected_version`.
+    ///
+    /// This compare-and-set makes sure two scheduling rounds can't both assign the same task.
+    pub fn assign_node(&self, id: Uuid, node_id: String, expected_version: u64) -> Result<(), OrchError> {
         let mut task_write = self.tasks.write()
             .map_err(|e| OrchError::TaskStoreError(format!("Failed to unlock the task: {}", e)))?;
 
-        println!("TASKS: {:#?}", task_write.values().cloned().collect::<Vec<_>>());
+        if let Some(task) = task_write.get_mut(&id) {
+            Self::check_version(task, Some(expected_version))?;
 
-        if let Some(task) = task_write.get_mut(&id.to_string()) {
-            task.node_id = Some(node_id);
-            task.status = TaskStatus::Scheduled;
+            let previous = task.node_id.replace(node_id.clone());
+            if let Err(e) = task.transition(TaskStatus::Scheduled, Some(format!("Assigned to {}", node_id))) {
+                task.node_id = previous;
+                return Err(e);
+            }
+            task.nominated_node = None;
+            self.record(EventType::Modified, |version| {
+                task.resource_version = version;
+                WatchObject::Task(Box::new(task.clone()))
+            })?;
             Ok(())
         } else {
             Err(OrchError::TaskNotFound(format!("Couldn't find the task {} to assign the node {}", id, node_id)))
         }
     }
 
-    pub fn update_status(&self, id: Uuid, status: TaskStatus, container_id: Option<String>) -> Result<bool, OrchError> {
+    /// Starts rescheduling `victims` off the node and sets the node aside for the task.
+    ///
+    /// Nothing changes unless the task and every victim are still at the versions the scheduler
+    /// saw. Like after a drain, the victims keep their room until their worker stopped them or
+    /// `grace_period` is over. The task stays `Pending` with the node as its `nominated_node`,
+    /// which the scheduler holds for it until then. Returns the preempted tasks.
+    pub fn preempt(
+        &self,
+        id: Uuid,
+        node_id: &str,
+        expected_version: u64,
+        victims: &[(Uuid, u64)],
+        grace_period: Duration,
+    ) -> Result<Vec<Task>, OrchError> {
+        let mut task_write = self.tasks.write()
+            .map_err(|e| OrchError::TaskStoreError(format!("Failed to unlock the task: {}", e)))?;
+
+        let Some(task) = task_write.get(&id) else {
+            return Err(OrchError::TaskNotFound(format!("Couldn't find the task {} to assign the node {}", id, node_id)));
+        };
+        Self::check_version(task, Some(expected_version))?;
+        if !task.status.can_transition_to(&TaskStatus::Scheduled) {
+            return Err(OrchError::InvalidTransition {
+                task_id: id.to_string(),
+                current: task.status.clone(),
+                requested: TaskStatus::Scheduled,
+            });
+        }
+        let reason = format!("Preempted by task {} on node {}", task.name, node_id);
+        for (victim_id, version) in victims {
+            let victim = task_write
+                .get(victim_id)
+                .ok_or_else(|| OrchError::TaskNotFound(format!("Couldn't find the task {} to preempt", victim_id)))?;
+            Self::check_version(victim, Some(*version))?;
+        }
+
+        let deadline = Utc::now() + grace_period;
+        let mut preempted = Vec::new();
+        for (victim_id, _) in victims {
+            let victim = task_write.get_mut(victim_id).expect("task was just looked up");
+            victim.rescheduling = Some(Rescheduling { reason: reason.clone(), deadline });
+            self.record(EventType::Modified, |version| {
+                victim.resource_version = version;
+                WatchObject::Task(Box::new(victim.clone()))
+            })?;
+            preempted.push(victim.clone());
+        }
+
+        let task = task_write.get_mut(&id).expect("task was just looked up");
+        task.nominated_node = Some(node_id.to_string());
+        self.record(EventType::Modified, |version| {
+            task.resource_version = version;
+            WatchObject::Task(Box::new(task.clone()))
+        })?;
+
+        Ok(preempted)
+    }
+
+    /// Gives up the node set aside for the task, e.g. when it no longer fits there.
+    pub fn clear_nomination(&self, id: Uuid, expected_version: u64) -> Result<(), OrchError> {
+        let mut task_write = self.tasks.write()
+            .map_err(|e| OrchError::TaskStoreError(format!("Failed to unlock the task: {}", e)))?;
+
+        let Some(task) = task_write.get_mut(&id) else {
+            return Err(OrchError::TaskNotFound(format!("Couldn't find the task {} to clear its nominated node", id)));
+        };
+        Self::check_version(task, Some(expected_version))?;
+        task.nominated_node = None;
+        self.record(EventType::Modified, |version| {
+            task.resource_version = version;
+            WatchObject::Task(Box::new(task.clone()))
+        })?;
+        Ok(())
+    }
+
+    /// Moves the task to `status`, rejecting transitions the task lifecycle doesn't allow.
+    ///
+    /// Moving to `Pending` also takes the task off its node. With `expected_version` set, the update is rejected if the task changed in the meantime.
+    pub fn update_status(
+        &self,
+        id: Uuid,
+        status: TaskStatus,
+        container_id: Option<String>,
+        reason: Option<String>,
+        expected_version: Option<u64>,
+    ) -> Result<Option<Task>, OrchError> {
         let mut task_write = self.tasks.write()
         .map_err(|e| OrchError::TaskStoreError(format!("Failed to unlock the task: {}", e)))?;
 
-        if let Some(task) = task_write.get_mut(&id.to_string()) {
-            task.status = status;
+        if let Some(task) = task_write.get_mut(&id) {
+            Self::check_version(task, expected_version)?;
+            if status == TaskStatus::Pending {
+                // a pending task belongs to no node, e.g. the worker stopped a task that is rescheduled
+                task.unbind(reason)?;
+            } else {
+                task.transition(status, reason)?;
+            }
             if container_id.is_some() {
                 task.container_id = container_id;
             }
+            self.record(EventType::Modified, |version| {
+                task.resource_version = version;
+                WatchObject::Task(Box::new(task.clone()))
+            })?;
+
+            return Ok(Some(task.clone()));
+        }
+
+        Ok(None)
+    }
+
+    /// Stores the probe results reported by the worker running the task.
+    pub fn update_health(&self, id: Uuid, health: TaskHealth, expected_version: Option<u64>) -> Result<Option<Task>, OrchError> {
+        let mut task_write = self.tasks.write()
+            .map_err(|e| OrchError::TaskStoreError(format!("Failed to unlock the task: {}", e)))?;
+
+        if let Some(task) = task_write.get_mut(&id) {
+            Self::check_version(task, expected_version)?;
+            task.health = health;
+            self.record(EventType::Modified, |version| {
+                task.resource_version = version;
+                WatchObject::Task(Box::new(task.clone()))
+            })?;
+            return Ok(Some(task.clone()));
+        }
+
+        Ok(None)
+    }
+
+    /// Replaces the spec of a task that hasn't been scheduled yet.
+    ///
+    /// Once a node picked the task up its spec is fixed, changing it fails with `Conflict`.
+    /// With `dry_run` all checks run, but the updated task is only returned, not stored.
+    pub fn update_spec(
+        &self,
+        id: Uuid,
+        spec: TaskSpec,
+        expected_version: Option<u64>,
+        dry_run: bool,
+    ) -> Result<Option<Task>, OrchError> {
+        let mut task_write = self.tasks.write()
+            .map_err(|e| OrchError::TaskStoreError(format!("Failed to unlock the task: {}", e)))?;
+
+        let Some(task) = task_write.get(&id) else {
+            return Ok(None);
+        };
+        Self::check_version(task, expected_version)?;
+        if task.status != TaskStatus::Pending {
+            return Err(OrchError::Conflict(format!(
+                "Task {} is {:?}, only pending tasks can be updated",
+                id, task.status
+            )));
+        }
+        task_write.check_name(&spec.name, id)?;
 
-            return Ok(true);
+        if dry_run {
+            let mut updated = task.clone();
+            spec.apply_to(&mut updated);
+            return Ok(Some(updated));
         }
 
-        Ok(false)
+        task_write.rename(id, &spec.name)?;
+        task_write.relabel(id, spec.labels.clone());
+        let task = task_write.get_mut(&id).expect("task was just looked up");
+        spec.apply_to(task);
+        self.record(EventType::Modified, |version| {
+            task.resource_version = version;
+            WatchObject::Task(Box::new(task.clone()))
+        })?;
+
+        Ok(Some(task.clone()))
+    }
+
+    /// Removes the task, watchers get a `Deleted` event carrying its last state.
+    pub fn delete_task(&self, id: Uuid, expected_version: Option<u64>) -> Result<Option<Task>, OrchError> {
+        let mut task_write = self.tasks.write()
+            .map_err(|e| OrchError::TaskStoreError(format!("Failed to unlock the task: {}", e)))?;
+
+        let Some(task) = task_write.get(&id) else {
+            return Ok(None);
+        };
+        Self::check_version(task, expected_version)?;
+
+        let mut task = task_write.remove(&id).expect("task was just looked up");
+        self.record(EventType::Deleted, |version| {
+            task.resource_version = version;
+            WatchObject::Task(Box::new(task.clone()))
+        })?;
+
+        let mut metrics = self.metrics.lock()
+            .map_err(|e| OrchError::TaskStoreError(format!("Failed to unlock the metrics: {}", e)))?;
+        metrics.retain_tasks(|task_id| *task_id != id);
+
+        Ok(Some(task))
+    }
+
+    /// Registers a worker node, replacing any previous registration with the same id.
+    ///
+    /// The taints and cordon of a known node are kept, they are managed through `update_taints`,
+    /// `set_unschedulable` and `drain_node` once the node registered. A registration that only
+    /// refreshes the heartbeat updates `last_heartbeat` without publishing a change.
+    pub fn register_node(&self, mut node: Node) -> Result<(), OrchError> {
+        let mut node_write = self.nodes.write()
+            .map_err(|e| OrchError::TaskStoreError(format!("Failed to unlock the node: {}", e)))?;
+
+        let event_type = match node_write.get(&node.id) {
+            Some(previous) => {
+                node.taints = previous.taints.clone();
+                node.unschedulable = previous.unschedulable;
+                node.resource_version = previous.resource_version;
+                node.last_heartbeat = previous.last_heartbeat;
+                EventType::Modified
+            }
+            None => EventType::Added,
+        };
+        let unchanged = node_write.get(&node.id) == Some(&node);
+        node.last_heartbeat = Some(Utc::now());
+        // a plain heartbeat would push the useful changes out of the watch history
+        if unchanged {
+            node_write.insert(node.id.clone(), node);
+            return Ok(());
+        }
+        self.record(event_type, |version| {
+            node.resource_version = version;
+            WatchObject::Node(Box::new(node.clone()))
+        })?;
+        node_write.insert(node.id.clone(), node);
+
+        Ok(())
     }
 
+    /// Changes the taints of a node, returns the node and the tasks evicted from it.
+    ///
+    /// Unfinished tasks on the node that don't tolerate one of its `NoExecute` taints
+    /// fail with an `Evicted` reason, their worker then stops them.
+    pub fn update_taints(
+        &self,
+        id: &str,
+        change: impl FnOnce(&mut Vec<Taint>),
+    ) -> Result<Option<(Node, Vec<Task>)>, OrchError> {
+        // same lock order as `watch`, tasks before nodes
+        let mut task_write = self.tasks.write()
+            .map_err(|e| OrchError::TaskStoreError(format!("Failed to unlock the task: {}", e)))?;
+        let mut node_write = self.nodes.write()
+            .map_err(|e| OrchError::TaskStoreError(format!("Failed to unlock the node: {}", e)))?;
+
+        let Some(node) = node_write.get_mut(id) else {
+            return Ok(None);
+        };
+        change(&mut node.taints);
+        self.record(EventType::Modified, |version| {
+            node.resource_version = version;
+            WatchObject::Node(Box::new(node.clone()))
+        })?;
+
+        let evictions: Vec<(Uuid, Taint)> = task_write
+            .values()
+            .filter(|t| t.node_id.as_deref() == Some(id) && !t.status.is_terminal())
+            .filter_map(|t| {
+                node.taints
+                    .iter()
+                    .find(|taint| taint.effect == TaintEffect::NoExecute && !tolerated(&t.tolerations, taint))
+                    .map(|taint| (t.id, taint.clone()))
+            })
+            .collect();
 
-}
\ No newline at end of file
+        let mut evicted = Vec::new();
+        for (task_id, taint) in evictions {
+            let task = task_write.get_mut(&task_id).expect("task was just looked up");
+            task.transition(TaskStatus::Failed, Some(format!("{}: node {} has the taint {}", EVICTED, id, taint)))?;
+            self.record(EventType::Modified, |version| {
+                task.resource_version = version;
+                WatchObject::Task(Box::new(task.clone()))
+            })?;
+            evicted.push(task.clone());
+        }
+
+        Ok(Some((node.clone(), evicted)))
+    }
+
+    /// Cordons (`true`) or uncordons (`false`) the node, tasks already on it are left alone.
+    pub fn set_unschedulable(&self, id: &str, unschedulable: bool) -> Result<Option<Node>, OrchError> {
+        let mut node_write = self.nodes.write()
+            .map_err(|e| OrchError::TaskStoreError(format!("Failed to unlock the node: {}", e)))?;
+
+        let Some(node) = node_write.get_mut(id) else {
+            return Ok(None);
+        };
+        if node.unschedulable != unschedulable {
+            node.unschedulable = unschedulable;
+            self.record(EventType::Modified, |version| {
+                node.resource_version = version;
+                WatchObject::Node(Box::new(node.clone()))
+            })?;
+        }
+
+        Ok(Some(node.clone()))
+    }
+
+    /// Cordons the node and starts rescheduling its unfinished tasks. Returns the node and those tasks.
+    ///
+    /// The tasks stay on the node until the worker reports their containers stopped, or until
+    /// `grace_period` is over, then they go back to `Pending` and the scheduler places them on
+    /// other nodes. See [`Rescheduling`].
+    pub fn drain_node(&self, id: &str, grace_period: Duration) -> Result<Option<(Node, Vec<Task>)>, OrchError> {
+        let mut task_write = self.tasks.write()
+            .map_err(|e| OrchError::TaskStoreError(format!("Failed to unlock the task: {}", e)))?;
+        let mut node_write = self.nodes.write()
+            .map_err(|e| OrchError::TaskStoreError(format!("Failed to unlock the node: {}", e)))?;
+
+        let Some(node) = node_write.get_mut(id) else {
+            return Ok(None);
+        };
+        if !node.unschedulable {
+            node.unschedulable = true;
+            self.record(EventType::Modified, |version| {
+                node.resource_version = version;
+                WatchObject::Node(Box::new(node.clone()))
+            })?;
+        }
+
+        let ids: Vec<Uuid> = task_write
+            .values()
+            .filter(|t| t.node_id.as_deref() == Some(id) && !t.status.is_terminal())
+            .map(|t| t.id)
+            .collect();
+
+        let deadline = Utc::now() + grace_period;
+        let mut drained = Vec::new();
+        for task_id in ids {
+            let task = task_write.get_mut(&task_id).expect("task was just looked up");
+            // drained again, the first deadline stands
+            if task.rescheduling.is_none() {
+                task.rescheduling = Some(Rescheduling { reason: format!("Drained from node {}", id), deadline });
+                self.record(EventType::Modified, |version| {
+                    task.resource_version = version;
+                    WatchObject::Task(Box::new(task.clone()))
+                })?;
+            }
+            drained.push(task.clone());
+        }
+
+        Ok(Some((node.clone(), drained)))
+    }
+
+    /// Marks the ready nodes that sent no heartbeat for `timeout` as `NotReady`, returns them.
+    ///
+    /// Their worker is presumably down, so no new tasks go there and their unfinished tasks are
+    /// rescheduled like after a drain, moving once the grace period is over. The next heartbeat
+    /// makes the node `Ready` again.
+    pub fn mark_unresponsive(&self, now: DateTime<Utc>, timeout: Duration) -> Result<Vec<Node>, OrchError> {
+        let mut task_write = self.tasks.write()
+            .map_err(|e| OrchError::TaskStoreError(format!("Failed to unlock the task: {}", e)))?;
+        let mut node_write = self.nodes.write()
+            .map_err(|e| OrchError::TaskStoreError(format!("Failed to unlock the node: {}", e)))?;
+
+        let expired: Vec<String> = node_write
+            .values()
+            .filter(|n| n.status == NodeStatus::Ready && n.last_heartbeat.is_some_and(|at| at + timeout <= now))
+            .map(|n| n.id.clone())
+            .collect();
+
+        let deadline = now + Duration::from_secs(DEFAULT_GRACE_PERIOD_SECS);
+        let mut marked = Vec::new();
+        for id in expired {
+            let node = node_write.get_mut(&id).expect("node was just looked up");
+            node.status = NodeStatus::NotReady;
+            self.record(EventType::Modified, |version| {
+                node.resource_version = version;
+                WatchObject::Node(Box::new(node.clone()))
+            })?;
+            marked.push(node.clone());
+
+            let reason = format!("Node {} stopped sending heartbeats", id);
+            let tasks: Vec<Uuid> = task_write
+                .values()
+                .filter(|t| t.node_id.as_deref() == Some(&id) && !t.status.is_terminal() && t.rescheduling.is_none())
+                .map(|t| t.id)
+                .collect();
+            for task_id in tasks {
+                let task = task_write.get_mut(&task_id).expect("task was just looked up");
+                task.rescheduling = Some(Rescheduling { reason: reason.clone(), deadline });
+                self.record(EventType::Modified, |version| {
+                    task.resource_version = version;
+                    WatchObject::Task(Box::new(task.clone()))
+                })?;
+            }
+        }
+
+        Ok(marked)
+    }
+
+    /// Sends the tasks whose rescheduling deadline passed back to `Pending`, returns them.
+    ///
+    /// Their worker didn't confirm in time that the container stopped, e.g. because it's down.
+    pub fn release_overdue(&self, now: DateTime<Utc>) -> Result<Vec<Task>, OrchError> {
+        let mut task_write = self.tasks.write()
+            .map_err(|e| OrchError::TaskStoreError(format!("Failed to unlock the task: {}", e)))?;
+
+        let overdue: Vec<Uuid> = task_write
+            .values()
+            .filter(|t| !t.status.is_terminal() && t.rescheduling.as_ref().is_some_and(|r| r.deadline <= now))
+            .map(|t| t.id)
+            .collect();
+
+        let mut released = Vec::new();
+        for id in overdue {
+            let task = task_write.get_mut(&id).expect("task was just looked up");
+            task.unbind(None)?;
+            self.record(EventType::Modified, |version| {
+                task.resource_version = version;
+                WatchObject::Task(Box::new(task.clone()))
+            })?;
+            released.push(task.clone());
+        }
+
+        Ok(released)
+    }
+
+    pub fn list_nodes(&self) -> Result<Vec<Node>, OrchError> {
+        let node_read = self.nodes.read()
+            .map_err(|e| OrchError::TaskStoreError(format!("Failed to unlock the node: {}", e)))?;
+
+        Ok(node_read.values().cloned().collect())
+    }
+
+    pub fn get_node(&self, id: &str) -> Result<Option<Node>, OrchError> {
+        let node_read = self.nodes.read()
+            .map_err(|e| OrchError::TaskStoreError(format!("Failed to unlock the node: {}", e)))?;
+
+        Ok(node_read.get(id).cloned())
+    }
+
+    /// Stores a new resource, fails with `Conflict` if one of its kind with the same name exists.
+    pub fn add_resource<R: NamedResource>(&self, mut resource: R) -> Result<R, OrchError> {
+        let mut table = R::table(self).write()?;
+
+        if table.contains_key(resource.name()) {
+            return Err(OrchError::Conflict(format!("a {} named '{}' already exists", R::KIND, resource.name())));
+        }
+        self.record(EventType::Added, |version| {
+            resource.set_resource_version(version);
+            resource.watch_object()
+        })?;
+        table.insert(resource.name().to_string(), resource.clone());
+
+        Ok(resource)
+    }
+
+    pub fn list_resources<R: NamedResource>(&self) -> Result<Vec<R>, OrchError> {
+        Ok(R::table(self).read()?.values().cloned().collect())
+    }
+
+    pub fn get_resource<R: NamedResource>(&self, name: &str) -> Result<Option<R>, OrchError> {
+        Ok(R::table(self).read()?.get(name).cloned())
+    }
+
+    /// Changes a resource, honouring `expected_version` like the task updates do.
+    ///
+    /// Returns `None` if there's no resource of its kind with that name.
+    pub fn update_resource<R: NamedResource>(
+        &self,
+        name: &str,
+        expected_version: Option<u64>,
+        change: impl FnOnce(&mut R),
+    ) -> Result<Option<R>, OrchError> {
+        let mut table = R::table(self).write()?;
+
+        let Some(resource) = table.get_mut(name) else {
+            return Ok(None);
+        };
+        resource.check_version(expected_version)?;
+
+        change(resource);
+        self.record(EventType::Modified, |version| {
+            resource.set_resource_version(version);
+            resource.watch_object()
+        })?;
+
+        Ok(Some(resource.clone()))
+    }
+
+    /// Removes the resource, its controller then deletes the tasks or jobs it owned.
+    pub fn delete_resource<R: NamedResource>(&self, name: &str, expected_version: Option<u64>) -> Result<Option<R>, OrchError> {
+        let mut table = R::table(self).write()?;
+
+        let Some(resource) = table.get(name) else {
+            return Ok(None);
+        };
+        resource.check_version(expected_version)?;
+
+        let mut resource = table.remove(name).expect("resource was just looked up");
+        self.record(EventType::Deleted, |version| {
+            resource.set_resource_version(version);
+            resource.watch_object()
+        })?;
+
+        Ok(Some(resource))
+    }
+
+    /// Keeps the usage a worker reported with its heartbeat.
+    ///
+    /// Samples of tasks that are gone from the store are dropped along the way.
+    pub fn record_usage(&self, node_id: &str, report: UsageReport) -> Result<(), OrchError> {
+        let task_read = self.tasks.read()
+            .map_err(|e| OrchError::TaskStoreError(format!("Failed to unlock the task: {}", e)))?;
+        let mut metrics = self.metrics.lock()
+            .map_err(|e| OrchError::TaskStoreError(format!("Failed to unlock the metrics: {}", e)))?;
+
+        metrics.record(node_id, report);
+        metrics.retain_tasks(|id| task_read.get(id).is_some());
+        Ok(())
+    }
+
+    /// Recent usage samples of the node, oldest first.
+    pub fn node_usage(&self, id: &str) -> Result<Vec<UsageSample>, OrchError> {
+        let metrics = self.metrics.lock()
+            .map_err(|e| OrchError::TaskStoreError(format!("Failed to unlock the metrics: {}", e)))?;
+
+        Ok(metrics.node(id))
+    }
+
+    /// The node that last measured the task and its recent usage samples, oldest first.
+    pub fn task_usage(&self, id: &Uuid) -> Result<Option<(String, Vec<UsageSample>)>, OrchError> {
+        let metrics = self.metrics.lock()
+            .map_err(|e| OrchError::TaskStoreError(format!("Failed to unlock the metrics: {}", e)))?;
+
+        Ok(metrics.task(id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_versions_increase_on_every_write() {
+        let store = TaskStore::new();
+        let task = Task::new("t".to_string(), "img".to_string());
+        let id = task.id;
+
+        let created = store.add_task(task).unwrap().resource_version;
+        let task = store.get_task(id).unwrap().unwrap();
+        assert_eq!(task.resource_version, created);
+
+        store.assign_node(id, "worker-1".to_string(), created).unwrap();
+        let scheduled = store.get_task(id).unwrap().unwrap().resource_version;
+        assert!(scheduled > created);
+
+        let running = store
+            .update_status(id, TaskStatus::Running, None, None, None)
+            .unwrap()
+            .unwrap()
+            .resource_version;
+        assert!(running > scheduled);
+    }
+
+    #[test]
+    fn test_assign_node_is_compare_and_set() {
+        let store = TaskStore::new();
+        let task = Task::new("t".to_string(), "img".to_string());
+        let id = task.id;
+        let version = store.add_task(task).unwrap().resource_version;
+
+        store.assign_node(id, "worker-1".to_string(), version).unwrap();
+
+        // a second scheduler working from the same snapshot must lose
+        match store.assign_node(id, "worker-2".to_string(), version) {
+            Err(OrchError::VersionConflict { expected, .. }) => assert_eq!(expected, version),
+            other => panic!("expected VersionConflict, got {:?}", other),
+        }
+        assert_eq!(store.get_task(id).unwrap().unwrap().node_id.as_deref(), Some("worker-1"));
+    }
+
+    #[test]
+    fn test_preempt_is_all_or_nothing() {
+        let store = TaskStore::new();
+        let batch = store.add_task(Task::new("batch".to_string(), "img".to_string())).unwrap();
+        store.assign_node(batch.id, "worker-1".to_string(), batch.resource_version).unwrap();
+        let batch = store
+            .update_status(batch.id, TaskStatus::Running, Some("c1".to_string()), None, None)
+            .unwrap()
+            .unwrap();
+        let api = store.add_task(Task::new("api".to_string(), "img".to_string())).unwrap();
+
+        // the victim moved on since the scheduler listed it
+        let stale = [(batch.id, batch.resource_version - 1)];
+        assert!(matches!(
+            store.preempt(api.id, "worker-1", api.resource_version, &stale, Duration::from_secs(30)),
+            Err(OrchError::VersionConflict { .. })
+        ));
+        assert_eq!(store.get_task(api.id).unwrap().unwrap().status, TaskStatus::Pending);
+
+        let victims = [(batch.id, batch.resource_version)];
+        let preempted = store.preempt(api.id, "worker-1", api.resource_version, &victims, Duration::from_secs(30)).unwrap();
+        assert_eq!(preempted.len(), 1);
+        // the victim keeps its room until its worker stopped it, the node is set aside for api meanwhile
+        let batch = store.get_task(batch.id).unwrap().unwrap();
+        assert_eq!((batch.status, batch.node_id.as_deref()), (TaskStatus::Running, Some("worker-1")));
+        let api = store.get_task(api.id).unwrap().unwrap();
+        assert_eq!((api.status, api.nominated_node.as_deref()), (TaskStatus::Pending, Some("worker-1")));
+
+        let stopped = Some("Container stopped".to_string());
+        let batch = store.update_status(batch.id, TaskStatus::Pending, None, stopped, None).unwrap().unwrap();
+        assert_eq!((batch.node_id, batch.rescheduling), (None, None));
+        assert_eq!(
+            batch.history.last().unwrap().reason.as_deref(),
+            Some("Preempted by task api on node worker-1")
+        );
+        store.assign_node(api.id, "worker-1".to_string(), api.resource_version).unwrap();
+        assert_eq!(store.get_task(api.id).unwrap().unwrap().nominated_node, None);
+    }
+
+    #[test]
+    fn test_watch_resumes_after_version() {
+        let store = TaskStore::new();
+        let first = store.add_task(Task::new("a".to_string(), "img".to_string())).unwrap();
+        let second = store.add_task(Task::new("b".to_string(), "img".to_string())).unwrap();
+        store.register_node(Node::new("worker-1".to_string(), 1024, 1.0)).unwrap();
+
+        let (backlog, _) = store.watch(Some(first.resource_version)).unwrap();
+        let versions: Vec<u64> = backlog.iter().map(|e| e.resource_version).collect();
+        assert_eq!(versions, vec![second.resource_version, second.resource_version + 1]);
+        assert!(matches!(backlog[1].object, WatchObject::Node(_)));
+
+        let (mut backlog, mut rx) = store.watch(None).unwrap();
+        assert_eq!(backlog.len(), 3);
+        assert!(backlog.iter().all(|e| e.event_type == EventType::Added));
+
+        store.update_status(first.id, TaskStatus::Failed, None, None, None).unwrap();
+        backlog.push(rx.try_recv().unwrap());
+        let last = backlog.last().unwrap();
+        assert_eq!(last.event_type, EventType::Modified);
+        assert!(matches!(&last.object, WatchObject::Task(t) if t.status == TaskStatus::Failed));
+    }
+
+    #[test]
+    fn test_heartbeats_stay_out_of_the_watch_history() {
+        let store = TaskStore::new();
+        store.register_node(Node::new("worker-1".to_string(), 4096, 4.0)).unwrap();
+        let registered = store.get_node("worker-1").unwrap().unwrap();
+
+        store.register_node(Node::new("worker-1".to_string(), 4096, 4.0)).unwrap();
+        let (backlog, _) = store.watch(Some(registered.resource_version)).unwrap();
+        assert!(backlog.is_empty());
+        let refreshed = store.get_node("worker-1").unwrap().unwrap();
+        assert_eq!(refreshed.resource_version, registered.resource_version);
+        assert!(refreshed.last_heartbeat >= registered.last_heartbeat);
+
+        let mut relabeled = Node::new("worker-1".to_string(), 4096, 4.0);
+        relabeled.labels.insert("disk".to_string(), "ssd".to_string());
+        store.register_node(relabeled).unwrap();
+        let (backlog, _) = store.watch(Some(registered.resource_version)).unwrap();
+        assert_eq!(backlog.len(), 1);
+    }
+
+    #[test]
+    fn test_watch_rejects_compacted_versions() {
+        let store = TaskStore::new();
+        for i in 0..WATCH_HISTORY + 2 {
+            store.add_task(Task::new(format!("t{}", i), "img".to_string())).unwrap();
+        }
+
+        assert!(matches!(store.watch(Some(1)), Err(OrchError::ResourceVersionTooOld(1))));
+        assert!(store.watch(Some(2)).is_ok());
+        // a version from before a restart
+        let ahead = WATCH_HISTORY as u64 + 3;
+        assert!(matches!(store.watch(Some(ahead)), Err(OrchError::ResourceVersionTooOld(v)) if v == ahead));
+    }
+
+    #[test]
+    fn test_stale_status_update_is_rejected() {
+        let store = TaskStore::new();
+        let task = Task::new("t".to_string(), "img".to_string());
+        let id = task.id;
+        let version = store.add_task(task).unwrap().resource_version;
+        store.update_health(id, TaskHealth::default(), None).unwrap();
+
+        let result = store.update_status(id, TaskStatus::Failed, None, None, Some(version));
+        assert!(matches!(result, Err(OrchError::VersionConflict { .. })));
+        assert_eq!(store.get_task(id).unwrap().unwrap().status, TaskStatus::Pending);
+    }
+
+    #[test]
+    fn test_spec_updates_and_deletes() {
+        let store = TaskStore::new();
+        let task = store.add_task(Task::new("web".to_string(), "nginx".to_string())).unwrap();
+        let (_, mut rx) = store.watch(None).unwrap();
+
+        let mut spec = TaskSpec::from_task(&task);
+        spec.image = "nginx:1.27".to_string();
+        let preview = store.update_spec(task.id, spec.clone(), Some(task.resource_version), true).unwrap().unwrap();
+        assert_eq!(preview.image, "nginx:1.27");
+        assert_eq!(store.get_task(task.id).unwrap().unwrap().image, "nginx");
+
+        let updated = store.update_spec(task.id, spec.clone(), Some(task.resource_version), false).unwrap().unwrap();
+        assert_eq!(updated.image, "nginx:1.27");
+
+        // scheduled tasks keep their spec
+        store.assign_node(task.id, "worker-1".to_string(), updated.resource_version).unwrap();
+        assert!(matches!(store.update_spec(task.id, spec, None, true), Err(OrchError::Conflict(_))));
+
+        let deleted = store.delete_task(task.id, None).unwrap().unwrap();
+        assert!(store.get_task(task.id).unwrap().is_none());
+        assert!(store.delete_task(task.id, None).unwrap().is_none());
+
+        let last = std::iter::from_fn(|| rx.try_recv().ok()).last().unwrap();
+        assert_eq!(last.event_type, EventType::Deleted);
+        assert_eq!(last.resource_version, deleted.resource_version);
+    }
+
+    #[test]
+    fn test_task_names_are_unique() {
+        let store = TaskStore::new();
+        let web = store.add_task(Task::new("web".to_string(), "nginx".to_string())).unwrap();
+        let db = store.add_task(Task::new("db".to_string(), "postgres".to_string())).unwrap();
+
+        let duplicate = store.add_task(Task::new("web".to_string(), "httpd".to_string()));
+        assert!(matches!(duplicate, Err(OrchError::Conflict(_))));
+        assert_eq!(store.list_tasks().unwrap().len(), 2);
+
+        // renaming moves the name in the index
+        let mut spec = TaskSpec::from_task(&db);
+        spec.name = "web".to_string();
+        assert!(matches!(store.update_spec(db.id, spec.clone(), None, false), Err(OrchError::Conflict(_))));
+        spec.name = "postgres".to_string();
+        store.update_spec(db.id, spec, None, false).unwrap();
+        assert_eq!(store.get_task_by_name("postgres").unwrap().unwrap().id, db.id);
+        assert!(store.get_task_by_name("db").unwrap().is_none());
+
+        // a deleted task frees its name
+        store.delete_task(web.id, None).unwrap();
+        store.add_task(Task::new("web".to_string(), "httpd".to_string())).unwrap();
+        assert_eq!(store.get_task_by_name("web").unwrap().unwrap().image, "httpd");
+    }
+
+    #[test]
+    fn test_select_uses_label_index() {
+        let store = TaskStore::new();
+        for i in 0..1000 {
+            let mut task = Task::new(format!("t{}", i), "img".to_string());
+            task.labels.insert("app".to_string(), if i % 2 == 0 { "web" } else { "db" }.to_string());
+            task.labels.insert("shard".to_string(), (i % 10).to_string());
+            store.add_task(task).unwrap();
+        }
+
+        let select = |selector: &str| store.select_tasks(&selector.parse().unwrap()).unwrap();
+        assert_eq!(select("app=web").len(), 500);
+        assert_eq!(select("app=web,shard in (0,1,2)").len(), 200);
+        assert_eq!(select("app!=web,shard notin (1)").len(), 400);
+        assert_eq!(select("zone").len(), 0);
+        assert_eq!(select("").len(), 1000);
+
+        // relabelled and deleted tasks leave the index
+        let task = store.get_task_by_name("t0").unwrap().unwrap();
+        let mut spec = TaskSpec::from_task(&task);
+        spec.labels.insert("app".to_string(), "cache".to_string());
+        store.update_spec(task.id, spec, None, false).unwrap();
+        store.delete_task(store.get_task_by_name("t2").unwrap().unwrap().id, None).unwrap();
+
+        assert_eq!(select("app=web").len(), 498);
+        assert_eq!(select("app=cache")[0].id, task.id);
+    }
+
+    #[test]
+    fn test_no_execute_taint_evicts_intolerant_tasks() {
+        let store = TaskStore::new();
+        store.register_node(Node::new("worker-1".to_string(), 4096, 4.0)).unwrap();
+
+        let mut ids = Vec::new();
+        for name in ["web", "db"] {
+            let mut task = Task::new(name.to_string(), "img".to_string());
+            if name == "db" {
+                task.tolerations.push(common::Toleration {
+                    key: Some("maintenance".to_string()),
+                    operator: common::TolerationOperator::Exists,
+                    value: String::new(),
+                    effect: None,
+                });
+            }
+            let task = store.add_task(task).unwrap();
+            store.assign_node(task.id, "worker-1".to_string(), task.resource_version).unwrap();
+            ids.push(task.id);
+        }
+
+        let taint: Taint = "dedicated=db:NoSchedule".parse().unwrap();
+        let (_, evicted) = store.update_taints("worker-1", |taints| taints.push(taint)).unwrap().unwrap();
+        assert!(evicted.is_empty());
+
+        let taint: Taint = "maintenance:NoExecute".parse().unwrap();
+        let (node, evicted) = store.update_taints("worker-1", |taints| taints.push(taint)).unwrap().unwrap();
+        assert_eq!(node.taints.len(), 2);
+        assert_eq!(evicted.len(), 1);
+        assert!(store.get_task(ids[0]).unwrap().unwrap().is_evicted());
+        assert_eq!(store.get_task(ids[1]).unwrap().unwrap().status, TaskStatus::Scheduled);
+
+        // re-registering the node doesn't drop its taints
+        store.register_node(Node::new("worker-1".to_string(), 4096, 4.0)).unwrap();
+        assert_eq!(store.get_node("worker-1").unwrap().unwrap().taints.len(), 2);
+        assert!(store.update_taints("worker-2", |_| {}).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_silent_nodes_become_not_ready() {
+        let store = TaskStore::new();
+        let mut node = Node::new("worker-1".to_string(), 4096, 4.0);
+        node.status = NodeStatus::Ready;
+        store.register_node(node).unwrap();
+        let task = store.add_task(Task::new("web".to_string(), "img".to_string())).unwrap();
+        store.assign_node(task.id, "worker-1".to_string(), task.resource_version).unwrap();
+
+        let timeout = Duration::from_secs(30);
+        let now = Utc::now();
+        assert!(store.mark_unresponsive(now, timeout).unwrap().is_empty());
+
+        let registered = store.get_node("worker-1").unwrap().unwrap();
+        let later = now + Duration::from_secs(31);
+        assert_eq!(store.mark_unresponsive(later, timeout).unwrap().len(), 1);
+        let (backlog, _) = store.watch(Some(registered.resource_version)).unwrap();
+        assert!(backlog.iter().any(|e| matches!(&e.object, WatchObject::Node(n) if n.status == NodeStatus::NotReady)));
+
+        let nodes = store.list_nodes().unwrap();
+        let other = Task::new("api".to_string(), "img".to_string());
+        assert!(crate::scheduler::place(&other, &nodes, &HashMap::new()).is_err());
+        let task = store.get_task(task.id).unwrap().unwrap();
+        assert_eq!(task.rescheduling.unwrap().reason, "Node worker-1 stopped sending heartbeats");
+        // only marked once
+        assert!(store.mark_unresponsive(later, timeout).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_drain_keeps_tasks_bound_until_stopped() {
+        let store = TaskStore::new();
+        store.register_node(Node::new("worker-1".to_string(), 4096, 4.0)).unwrap();
+
+        let add_running = |name: &str| {
+            let task = store.add_task(Task::new(name.to_string(), "img".to_string())).unwrap();
+            store.assign_node(task.id, "worker-1".to_string(), task.resource_version).unwrap();
+            store.update_status(task.id, TaskStatus::Running, Some(format!("c-{}", name)), None, None).unwrap();
+            task
+        };
+        let task = add_running("web");
+        let slow = add_running("slow");
+        let done = store.add_task(Task::new("job".to_string(), "img".to_string())).unwrap();
+        store.assign_node(done.id, "worker-1".to_string(), done.resource_version).unwrap();
+        store.update_status(done.id, TaskStatus::Failed, None, None, None).unwrap();
+
+        let (node, drained) = store.drain_node("worker-1", Duration::from_secs(30)).unwrap().unwrap();
+        assert!(node.unschedulable);
+        assert_eq!(drained.len(), 2);
+
+        // still on the node and counted there until the worker confirms the container stopped
+        let marked = store.get_task(task.id).unwrap().unwrap();
+        assert_eq!(marked.status, TaskStatus::Running);
+        assert_eq!(marked.node_id.as_deref(), Some("worker-1"));
+        let deadline = marked.rescheduling.as_ref().unwrap().deadline;
+        assert!(store.release_overdue(Utc::now()).unwrap().is_empty());
+
+        // draining again keeps the first deadline
+        store.drain_node("worker-1", Duration::from_secs(300)).unwrap();
+        assert_eq!(store.get_task(task.id).unwrap().unwrap().rescheduling.unwrap().deadline, deadline);
+
+        store.update_status(task.id, TaskStatus::Pending, None, Some("Container stopped".to_string()), None).unwrap();
+        let task = store.get_task(task.id).unwrap().unwrap();
+        assert_eq!(task.status, TaskStatus::Pending);
+        assert_eq!((task.node_id, task.container_id, task.rescheduling), (None, None, None));
+        let last = task.history.last().unwrap();
+        assert_eq!(last.node_id.as_deref(), Some("worker-1"));
+        assert_eq!(last.reason.as_deref(), Some("Drained from node worker-1"));
+
+        // the worker never confirmed, so the task is rescheduled once the grace period is over
+        let released = store.release_overdue(deadline).unwrap();
+        assert_eq!(released.iter().map(|t| t.id).collect::<Vec<_>>(), [slow.id]);
+        assert_eq!(released[0].status, TaskStatus::Pending);
+        assert_eq!(released[0].node_id, None);
+
+        // the cordon survives re-registration until the node is uncordoned
+        store.register_node(Node::new("worker-1".to_string(), 4096, 4.0)).unwrap();
+        assert!(store.get_node("worker-1").unwrap().unwrap().unschedulable);
+        assert!(!store.set_unschedulable("worker-1", false).unwrap().unwrap().unschedulable);
+    }
+
+    #[test]
+    fn test_usage_of_deleted_tasks_is_dropped() {
+        let store = TaskStore::new();
+        let kept = store.add_task(Task::new("web".to_string(), "img".to_string())).unwrap();
+        let deleted = store.add_task(Task::new("job".to_string(), "img".to_string())).unwrap();
+        let start = Utc::now();
+        let report = |secs: i64, ids: &[Uuid]| common::UsageReport {
+            timestamp: start + chrono::Duration::seconds(secs),
+            node: common::Usage { cpu: 1.0, memory: 512 },
+            tasks: ids.iter().map(|id| common::TaskUsage { task_id: *id, usage: common::Usage { cpu: 0.5, memory: 64 } }).collect(),
+        };
+
+        store.record_usage("worker-1", report(0, &[kept.id, deleted.id])).unwrap();
+        store.delete_task(deleted.id, None).unwrap();
+        assert!(store.task_usage(&deleted.id).unwrap().is_none());
+
+        // a report racing the delete doesn't bring it back, nor does one of a task the store never had
+        let unknown = Uuid::new_v4();
+        store.record_usage("worker-1", report(5, &[kept.id, deleted.id, unknown])).unwrap();
+        assert!(store.task_usage(&deleted.id).unwrap().is_none());
+        assert!(store.task_usage(&unknown).unwrap().is_none());
+        assert_eq!(store.task_usage(&kept.id).unwrap().unwrap().1.len(), 2);
+    }
+
+    fn service(name: &str) -> Service {
+        let template = TaskSpec::from_task(&Task::new(String::new(), "nginx".to_string()));
+        let strategy = Default::default();
+        common::ServiceSpec { name: name.to_string(), labels: Default::default(), replicas: 1, template, strategy }.into_service()
+    }
+
+    #[test]
+    fn test_named_resources() {
+        let store = TaskStore::new();
+        let web = store.add_resource(service("web")).unwrap();
+        assert!(matches!(store.add_resource(service("web")), Err(OrchError::Conflict(_))));
+        assert!(store.get_resource::<Job>("web").unwrap().is_none());
+
+        let scaled = store.update_resource("web", Some(web.resource_version), |s: &mut Service| s.replicas = 3).unwrap().unwrap();
+        assert!(scaled.resource_version > web.resource_version);
+        let stale = store.update_resource("web", Some(web.resource_version), |s: &mut Service| s.replicas = 5);
+        assert_eq!(
+            stale.unwrap_err().to_string(),
+            OrchError::Conflict(format!(
+                "service web was modified concurrently: expected version {}, found {}",
+                web.resource_version, scaled.resource_version
+            ))
+            .to_string()
+        );
+        assert!(store.update_resource("api", None, |s: &mut Service| s.replicas = 5).unwrap().is_none());
+
+        let (events, _) = store.watch(Some(web.resource_version)).unwrap();
+        assert!(matches!(&events[..], [WatchEvent { object: WatchObject::Service(s), .. }] if s.replicas == 3));
+
+        assert!(store.delete_resource::<Service>("web", Some(web.resource_version)).is_err());
+        assert_eq!(store.delete_resource::<Service>("web", None).unwrap().unwrap().replicas, 3);
+        assert!(store.list_resources::<Service>().unwrap().is_empty());
+    }
+}
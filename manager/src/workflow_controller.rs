@@ -0,0 +1,188 @@
+use chrono::{DateTime, Utc};
+use common::{OrchError, OwnerKind, StepState, StepStatus, Task, TaskStatus, Workflow, WorkflowState, WorkflowStatus};
+use crate::reconcile;
+use crate::store::SharedState;
+
+const CONTROLLER: &str = "Workflow controller";
+
+/// What the workflow controller does to move a workflow along.
+struct Plan {
+    /// Positions of the steps to start a task for
+    create: Vec<usize>,
+    /// The workflow's status as of this round
+    status: WorkflowStatus,
+}
+
+/// Works out the state of each step from its task and which steps can start.
+///
+/// Steps are looked at in dependency order. A step without a task is released once every step it
+/// depends on succeeded, and skipped as soon as one of them failed or was skipped, so a failure
+/// propagates down the whole branch. Finished steps keep their state even if their task is deleted.
+fn plan(workflow: &Workflow, owned: &[&Task], now: DateTime<Utc>) -> Plan {
+    let mut plan = Plan { create: Vec::new(), status: workflow.status.clone() };
+    if workflow.is_finished() {
+        return plan;
+    }
+
+    let dependencies = workflow.dependencies();
+    let mut steps: Vec<StepStatus> =
+        workflow.steps.iter().map(|step| StepStatus { name: step.name.clone(), ..Default::default() }).collect();
+
+    for i in workflow.levels().concat() {
+        if let Some(previous) = workflow.status.steps.get(i).filter(|s| s.state.is_finished()) {
+            steps[i] = previous.clone();
+            continue;
+        }
+
+        let latest = owned.iter().filter(|t| workflow.step_of(t) == Some(i)).max_by_key(|t| t.created_at);
+        if let Some(task) = latest {
+            let step = &mut steps[i];
+            step.task = Some(task.name.clone());
+            step.state = match task.status {
+                TaskStatus::Complete => StepState::Succeeded,
+                TaskStatus::Failed => StepState::Failed,
+                _ => StepState::Running,
+            };
+            if task.status.is_terminal() {
+                step.finished_at = Some(task.finished_at.unwrap_or(now));
+            }
+            if task.status == TaskStatus::Failed {
+                let reason = task.history.last().and_then(|h| h.reason.clone());
+                step.reason = Some(reason.unwrap_or_else(|| "the task failed".to_string()));
+            }
+            continue;
+        }
+
+        let blocked = dependencies[i].iter().map(|&d| &steps[d]).find(|s| matches!(s.state, StepState::Failed | StepState::Skipped));
+        if let Some(dependency) = blocked {
+            let verb = if dependency.state == StepState::Failed { "failed" } else { "was skipped" };
+            let reason = format!("step {} {}", dependency.name, verb);
+            steps[i].state = StepState::Skipped;
+            steps[i].reason = Some(reason);
+            steps[i].finished_at = Some(now);
+        } else if dependencies[i].iter().all(|&d| steps[d].state == StepState::Succeeded) {
+            plan.create.push(i);
+        }
+    }
+
+    let status = &mut plan.status;
+    if steps.iter().all(|s| s.state.is_finished()) {
+        let failed: Vec<&str> = steps.iter().filter(|s| s.state == StepState::Failed).map(|s| s.name.as_str()).collect();
+        if failed.is_empty() {
+            status.state = WorkflowState::Succeeded;
+        } else {
+            status.state = WorkflowState::Failed;
+            status.message = Some(format!("steps failed: {}", failed.join(", ")));
+        }
+        status.finished_at = Some(now);
+    }
+    plan.create.sort();
+    status.steps = steps;
+    plan
+}
+
+/// Starts the steps of every workflow as their dependencies complete, and deletes the tasks of deleted workflows.
+pub async fn run_workflow_controller(store: SharedState) -> Result<(), OrchError> {
+    reconcile::run(CONTROLLER, store, reconcile).await
+}
+
+fn reconcile(store: &SharedState) -> Result<(), OrchError> {
+    let workflows = store.list_resources::<Workflow>()?;
+    let tasks = store.list_tasks()?;
+    reconcile::delete_orphans(CONTROLLER, store, &workflows, &tasks, OwnerKind::Workflow, Workflow::owns);
+
+    let now = Utc::now();
+    for workflow in &workflows {
+        let owned: Vec<&Task> = tasks.iter().filter(|t| workflow.owns(t)).collect();
+        let plan = plan(workflow, &owned, now);
+
+        for index in &plan.create {
+            match store.add_task(workflow.new_task(*index)) {
+                Ok(task) => println!("{}: workflow/{} created task {}", CONTROLLER, workflow.name, task.name),
+                Err(e) => eprintln!("{}: workflow/{}: {}", CONTROLLER, workflow.name, e),
+            }
+        }
+
+        if plan.status != workflow.status {
+            if plan.status.state != workflow.status.state {
+                println!("{}: workflow/{} {:?}", CONTROLLER, workflow.name, plan.status.state);
+            }
+            // a changed workflow is looked at again next round
+            if let Err(e) = store.update_resource(&workflow.name, Some(workflow.resource_version), |w: &mut Workflow| w.status = plan.status) {
+                eprintln!("{}: {}", CONTROLLER, e);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::{Step, TaskSpec, WorkflowSpec};
+
+    fn workflow(steps: &[(&str, &[&str])]) -> Workflow {
+        let steps = steps
+            .iter()
+            .map(|(name, depends_on)| Step {
+                name: name.to_string(),
+                depends_on: depends_on.iter().map(|d| d.to_string()).collect(),
+                template: TaskSpec::from_task(&Task::new(String::new(), "busybox".to_string())),
+            })
+            .collect();
+        WorkflowSpec { name: "etl".to_string(), labels: Default::default(), steps }.into_workflow()
+    }
+
+    fn task(workflow: &Workflow, step: usize, status: TaskStatus) -> Task {
+        let mut task = workflow.new_task(step);
+        task.status = status;
+        task
+    }
+
+    fn states(plan: &Plan) -> Vec<StepState> {
+        plan.status.steps.iter().map(|s| s.state.clone()).collect()
+    }
+
+    #[test]
+    fn test_plan_releases_steps_after_dependencies_complete() {
+        let workflow = workflow(&[("extract", &[]), ("transform", &["extract"]), ("enrich", &["extract"]), ("load", &["transform", "enrich"])]);
+        let now = Utc::now();
+        assert_eq!(plan(&workflow, &[], now).create, [0]);
+
+        let running = task(&workflow, 0, TaskStatus::Running);
+        assert!(plan(&workflow, &[&running], now).create.is_empty());
+
+        let extracted = task(&workflow, 0, TaskStatus::Complete);
+        let plan = super::plan(&workflow, &[&extracted], now);
+        assert_eq!(plan.create, [1, 2]);
+        assert_eq!(states(&plan), [StepState::Succeeded, StepState::Waiting, StepState::Waiting, StepState::Waiting]);
+
+        let transformed = task(&workflow, 1, TaskStatus::Complete);
+        let enriched = task(&workflow, 2, TaskStatus::Complete);
+        let loaded = task(&workflow, 3, TaskStatus::Complete);
+        let all = [&extracted, &transformed, &enriched];
+        assert_eq!(super::plan(&workflow, &all, now).create, [3]);
+        let plan = super::plan(&workflow, &[&extracted, &transformed, &enriched, &loaded], now);
+        assert_eq!(plan.status.state, WorkflowState::Succeeded);
+    }
+
+    #[test]
+    fn test_plan_skips_steps_after_a_failure() {
+        let workflow = workflow(&[("a", &[]), ("b", &["a"]), ("c", &["b"]), ("other", &[])]);
+        let now = Utc::now();
+        let failed = task(&workflow, 0, TaskStatus::Failed);
+        let other = task(&workflow, 3, TaskStatus::Running);
+
+        let plan = plan(&workflow, &[&failed, &other], now);
+        assert!(plan.create.is_empty());
+        assert_eq!(states(&plan), [StepState::Failed, StepState::Skipped, StepState::Skipped, StepState::Running]);
+        assert_eq!(plan.status.steps[2].reason.as_deref(), Some("step b was skipped"));
+        assert_eq!(plan.status.state, WorkflowState::Running);
+
+        let other = task(&workflow, 3, TaskStatus::Complete);
+        let plan = super::plan(&workflow, &[&failed, &other], now);
+        assert_eq!(plan.status.state, WorkflowState::Failed);
+        assert_eq!(plan.status.message.as_deref(), Some("steps failed: a"));
+    }
+}
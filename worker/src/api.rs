@@ -0,0 +1,222 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use futures_util::StreamExt;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use serde::Deserialize;
+use uuid::Uuid;
+use crate::{DockerClient, ExecSession};
+
+/// The tasks the manager assigned to this node, kept up to date from the watch stream.
+///
+/// The API only gives access to the containers of these tasks.
+pub type AssignedTasks = Arc<RwLock<HashSet<Uuid>>>;
+
+#[derive(Deserialize)]
+struct ExecRequest {
+    command: Vec<String>,
+    #[serde(default)]
+    interactive: bool,
+    #[serde(default)]
+    tty: bool,
+}
+
+/// Runs the worker's API server.
+///
+/// The manager relays client requests that need direct access to the container runtime
+/// (logs, exec) to this server. Like the manager, it speaks plain HTTP/1.1 over a raw `TcpListener`.
+pub async fn serve(addr: String, docker: Arc<DockerClient>, assigned: AssignedTasks) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(&addr).await?;
+    println!("Worker API listening on {}", addr);
+
+    loop {
+        let (socket, _) = listener.accept().await?;
+        let docker = Arc::clone(&docker);
+        let assigned = Arc::clone(&assigned);
+
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(socket, docker, assigned).await {
+                eprintln!("Worker API: error handling connection: {}", e);
+            }
+        });
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream, docker: Arc<DockerClient>, assigned: AssignedTasks) -> anyhow::Result<()> {
+    let Some((head, body)) = common::http::read_request(&mut stream).await? else {
+        return Ok(());
+    };
+
+    match (head.method.as_str(), head.segments().as_slice()) {
+        (method, ["containers", id, action @ ("logs" | "exec")]) => {
+            if let Err((status, message)) = check_access(id, &docker, &assigned).await {
+                return respond(&mut stream, status, &message).await;
+            }
+            match (method, *action) {
+                ("GET", "logs") => handle_logs(stream, id, &head.query, docker).await,
+                ("POST", "exec") => handle_exec(stream, id, &body, docker).await,
+                _ => respond(&mut stream, "404 NOT FOUND", "").await,
+            }
+        }
+        _ => respond(&mut stream, "404 NOT FOUND", "").await,
+    }
+}
+
+/// Looks up the container and makes sure it runs one of our tasks.
+async fn check_access(
+    container_id: &str,
+    docker: &DockerClient,
+    assigned: &AssignedTasks,
+) -> Result<(), (&'static str, String)> {
+    let name = docker
+        .container_name_of(container_id)
+        .await
+        .map_err(|e| ("404 NOT FOUND", e.to_string()))?;
+    let assigned = assigned.read().unwrap_or_else(|e| e.into_inner());
+    owned_task(&name, &assigned).map(|_| ()).map_err(|message| ("403 FORBIDDEN", message))
+}
+
+/// The task running in the named container, if it's a task container assigned to this node.
+///
+/// Other containers on the host, ours or not, are off limits to the API.
+pub fn owned_task(container_name: &str, assigned: &HashSet<Uuid>) -> Result<Uuid, String> {
+    let Some(task_id) = crate::stats::task_id_of(container_name) else {
+        return Err(format!("container {} doesn't belong to a task", container_name));
+    };
+    if !assigned.contains(&task_id) {
+        return Err(format!("task {} isn't assigned to this node", task_id));
+    }
+    Ok(task_id)
+}
+
+async fn respond(stream: &mut TcpStream, status: &str, body: &str) -> anyhow::Result<()> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    Ok(())
+}
+
+/// Streams container output as `text/plain` until the log stream ends.
+///
+/// There's no `Content-Length` since the size is unknown up front (especially with `follow`),
+/// the body ends when we close the connection.
+async fn handle_logs(
+    mut stream: TcpStream,
+    container_id: &str,
+    query: &HashMap<String, String>,
+    docker: Arc<DockerClient>,
+) -> anyhow::Result<()> {
+    let tail = query.get("tail").cloned();
+    let since = query.get("since").and_then(|v| v.parse::<i32>().ok());
+    let follow = query.get("follow").is_some_and(|v| v == "true" || v == "1");
+
+    let mut logs = docker.logs(container_id, tail, since, follow);
+
+    // wait for the first chunk so an unknown container still gets a proper error status
+    let first = logs.next().await;
+    if let Some(Err(e)) = &first {
+        let body = e.to_string();
+        let response = format!(
+            "HTTP/1.1 404 NOT FOUND\r\nContent-Type: text/plain\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        stream.write_all(response.as_bytes()).await?;
+        return Ok(());
+    }
+
+    stream
+        .write_all(b"HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nConnection: close\r\n\r\n")
+        .await?;
+
+    let mut logs = futures_util::stream::iter(first).chain(logs);
+    while let Some(output) = logs.next().await {
+        match output {
+            Ok(bytes) => stream.write_all(&bytes).await?,
+            Err(e) => {
+                eprintln!("Worker API: {}", e);
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs a command in the container and turns the connection into a raw exec session.
+///
+/// Like `docker exec`, we answer with `101 Switching Protocols` and from then on the
+/// connection carries the command's stdin (client -> worker) and output (worker -> client).
+/// The session ends when the command's output stream ends.
+async fn handle_exec(
+    mut stream: TcpStream,
+    container_id: &str,
+    body: &[u8],
+    docker: Arc<DockerClient>,
+) -> anyhow::Result<()> {
+    let Ok(exec_req) = serde_json::from_slice::<ExecRequest>(body) else {
+        stream.write_all(b"HTTP/1.1 400 BAD REQUEST\r\nContent-Length: 0\r\n\r\n").await?;
+        return Ok(());
+    };
+
+    let session = match docker.exec(container_id, exec_req.command, exec_req.interactive, exec_req.tty).await {
+        Ok(session) => session,
+        Err(e) => {
+            let body = e.to_string();
+            let response = format!(
+                "HTTP/1.1 404 NOT FOUND\r\nContent-Type: text/plain\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).await?;
+            return Ok(());
+        }
+    };
+
+    stream
+        .write_all(b"HTTP/1.1 101 SWITCHING PROTOCOLS\r\nConnection: Upgrade\r\nUpgrade: tcp\r\n\r\n")
+        .await?;
+
+    let ExecSession { mut output, mut input } = session;
+    let (mut reader, mut writer) = stream.into_split();
+
+    let stdin = tokio::spawn(async move {
+        let _ = tokio::io::copy(&mut reader, &mut input).await;
+        let _ = input.shutdown().await;
+    });
+
+    while let Some(chunk) = output.next().await {
+        match chunk {
+            Ok(bytes) => writer.write_all(&bytes).await?,
+            Err(e) => {
+                eprintln!("Worker API: {}", e);
+                break;
+            }
+        }
+    }
+
+    stdin.abort();
+    writer.shutdown().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_assigned_task_containers_are_served() {
+        let task_id = Uuid::new_v4();
+        let assigned = HashSet::from([task_id]);
+        let name = crate::docker::container_name(&task_id.to_string());
+
+        assert_eq!(owned_task(&name, &assigned), Ok(task_id));
+        assert!(owned_task(&name, &HashSet::new()).unwrap_err().contains("isn't assigned to this node"));
+        assert!(owned_task("postgres", &assigned).unwrap_err().contains("doesn't belong to a task"));
+        assert!(owned_task("rust-orch-manager", &assigned).is_err());
+    }
+}
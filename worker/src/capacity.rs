@@ -0,0 +1,233 @@
+use std::ffi::CString;
+use std::path::Path;
+
+use common::Resources;
+
+/// Where Docker keeps images and containers, its file system is the node's disk.
+const DOCKER_ROOT: &str = "/var/lib/docker";
+
+/// The node's resources as configured by [`configure`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct NodeResources {
+    pub capacity: Resources,
+    pub reserved: Resources,
+    /// Capacity minus the reservation
+    pub allocatable: Resources,
+}
+
+/// Works out the node's capacity and what of it is allocatable to tasks.
+///
+/// CPU, memory and disk are detected from the host, `ORCH_NODE_CPU`, `ORCH_NODE_MEMORY` and
+/// `ORCH_NODE_DISK` (MB) override them. `ORCH_SYSTEM_RESERVED` (e.g. `cpu=0.5,memory=512`)
+/// sets aside resources for the system daemons, the rest is allocatable.
+pub fn configure() -> anyhow::Result<NodeResources> {
+    let mut capacity = detect();
+
+    if let Ok(cpu) = std::env::var("ORCH_NODE_CPU") {
+        capacity.cpu = cpu.parse().map_err(|_| anyhow::anyhow!("ORCH_NODE_CPU: invalid number '{}'", cpu))?;
+    }
+    if let Ok(memory) = std::env::var("ORCH_NODE_MEMORY") {
+        capacity.memory = memory.parse().map_err(|_| anyhow::anyhow!("ORCH_NODE_MEMORY: invalid number '{}'", memory))?;
+    }
+    if let Ok(disk) = std::env::var("ORCH_NODE_DISK") {
+        capacity.disk = disk.parse().map_err(|_| anyhow::anyhow!("ORCH_NODE_DISK: invalid number '{}'", disk))?;
+    }
+    if capacity.memory <= 0 {
+        anyhow::bail!("couldn't detect the node's memory, set ORCH_NODE_MEMORY (MB)");
+    }
+    if capacity.cpu <= 0.0 {
+        anyhow::bail!("the node has no cpu, set ORCH_NODE_CPU");
+    }
+
+    let reserved = match std::env::var("ORCH_SYSTEM_RESERVED") {
+        Ok(reserved) => parse_resources(&reserved).map_err(|e| anyhow::anyhow!("ORCH_SYSTEM_RESERVED: {}", e))?,
+        Err(_) => Resources::default(),
+    };
+    let allocatable = allocatable(&capacity, &reserved).map_err(|e| anyhow::anyhow!("ORCH_SYSTEM_RESERVED: {}", e))?;
+
+    Ok(NodeResources { capacity, reserved, allocatable })
+}
+
+/// What's left for tasks once the reservation is taken out of the capacity.
+pub fn allocatable(capacity: &Resources, reserved: &Resources) -> Result<Resources, String> {
+    let allocatable = Resources {
+        cpu: capacity.cpu - reserved.cpu,
+        memory: capacity.memory - reserved.memory,
+        disk: capacity.disk - reserved.disk,
+    };
+
+    if allocatable.cpu <= 0.0 || allocatable.memory <= 0 || allocatable.disk < 0 {
+        return Err(format!(
+            "reserving cpu {}, memory {}MB, disk {}MB leaves nothing of cpu {}, memory {}MB, disk {}MB",
+            reserved.cpu, reserved.memory, reserved.disk, capacity.cpu, capacity.memory, capacity.disk
+        ));
+    }
+    Ok(allocatable)
+}
+
+/// Parses `cpu=0.5,memory=512,disk=1024`, memory and disk in MB. Missing ones are 0.
+pub fn parse_resources(input: &str) -> Result<Resources, String> {
+    let mut resources = Resources::default();
+
+    for pair in input.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (name, value) = pair
+            .split_once('=')
+            .ok_or_else(|| format!("invalid resource '{}', expected name=value", pair))?;
+        let invalid = || format!("invalid amount '{}' for {}", value, name);
+
+        match name.trim() {
+            "cpu" => resources.cpu = value.trim().parse().map_err(|_| invalid())?,
+            "memory" => resources.memory = value.trim().parse().map_err(|_| invalid())?,
+            "disk" => resources.disk = value.trim().parse().map_err(|_| invalid())?,
+            other => return Err(format!("unknown resource '{}', expected cpu, memory or disk", other)),
+        }
+    }
+
+    Ok(resources)
+}
+
+/// Detects the CPU, memory and disk of the host, limited by the worker's cgroup if it has one.
+pub fn detect() -> Resources {
+    let disk_path = if Path::new(DOCKER_ROOT).exists() { DOCKER_ROOT } else { "/" };
+
+    Resources {
+        cpu: detect_cpu(),
+        memory: detect_memory(),
+        disk: disk_size(disk_path).unwrap_or(0),
+    }
+}
+
+fn detect_cpu() -> f32 {
+    let cores = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1) as f32;
+    let cpu_max = read("/sys/fs/cgroup/cpu.max");
+    let cfs = read("/sys/fs/cgroup/cpu/cpu.cfs_quota_us").zip(read("/sys/fs/cgroup/cpu/cpu.cfs_period_us"));
+
+    cpu_limit(cores, cpu_max.as_deref(), cfs.as_ref().map(|(quota, period)| (quota.as_str(), period.as_str())))
+}
+
+fn detect_memory() -> i32 {
+    let meminfo = read("/proc/meminfo");
+    let limit = read("/sys/fs/cgroup/memory.max").or_else(|| read("/sys/fs/cgroup/memory/memory.limit_in_bytes"));
+
+    memory_limit(meminfo.as_deref(), limit.as_deref())
+}
+
+/// CPUs the worker may use: the host's cores, limited by the cgroup v2 `cpu.max` or else
+/// the v1 CFS quota and period.
+fn cpu_limit(cores: f32, cpu_max: Option<&str>, cfs: Option<(&str, &str)>) -> f32 {
+    // the quota can be a fraction of a core, which `available_parallelism` rounds away
+    let quota = cpu_max
+        .and_then(parse_cpu_max)
+        .or_else(|| cfs.and_then(|(quota, period)| parse_cfs_quota(quota, period)));
+
+    quota.map_or(cores, |quota| quota.min(cores))
+}
+
+/// Memory the worker may use in MB: `MemTotal` of `/proc/meminfo`, limited by the cgroup's
+/// memory limit. 0 if neither can be read.
+fn memory_limit(meminfo: Option<&str>, limit: Option<&str>) -> i32 {
+    let host = meminfo.and_then(parse_meminfo);
+    let limit = limit.and_then(parse_memory_limit);
+
+    let bytes = match (host, limit) {
+        (Some(host), Some(limit)) => host.min(limit),
+        (host, limit) => host.or(limit).unwrap_or(0),
+    };
+    (bytes / (1024 * 1024)).min(i32::MAX as u64) as i32
+}
+
+fn read(path: &str) -> Option<String> {
+    std::fs::read_to_string(path).ok()
+}
+
+/// cgroup v2 `cpu.max`: `<quota> <period>` in microseconds, or `max <period>` without a limit.
+fn parse_cpu_max(content: &str) -> Option<f32> {
+    let mut parts = content.split_whitespace();
+    let quota: f32 = parts.next()?.parse().ok()?;
+    let period: f32 = parts.next()?.parse().ok()?;
+    (period > 0.0).then_some(quota / period)
+}
+
+/// cgroup v1 CFS quota and period, a quota of `-1` means no limit.
+fn parse_cfs_quota(quota: &str, period: &str) -> Option<f32> {
+    let quota: f32 = quota.trim().parse().ok()?;
+    let period: f32 = period.trim().parse().ok()?;
+    (quota > 0.0 && period > 0.0).then_some(quota / period)
+}
+
+/// `MemTotal` of `/proc/meminfo` in bytes.
+fn parse_meminfo(content: &str) -> Option<u64> {
+    let line = content.lines().find(|l| l.starts_with("MemTotal:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+/// A cgroup memory limit in bytes, `max` (v2) means no limit.
+fn parse_memory_limit(content: &str) -> Option<u64> {
+    content.trim().parse().ok()
+}
+
+/// Size of the file system holding `path`, in MB.
+fn disk_size(path: &str) -> Option<i64> {
+    let path = CString::new(path).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `path` is a valid C string and `stat` a properly sized, writable statvfs
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    Some((stat.f_blocks as u64 * stat.f_frsize as u64 / (1024 * 1024)) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cgroup_limits() {
+        assert_eq!(parse_cpu_max("150000 100000\n"), Some(1.5));
+        assert_eq!(parse_cpu_max("max 100000\n"), None);
+        assert_eq!(parse_cfs_quota("50000\n", "100000\n"), Some(0.5));
+        assert_eq!(parse_cfs_quota("-1\n", "100000\n"), None);
+
+        assert_eq!(parse_memory_limit("536870912\n"), Some(512 * 1024 * 1024));
+        assert_eq!(parse_memory_limit("max\n"), None);
+        assert_eq!(parse_meminfo("MemTotal:       16318480 kB\nMemFree: 1 kB\n"), Some(16318480 * 1024));
+    }
+
+    #[test]
+    fn test_reservations() {
+        let capacity = Resources { cpu: 4.0, memory: 8192, disk: 100_000 };
+        let reserved = parse_resources("cpu=0.5, memory=1024").unwrap();
+        assert_eq!(
+            allocatable(&capacity, &reserved).unwrap(),
+            Resources { cpu: 3.5, memory: 7168, disk: 100_000 }
+        );
+
+        assert!(allocatable(&capacity, &parse_resources("memory=8192").unwrap()).is_err());
+        assert!(parse_resources("gpu=1").is_err());
+        assert!(parse_resources("cpu=lots").is_err());
+    }
+
+    #[test]
+    fn test_cpu_limit() {
+        assert_eq!(cpu_limit(8.0, None, None), 8.0);
+        assert_eq!(cpu_limit(8.0, Some("max 100000\n"), None), 8.0);
+        assert_eq!(cpu_limit(8.0, Some("150000 100000\n"), Some(("400000", "100000"))), 1.5);
+        assert_eq!(cpu_limit(8.0, None, Some(("50000\n", "100000\n"))), 0.5);
+        // a quota above the host's cores doesn't add any
+        assert_eq!(cpu_limit(2.0, Some("400000 100000\n"), None), 2.0);
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        let meminfo = "MemTotal:        8388608 kB\nMemFree:         1048576 kB\nMemAvailable:    4194304 kB\n";
+        assert_eq!(memory_limit(Some(meminfo), None), 8192);
+        assert_eq!(memory_limit(Some(meminfo), Some("max\n")), 8192);
+        assert_eq!(memory_limit(Some(meminfo), Some("2147483648\n")), 2048);
+        // v1 reports a huge number instead of "max" without a limit
+        assert_eq!(memory_limit(Some(meminfo), Some("9223372036854771712\n")), 8192);
+        assert_eq!(memory_limit(None, Some("1073741824\n")), 1024);
+        assert_eq!(memory_limit(Some("MemFree: 1 kB\n"), None), 0);
+        assert_eq!(memory_limit(None, None), 0);
+    }
+}
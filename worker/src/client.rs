@@ -0,0 +1,143 @@
+use uuid::Uuid;
+use common::{Heartbeat, Node, OrchError, TaskHealth, TaskStatus, UsageReport, WatchEvent};
+
+/// Thin wrapper around the manager's HTTP API as used by the worker.
+#[derive(Clone)]
+pub struct ManagerClient {
+    http: reqwest::Client,
+    base_url: String,
+}
+
+impl ManagerClient {
+    pub fn new(base_url: &str) -> Self {
+        ManagerClient {
+            http: reqwest::Client::new(),
+            base_url: base_url.to_string(),
+        }
+    }
+
+    /// Registers the node, repeated as the heartbeat that also carries the measured usage.
+    pub async fn register_node(&self, node: &Node, usage: Option<UsageReport>) -> Result<(), OrchError> {
+        let resp = self.http
+            .post(format!("{}/nodes", self.base_url))
+            .json(&Heartbeat { node: node.clone(), usage })
+            .send()
+            .await
+            .map_err(|e| OrchError::NetworkError(format!("Failed to register node: {}", e)))?;
+
+        if resp.status().is_success() {
+            return Ok(());
+        }
+
+        let code = resp.status();
+        match resp.json::<OrchError>().await {
+            Ok(err) => Err(err),
+            Err(_) => Err(OrchError::NetworkError(format!("Manager rejected node registration: {}", code))),
+        }
+    }
+
+    /// Opens the manager's change feed, resuming after `since` if given.
+    ///
+    /// Fails with `OrchError::ResourceVersionTooOld` if the manager can't resume from `since`,
+    /// the caller then has to start over without a version.
+    pub async fn watch(&self, since: Option<u64>) -> Result<WatchStream, OrchError> {
+        let url = match since {
+            Some(version) => format!("{}/watch?resourceVersion={}", self.base_url, version),
+            None => format!("{}/watch", self.base_url),
+        };
+
+        let resp = self.http
+            .get(url)
+            .send()
+            .await
+            .map_err(|e| OrchError::NetworkError(format!("Failed to watch: {}", e)))?;
+
+        if !resp.status().is_success() {
+            let code = resp.status();
+            return match resp.json::<OrchError>().await {
+                Ok(err) => Err(err),
+                Err(_) => Err(OrchError::NetworkError(format!("Manager rejected watch: {}", code))),
+            };
+        }
+
+        Ok(WatchStream { response: resp, buffer: Vec::new() })
+    }
+
+    /// Reports a status change of a task.
+    ///
+    /// If the manager rejects the transition, the returned `OrchError::InvalidTransition`
+    /// carries the task's current status so the caller can resynchronise.
+    pub async fn report_status(
+        &self,
+        task_id: Uuid,
+        status: TaskStatus,
+        container_id: Option<String>,
+        reason: Option<String>,
+    ) -> Result<(), OrchError> {
+        let resp = self.http
+            .put(format!("{}/tasks/{}/status", self.base_url, task_id))
+            .json(&serde_json::json!({
+                "status": status,
+                "container_id": container_id,
+                "reason": reason,
+            }))
+            .send()
+            .await
+            .map_err(|e| OrchError::NetworkError(format!("Failed to report status: {}", e)))?;
+
+        if resp.status().is_success() {
+            return Ok(());
+        }
+
+        let code = resp.status();
+        match resp.json::<OrchError>().await {
+            Ok(err) => Err(err),
+            Err(_) => Err(OrchError::NetworkError(format!("Manager rejected status update: {}", code))),
+        }
+    }
+
+    pub async fn report_health(&self, task_id: Uuid, health: &TaskHealth) -> Result<(), OrchError> {
+        let resp = self.http
+            .put(format!("{}/tasks/{}/health", self.base_url, task_id))
+            .json(health)
+            .send()
+            .await
+            .map_err(|e| OrchError::NetworkError(format!("Failed to report health: {}", e)))?;
+
+        if resp.status().is_success() {
+            return Ok(());
+        }
+
+        let code = resp.status();
+        match resp.json::<OrchError>().await {
+            Ok(err) => Err(err),
+            Err(_) => Err(OrchError::NetworkError(format!("Manager rejected health update: {}", code))),
+        }
+    }
+}
+
+/// The events of an open `GET /watch` response, one JSON document per line.
+pub struct WatchStream {
+    response: reqwest::Response,
+    buffer: Vec<u8>,
+}
+
+impl WatchStream {
+    /// Waits for the next event. `Ok(None)` means the manager closed the stream.
+    pub async fn next(&mut self) -> Result<Option<WatchEvent>, OrchError> {
+        loop {
+            if let Some(pos) = self.buffer.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = self.buffer.drain(..=pos).collect();
+                return serde_json::from_slice(&line)
+                    .map(Some)
+                    .map_err(|e| OrchError::NetworkError(format!("Failed to decode watch event: {}", e)));
+            }
+
+            match self.response.chunk().await {
+                Ok(Some(chunk)) => self.buffer.extend_from_slice(&chunk),
+                Ok(None) => return Ok(None),
+                Err(e) => return Err(OrchError::NetworkError(format!("Watch stream broke: {}", e))),
+            }
+        }
+    }
+}
@@ -1,15 +1,38 @@
 use std::collections::HashMap;
+use std::pin::Pin;
+use std::time::Duration;
 
 use bollard::Docker;
-use bollard::models::{Config, ContainerConfig, ContainerCreateBody};
-use bollard::query_parameters::{CreateContainerOptions, CreateImageOptions, RemoveContainerOptions, StartContainerOptions, StopContainerOptions};
+use bollard::exec::{CreateExecOptions, StartExecOptions, StartExecResults};
+use bollard::models::ContainerCreateBody;
+use bollard::query_parameters::{CreateContainerOptions, CreateImageOptions, InspectContainerOptions, ListContainersOptions, LogsOptions, RemoveContainerOptions, RestartContainerOptions, StartContainerOptions, StatsOptions, StopContainerOptions, WaitContainerOptions};
 use futures_util::StreamExt;
-use common::OrchError;
+use futures_util::stream::BoxStream;
+use tokio::io::AsyncWrite;
+use uuid::Uuid;
+use common::{OrchError, Usage};
+use crate::stats;
+
+/// Name of the container running the task, Docker accepts it wherever it takes a container id.
+pub fn container_name(task_id: &str) -> String {
+    format!("rust-orch-{}", task_id)
+}
+
+/// How long a stopped container gets to come back before its exit counts, see [`DockerClient::wait_exit`].
+const EXIT_SETTLE: Duration = Duration::from_secs(1);
 
 pub struct DockerClient {
     inner: Docker,
 }
 
+/// An attached `docker exec` session.
+pub struct ExecSession {
+    /// Combined stdout/stderr of the command
+    pub output: BoxStream<'static, Result<Vec<u8>, OrchError>>,
+    /// Stdin of the command, only meaningful if the session was created as interactive
+    pub input: Pin<Box<dyn AsyncWrite + Send>>,
+}
+
 impl DockerClient {
     pub async fn new() -> Result<Self, OrchError> {
         let docker = Docker::connect_with_socket_defaults()
@@ -23,7 +46,7 @@ impl DockerClient {
         image: &str,
         env: HashMap<String, String>,
     ) -> Result<String, OrchError> {
-        let container_name = format!("rust-orch-{}", task_id);
+        let container_name = container_name(task_id);
 
         // check if image exists locally, if not pull it.
 
@@ -84,11 +107,11 @@ impl DockerClient {
     }
 
     pub async fn stop_container(&self, container_id: &str) -> Result<(), OrchError> {
-        // Stop
-        self.inner
-            .stop_container(container_id, None::<StopContainerOptions>)
-            .await
-            .map_err(|e| OrchError::DockerError(format!("Failed to stop container: {}", e)))?;
+        // Stop, a container that already exited answers 304 and only needs removing
+        match self.inner.stop_container(container_id, None::<StopContainerOptions>).await {
+            Ok(()) | Err(bollard::errors::Error::DockerResponseServerError { status_code: 304, .. }) => {}
+            Err(e) => return Err(OrchError::DockerError(format!("Failed to stop container: {}", e))),
+        }
 
         // Remove
         self.inner
@@ -104,4 +127,217 @@ impl DockerClient {
         
         Ok(())
     }
+
+    /// Streams the stdout/stderr output of a container.
+    ///
+    /// `tail` is the number of lines from the end (or `"all"`), `since` a unix timestamp,
+    /// and `follow` keeps the stream open for new output.
+    pub fn logs(
+        &self,
+        container_id: &str,
+        tail: Option<String>,
+        since: Option<i32>,
+        follow: bool,
+    ) -> BoxStream<'static, Result<Vec<u8>, OrchError>> {
+        let options = LogsOptions {
+            follow,
+            stdout: true,
+            stderr: true,
+            since: since.unwrap_or(0),
+            tail: tail.unwrap_or_else(|| "all".to_string()),
+            ..Default::default()
+        };
+
+        self.inner
+            .logs(container_id, Some(options))
+            .map(|output| {
+                output
+                    .map(|o| o.into_bytes().to_vec())
+                    .map_err(|e| OrchError::DockerError(format!("Failed to read logs: {}", e)))
+            })
+            .boxed()
+    }
+
+    /// Runs a command inside a running container and attaches to it.
+    pub async fn exec(
+        &self,
+        container_id: &str,
+        command: Vec<String>,
+        interactive: bool,
+        tty: bool,
+    ) -> Result<ExecSession, OrchError> {
+        let config = CreateExecOptions {
+            cmd: Some(command),
+            attach_stdin: Some(interactive),
+            attach_stdout: Some(true),
+            attach_stderr: Some(true),
+            tty: Some(tty),
+            ..Default::default()
+        };
+
+        let exec = self.inner
+            .create_exec(container_id, config)
+            .await
+            .map_err(|e| OrchError::DockerError(format!("Failed to create exec: {}", e)))?;
+
+        let started = self.inner
+            .start_exec(&exec.id, Some(StartExecOptions { detach: false, tty, output_capacity: None }))
+            .await
+            .map_err(|e| OrchError::DockerError(format!("Failed to start exec: {}", e)))?;
+
+        match started {
+            StartExecResults::Attached { output, input } => Ok(ExecSession {
+                output: output
+                    .map(|o| {
+                        o.map(|o| o.into_bytes().to_vec())
+                            .map_err(|e| OrchError::DockerError(format!("Failed to read exec output: {}", e)))
+                    })
+                    .boxed(),
+                input,
+            }),
+            StartExecResults::Detached => Err(OrchError::DockerError("Exec unexpectedly detached".to_string())),
+        }
+    }
+
+    /// Returns the container's IP address on its first network, used to reach it from probes.
+    /// The name of the container, without Docker's leading `/`.
+    pub async fn container_name_of(&self, container_id: &str) -> Result<String, OrchError> {
+        let info = self.inner
+            .inspect_container(container_id, None::<InspectContainerOptions>)
+            .await
+            .map_err(|e| OrchError::DockerError(format!("Failed to inspect container: {}", e)))?;
+
+        Ok(info.name.unwrap_or_default().trim_start_matches('/').to_string())
+    }
+
+    pub async fn container_ip(&self, container_id: &str) -> Result<String, OrchError> {
+        let info = self.inner
+            .inspect_container(container_id, None::<InspectContainerOptions>)
+            .await
+            .map_err(|e| OrchError::DockerError(format!("Failed to inspect container: {}", e)))?;
+
+        info.network_settings
+            .and_then(|settings| settings.networks)
+            .and_then(|networks| networks.into_values().find_map(|n| n.ip_address.filter(|ip| !ip.is_empty())))
+            .ok_or_else(|| OrchError::DockerError(format!("Container {} has no IP address", container_id)))
+    }
+
+    /// Waits until the container stops and returns its exit code.
+    ///
+    /// Returns `None` if the container runs again by then, e.g. restarted by its liveness probe.
+    pub async fn wait_exit(&self, container_id: &str) -> Result<Option<i64>, OrchError> {
+        // the stream ends when the container stops, non-zero exit codes come back as errors
+        let mut wait = self.inner.wait_container(container_id, None::<WaitContainerOptions>);
+        while wait.next().await.is_some() {}
+
+        // a restart stops the container before starting it again
+        tokio::time::sleep(EXIT_SETTLE).await;
+        let info = self.inner
+            .inspect_container(container_id, None::<InspectContainerOptions>)
+            .await
+            .map_err(|e| OrchError::DockerError(format!("Failed to inspect container: {}", e)))?;
+
+        let state = info.state.unwrap_or_default();
+        if state.running == Some(true) || state.restarting == Some(true) {
+            return Ok(None);
+        }
+        Ok(Some(state.exit_code.unwrap_or(-1)))
+    }
+
+    pub async fn restart_container(&self, container_id: &str) -> Result<(), OrchError> {
+        self.inner
+            .restart_container(container_id, None::<RestartContainerOptions>)
+            .await
+            .map_err(|e| OrchError::DockerError(format!("Failed to restart container: {}", e)))
+    }
+
+    /// Ids of the tasks with a running container on this host, found by the container names.
+    pub async fn task_containers(&self) -> Result<Vec<Uuid>, OrchError> {
+        let options = ListContainersOptions {
+            filters: Some(HashMap::from([("name".to_string(), vec![container_name("")])])),
+            ..Default::default()
+        };
+
+        let containers = self.inner
+            .list_containers(Some(options))
+            .await
+            .map_err(|e| OrchError::DockerError(format!("Failed to list containers: {}", e)))?;
+
+        Ok(containers
+            .into_iter()
+            .flat_map(|c| c.names.unwrap_or_default())
+            .filter_map(|name| stats::task_id_of(&name))
+            .collect())
+    }
+
+    /// Current CPU and memory usage of the task's container.
+    pub async fn container_usage(&self, task_id: &Uuid) -> Result<Usage, OrchError> {
+        // not one-shot, so Docker waits for a second read to compute the CPU from
+        let options = StatsOptions { stream: false, one_shot: false };
+        let stats = self.inner
+            .stats(&container_name(&task_id.to_string()), Some(options))
+            .next()
+            .await
+            .ok_or_else(|| OrchError::DockerError("Docker returned no stats".to_string()))?
+            .map_err(|e| OrchError::DockerError(format!("Failed to read stats: {}", e)))?;
+
+        let total_usage = |cpu: &Option<bollard::models::ContainerCpuStats>| {
+            cpu.as_ref().and_then(|c| c.cpu_usage.as_ref()).and_then(|u| u.total_usage).unwrap_or(0)
+        };
+        let system_usage = |cpu: &Option<bollard::models::ContainerCpuStats>| {
+            cpu.as_ref().and_then(|c| c.system_cpu_usage).unwrap_or(0)
+        };
+        let online_cpus = stats.cpu_stats.as_ref().and_then(|c| c.online_cpus).unwrap_or(1);
+        let cpu = stats::container_cpu(
+            total_usage(&stats.cpu_stats).saturating_sub(total_usage(&stats.precpu_stats)),
+            system_usage(&stats.cpu_stats).saturating_sub(system_usage(&stats.precpu_stats)),
+            online_cpus,
+        );
+
+        let memory = stats.memory_stats.unwrap_or_default();
+        // cgroup v2 calls the page cache `inactive_file`, v1 `total_inactive_file`
+        let inactive_file = memory
+            .stats
+            .as_ref()
+            .and_then(|s| s.get("inactive_file").or_else(|| s.get("total_inactive_file")).copied())
+            .unwrap_or(0);
+
+        Ok(Usage {
+            cpu,
+            memory: stats::container_memory(memory.usage.unwrap_or(0), inactive_file),
+        })
+    }
+
+    /// Runs a command inside the container to completion and returns its exit code.
+    pub async fn exec_exit_code(&self, container_id: &str, command: Vec<String>) -> Result<i64, OrchError> {
+        let exec = self.inner
+            .create_exec(
+                container_id,
+                CreateExecOptions {
+                    cmd: Some(command),
+                    attach_stdout: Some(true),
+                    attach_stderr: Some(true),
+                    ..Default::default()
+                },
+            )
+            .await
+            .map_err(|e| OrchError::DockerError(format!("Failed to create exec: {}", e)))?;
+
+        // drain the output, the command has finished once the stream ends
+        if let StartExecResults::Attached { mut output, .. } = self.inner
+            .start_exec(&exec.id, None::<StartExecOptions>)
+            .await
+            .map_err(|e| OrchError::DockerError(format!("Failed to start exec: {}", e)))?
+        {
+            while output.next().await.is_some() {}
+        }
+
+        let inspect = self.inner
+            .inspect_exec(&exec.id)
+            .await
+            .map_err(|e| OrchError::DockerError(format!("Failed to inspect exec: {}", e)))?;
+
+        inspect.exit_code
+            .ok_or_else(|| OrchError::DockerError("Exec finished without exit code".to_string()))
+    }
 }
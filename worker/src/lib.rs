@@ -1,3 +1,9 @@
+pub mod api;
+pub mod capacity;
+pub mod client;
 pub mod docker;
+pub mod probe;
+pub mod stats;
 
-pub use docker::DockerClient;
+pub use client::{ManagerClient, WatchStream};
+pub use docker::{DockerClient, ExecSession};
@@ -1,9 +1,11 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
 use std::time::Duration;
+use tokio::task::JoinHandle;
 use tokio::time::sleep;
 use uuid::Uuid;
-use common::{Task, TaskStatus};
-use worker::DockerClient;
+use common::{EventType, Node, NodeStatus, OrchError, Task, TaskHealth, TaskStatus, WatchObject};
+use worker::{DockerClient, ManagerClient};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -12,44 +14,307 @@ async fn main() -> anyhow::Result<()> {
     println!("Starting Worker {node_id}");
 
     // initialize docker client
-    let docker = DockerClient::new().await?;
+    let docker = Arc::new(DockerClient::new().await?);
     println!("Worker: Connected to Docker Daemon");
-    let http_client = reqwest::Client::new();
+    let manager = ManagerClient::new(manager_url);
+
+    let worker::capacity::NodeResources { capacity, reserved, allocatable } = worker::capacity::configure()?;
+    println!(
+        "Worker: capacity cpu {}, memory {}MB, disk {}MB, allocatable cpu {}, memory {}MB",
+        capacity.cpu, capacity.memory, capacity.disk, allocatable.cpu, allocatable.memory
+    );
+
+    let mut node = Node::new(node_id.to_string(), allocatable.memory, allocatable.cpu);
+    node.capacity = capacity;
+    node.reserved = reserved;
+    node.status = NodeStatus::Ready;
+    // e.g. ORCH_NODE_LABELS="disk=ssd,zone=eu-1"
+    if let Ok(labels) = std::env::var("ORCH_NODE_LABELS") {
+        node.labels = common::labels::parse_labels(&labels).map_err(|e| anyhow::anyhow!("ORCH_NODE_LABELS: {}", e))?;
+    }
+    // e.g. ORCH_NODE_TAINTS="dedicated=db:NoSchedule,gpu:PreferNoSchedule", only used on first registration
+    if let Ok(taints) = std::env::var("ORCH_NODE_TAINTS") {
+        node.taints = taints
+            .split(',')
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map_err(|e| anyhow::anyhow!("ORCH_NODE_TAINTS: {}", e))?;
+    }
+    // serve logs for the manager to relay
+    let api_docker = Arc::clone(&docker);
+    let api_addr = node.api_address();
+    let assigned: worker::api::AssignedTasks = Arc::new(RwLock::new(HashSet::new()));
+    let api_assigned = Arc::clone(&assigned);
+    tokio::spawn(async move {
+        if let Err(e) = worker::api::serve(api_addr, api_docker, api_assigned).await {
+            eprintln!("Worker: API server error: {}", e);
+        }
+    });
+
+    // (re-)register periodically so the manager knows about us even after a restart
+    let heartbeat_manager = manager.clone();
+    let usage = worker::stats::spawn_sampler(Arc::clone(&docker), Duration::from_secs(5));
+    tokio::spawn(async move {
+        loop {
+            let report = usage.borrow().clone();
+            if let Err(e) = heartbeat_manager.register_node(&node, report).await {
+                eprintln!("Worker: {}", e);
+            }
+            sleep(Duration::from_secs(5)).await;
+        }
+    });
+
+    // tasks we already picked up, so a replayed event doesn't start a second container
+    let mut started: HashMap<Uuid, JoinHandle<()>> = HashMap::new();
+    // tasks being rescheduled we already stop, for the same reason
+    let mut handed_back: HashSet<Uuid> = HashSet::new();
+    let mut last_version: Option<u64> = None;
 
     loop {
-        let resp = http_client.get(format!("{}/tasks", &manager_url)).send().await?;
-        let tasks = resp.json::<Vec<Task>>().await?;
-        let worker_tasks = tasks
-          .into_iter()
-          .filter(|t| t.node_id.as_deref() == Some(node_id))
-          .collect::<Vec<Task>>();
-        
-        println!("Worker: tasks len: {}", worker_tasks.len());
-       
-        for task in worker_tasks {
-           match docker.start_container(&task.id.to_string(), &task.image, HashMap::new()).await {
-               Ok(container_id) => {
-                   let update_payload = serde_json::json!({
-                       "status": TaskStatus::Running,
-                       "container_id": Some(container_id.to_string()),
-                   });
-
-                   let _ = http_client.put(format!("{}/tasks/{}/status", manager_url, task.id.to_string()))
-                       .json(&update_payload)
-                       .send()
-                       .await;
-                   
-                   println!("Worker: Successfully started container {}", container_id);
-               },
-               Err(e) => {
-                    eprintln!("Worker: Error starting container: {}", e);
-                    let _ = http_client.put(format!("{}/tasks/{}/status", manager_url, task.id.to_string()))
-                        .json(&serde_json::json!({"status": TaskStatus::Failed, "container_id": None::<String>}))
-                        .send()
-                        .await;
-               }
-           };
-       }
-        sleep(Duration::from_secs(5)).await;
+        let mut watch = match manager.watch(last_version).await {
+            Ok(watch) => watch,
+            Err(OrchError::ResourceVersionTooOld(version)) => {
+                println!("Worker: version {} is gone, watching from scratch", version);
+                last_version = None;
+                continue;
+            }
+            Err(e) => {
+                eprintln!("Worker: {}", e);
+                sleep(Duration::from_secs(5)).await;
+                continue;
+            }
+        };
+
+        loop {
+            let event = match watch.next().await {
+                Ok(Some(event)) => event,
+                Ok(None) => break,
+                Err(e) => {
+                    eprintln!("Worker: {}", e);
+                    break;
+                }
+            };
+            last_version = Some(event.resource_version);
+
+            let WatchObject::Task(task) = event.object else {
+                continue;
+            };
+
+            {
+                let mut assigned = assigned.write().unwrap_or_else(|e| e.into_inner());
+                if event.event_type != EventType::Deleted && task.node_id.as_deref() == Some(node_id) {
+                    assigned.insert(task.id);
+                } else {
+                    assigned.remove(&task.id);
+                }
+            }
+
+            if event.event_type == EventType::Deleted {
+                let handle = started.remove(&task.id);
+                if task.node_id.as_deref() == Some(node_id) {
+                    stop_task(&task, handle, &docker).await;
+                }
+                continue;
+            }
+
+            if task.status.is_terminal() {
+                let handle = started.remove(&task.id);
+                // evicted by the manager, e.g. for a NoExecute taint, so it must not keep running
+                if task.is_evicted() && task.node_id.as_deref() == Some(node_id) {
+                    stop_task(&task, handle, &docker).await;
+                }
+                continue;
+            }
+
+            // being rescheduled, the manager places it elsewhere once its container stopped
+            if task.rescheduling.is_some() && task.node_id.as_deref() == Some(node_id) {
+                if handed_back.insert(task.id) {
+                    let handle = started.remove(&task.id);
+                    tokio::spawn(hand_back_task(*task, handle, Arc::clone(&docker), manager.clone()));
+                }
+                continue;
+            }
+            handed_back.remove(&task.id);
+
+            // moved off this node without waiting for us, e.g. its grace period ran out
+            if task.node_id.as_deref() != Some(node_id)
+                && let Some(handle) = started.remove(&task.id)
+            {
+                tokio::spawn(release_task(task.id, handle, Arc::clone(&docker)));
+                continue;
+            }
+
+            if task.status == TaskStatus::Scheduled
+                && task.node_id.as_deref() == Some(node_id)
+                && !started.contains_key(&task.id)
+            {
+                let id = task.id;
+                started.insert(id, tokio::spawn(run_task(*task, Arc::clone(&docker), manager.clone())));
+            }
+        }
+
+        // the stream ended, resume right after the last event we saw
+        println!("Worker: watch closed, resuming after version {:?}", last_version);
+        sleep(Duration::from_secs(1)).await;
+    }
+}
+
+/// Starts the task's container and reports the outcome to the manager.
+async fn run_task(task: Task, docker: Arc<DockerClient>, manager: ManagerClient) {
+    match docker.start_container(&task.id.to_string(), &task.image, task.env.clone()).await {
+        Ok(container_id) => {
+            match manager.report_status(task.id, TaskStatus::Running, Some(container_id.clone()), None).await {
+                Ok(()) => {}
+                // the task moved on while we were starting it (e.g. it was failed), so it must not keep running
+                Err(OrchError::InvalidTransition { current, .. }) => {
+                    println!("Worker: task {} is {:?} on the manager, stopping its container", task.id, current);
+                    if let Err(e) = docker.stop_container(&container_id).await {
+                        eprintln!("Worker: {}", e);
+                    }
+                    return;
+                }
+                // deleted while we were starting it
+                Err(OrchError::TaskNotFound(_)) => {
+                    println!("Worker: task {} was deleted, stopping its container", task.id);
+                    if let Err(e) = docker.stop_container(&container_id).await {
+                        eprintln!("Worker: {}", e);
+                    }
+                    return;
+                }
+                Err(e) => eprintln!("Worker: {}", e),
+            }
+
+            println!("Worker: Successfully started container {}", container_id);
+            keep_running(&task, &container_id, &docker, &manager).await;
+        }
+        Err(e) => {
+            eprintln!("Worker: Error starting container: {}", e);
+            if let Err(e) = manager.report_status(task.id, TaskStatus::Failed, None, Some(e.to_string())).await {
+                eprintln!("Worker: {}", e);
+            }
+        }
+    }
+}
+
+/// Why a task's container stopped serving.
+enum Stop {
+    Exited(i64),
+    Unhealthy(String),
+}
+
+/// Watches the started container, restarting it after it exits or fails its liveness probe
+/// until the task's restart policy says the task is done.
+///
+/// Restarts wait longer the more of them there were. The task ends `Complete` when its last
+/// container exited with code 0 and `Failed` otherwise. The container is kept so its logs stay
+/// readable, it's removed when the task is deleted.
+async fn keep_running(task: &Task, container_id: &str, docker: &DockerClient, manager: &ManagerClient) {
+    let mut restart_count = 0;
+    loop {
+        // a container that exits ends its probes, a liveness probe that trips ends the wait
+        let stop = tokio::select! {
+            message = worker::probe::supervise(task, container_id, docker, manager, restart_count) => Stop::Unhealthy(message),
+            code = wait_exit(task.id, container_id, docker) => match code {
+                Some(code) => Stop::Exited(code),
+                None => return,
+            },
+        };
+        let (failed, reason) = match &stop {
+            Stop::Exited(code) => (*code != 0, format!("Exited with code {}", code)),
+            Stop::Unhealthy(message) => (true, format!("Liveness probe failed: {}", message)),
+        };
+        println!("Worker: task {}: {}", task.id, reason);
+
+        if !task.restart_policy.restarts(failed) {
+            if let Stop::Unhealthy(_) = stop
+                && let Err(e) = docker.stop_container(container_id).await
+            {
+                eprintln!("Worker: {}", e);
+            }
+            let status = if failed { TaskStatus::Failed } else { TaskStatus::Complete };
+            if let Err(e) = manager.report_status(task.id, status, None, Some(reason)).await {
+                eprintln!("Worker: {}", e);
+            }
+            return;
+        }
+
+        restart_count += 1;
+        let delay = worker::probe::restart_delay(restart_count);
+        println!("Worker: restarting task {} in {}s", task.id, delay.as_secs());
+        sleep(delay).await;
+        if let Err(e) = docker.restart_container(container_id).await {
+            eprintln!("Worker: giving up on task {}: {}", task.id, e);
+            if let Err(e) = manager.report_status(task.id, TaskStatus::Failed, None, Some(e.to_string())).await {
+                eprintln!("Worker: {}", e);
+            }
+            return;
+        }
+
+        // the restarted container starts from scratch, so do its probes
+        let health = TaskHealth { restart_count, message: Some(reason), ..TaskHealth::default() };
+        worker::probe::report_health(manager, task, &health).await;
+    }
+}
+
+/// The exit code of the container once it stopped for good, a restart doesn't count.
+async fn wait_exit(task_id: Uuid, container_id: &str, docker: &DockerClient) -> Option<i64> {
+    loop {
+        match docker.wait_exit(container_id).await {
+            Ok(Some(code)) => return Some(code),
+            Ok(None) => continue,
+            Err(e) => {
+                eprintln!("Worker: no longer watching task {} for its exit: {}", task_id, e);
+                return None;
+            }
+        }
+    }
+}
+
+/// Stops a deleted or evicted task: its probes are cancelled and its container removed.
+///
+/// If the container isn't known yet, `run_task` is left alone, it stops the container
+/// itself once the manager rejects its status report.
+async fn stop_task(task: &Task, handle: Option<JoinHandle<()>>, docker: &DockerClient) {
+    let Some(container_id) = &task.container_id else {
+        return;
     };
-}
\ No newline at end of file
+
+    if let Some(handle) = handle {
+        handle.abort();
+    }
+    println!("Worker: task {} is gone from this node, stopping container {}", task.id, container_id);
+    if let Err(e) = docker.stop_container(container_id).await {
+        eprintln!("Worker: {}", e);
+    }
+}
+
+/// Stops the container of a task that is being rescheduled, then hands the task back to the manager.
+///
+/// A task whose container never started is handed back right away.
+async fn hand_back_task(task: Task, handle: Option<JoinHandle<()>>, docker: Arc<DockerClient>, manager: ManagerClient) {
+    if handle.is_some() || task.container_id.is_some() {
+        if let Some(handle) = handle {
+            handle.abort();
+        }
+        println!("Worker: task {} is rescheduled, stopping its container", task.id);
+        if let Err(e) = docker.stop_container(&worker::docker::container_name(&task.id.to_string())).await {
+            eprintln!("Worker: {}", e);
+        }
+    }
+    if let Err(e) = manager.report_status(task.id, TaskStatus::Pending, None, Some("Container stopped".to_string())).await {
+        eprintln!("Worker: {}", e);
+    }
+}
+
+/// Stops a task that was moved to another node, giving its container the usual grace period.
+///
+/// The manager already forgot the container id, so the container is found by its name.
+async fn release_task(task_id: Uuid, handle: JoinHandle<()>, docker: Arc<DockerClient>) {
+    handle.abort();
+    println!("Worker: task {} moved off this node, stopping its container", task_id);
+    if let Err(e) = docker.stop_container(&worker::docker::container_name(&task_id.to_string())).await {
+        eprintln!("Worker: {}", e);
+    }
+}
@@ -0,0 +1,216 @@
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::time::{sleep_until, timeout, Instant};
+use common::{HealthStatus, Probe, ProbeAction, Task, TaskHealth};
+use crate::{DockerClient, ManagerClient};
+
+/// Counts consecutive probe results and flips the status once a threshold is reached.
+pub struct ProbeTracker {
+    success_threshold: u32,
+    failure_threshold: u32,
+    successes: u32,
+    failures: u32,
+    status: HealthStatus,
+}
+
+impl ProbeTracker {
+    pub fn new(probe: &Probe) -> Self {
+        ProbeTracker {
+            success_threshold: probe.success_threshold.max(1),
+            failure_threshold: probe.failure_threshold.max(1),
+            successes: 0,
+            failures: 0,
+            status: HealthStatus::Unknown,
+        }
+    }
+
+    /// Records a probe result, returns the new status if it changed.
+    pub fn record(&mut self, success: bool) -> Option<HealthStatus> {
+        let next = if success {
+            self.successes += 1;
+            self.failures = 0;
+            (self.successes >= self.success_threshold).then_some(HealthStatus::Healthy)
+        } else {
+            self.failures += 1;
+            self.successes = 0;
+            (self.failures >= self.failure_threshold).then_some(HealthStatus::Unhealthy)
+        };
+
+        match next {
+            Some(status) if status != self.status => {
+                self.status = status.clone();
+                Some(status)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Executes a probe once. The error describes why the probe failed.
+pub async fn run_probe(docker: &DockerClient, container_id: &str, probe: &Probe) -> Result<(), String> {
+    let limit = Duration::from_secs(probe.timeout_secs.max(1));
+
+    let check = async {
+        match &probe.action {
+            ProbeAction::Http { path, port } => {
+                let ip = docker.container_ip(container_id).await.map_err(|e| e.to_string())?;
+                let url = format!("http://{}:{}{}", ip, port, path);
+                let resp = reqwest::get(&url).await.map_err(|e| format!("GET {} failed: {}", url, e))?;
+                let status = resp.status();
+                if status.is_success() || status.is_redirection() {
+                    Ok(())
+                } else {
+                    Err(format!("GET {} returned {}", url, status))
+                }
+            }
+            ProbeAction::Tcp { port } => {
+                let ip = docker.container_ip(container_id).await.map_err(|e| e.to_string())?;
+                TcpStream::connect((ip.as_str(), *port))
+                    .await
+                    .map(|_| ())
+                    .map_err(|e| format!("connect to {}:{} failed: {}", ip, port, e))
+            }
+            ProbeAction::Exec { command } => {
+                match docker.exec_exit_code(container_id, command.clone()).await {
+                    Ok(0) => Ok(()),
+                    Ok(code) => Err(format!("{:?} exited with {}", command, code)),
+                    Err(e) => Err(e.to_string()),
+                }
+            }
+        }
+    };
+
+    timeout(limit, check)
+        .await
+        .unwrap_or_else(|_| Err(format!("probe timed out after {}s", limit.as_secs())))
+}
+
+/// A probe together with its tracker and the time it's due next.
+struct Scheduled<'a> {
+    probe: &'a Probe,
+    tracker: ProbeTracker,
+    due: Instant,
+}
+
+impl<'a> Scheduled<'a> {
+    fn new(probe: &'a Probe, started: Instant) -> Self {
+        Scheduled {
+            probe,
+            tracker: ProbeTracker::new(probe),
+            due: started + Duration::from_secs(probe.initial_delay_secs),
+        }
+    }
+
+    fn period(&self) -> Duration {
+        Duration::from_secs(self.probe.period_secs.max(1))
+    }
+}
+
+/// First delay before a stopped container is started again, doubled with every further restart.
+const RESTART_DELAY: Duration = Duration::from_secs(10);
+
+/// Longest delay before a restart, keeps a crash-looping container from hogging the node.
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(300);
+
+/// How long to wait before the `restarts`th restart of a task's container.
+pub fn restart_delay(restarts: u32) -> Duration {
+    let doublings = restarts.saturating_sub(1).min(16);
+    RESTART_DELAY.saturating_mul(1 << doublings).min(MAX_RESTART_DELAY)
+}
+
+/// Runs the task's liveness and readiness probes on a freshly started container.
+///
+/// Health changes are reported to the manager, `restart_count` included. Returns why the
+/// liveness probe tripped, the caller then restarts the container or ends the task as its
+/// restart policy says. Never returns for a task without a liveness probe.
+pub async fn supervise(task: &Task, container_id: &str, docker: &DockerClient, manager: &ManagerClient, restart_count: u32) -> String {
+    let started = Instant::now();
+    let mut liveness = task.liveness_probe.as_ref().map(|p| Scheduled::new(p, started));
+    let mut readiness = task.readiness_probe.as_ref().map(|p| Scheduled::new(p, started));
+    let mut health = TaskHealth { restart_count, ..TaskHealth::default() };
+
+    loop {
+        let Some(next) = [&liveness, &readiness].into_iter().flatten().map(|s| s.due).min() else {
+            return std::future::pending().await;
+        };
+        sleep_until(next).await;
+
+        let mut changed = false;
+
+        if let Some(readiness) = readiness.as_mut().filter(|s| s.due <= Instant::now()) {
+            let result = run_probe(docker, container_id, readiness.probe).await;
+            readiness.due = Instant::now() + readiness.period();
+            if let Some(status) = readiness.tracker.record(result.is_ok()) {
+                health.ready = status;
+                health.message = result.err();
+                changed = true;
+            }
+        }
+
+        if let Some(live) = liveness.as_mut().filter(|s| s.due <= Instant::now()) {
+            let result = run_probe(docker, container_id, live.probe).await;
+            live.due = Instant::now() + live.period();
+            if let Some(status) = live.tracker.record(result.is_ok()) {
+                health.live = status.clone();
+                health.message = result.err();
+                changed = true;
+
+                if status == HealthStatus::Unhealthy {
+                    report_health(manager, task, &health).await;
+                    return health.message.unwrap_or_else(|| "unknown".to_string());
+                }
+            }
+        }
+
+        if changed {
+            report_health(manager, task, &health).await;
+        }
+    }
+}
+
+pub async fn report_health(manager: &ManagerClient, task: &Task, health: &TaskHealth) {
+    if let Err(e) = manager.report_health(task.id, health).await {
+        eprintln!("Worker: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tracker_flips_after_thresholds() {
+        let mut probe = Probe::new(ProbeAction::Tcp { port: 80 });
+        probe.success_threshold = 2;
+        probe.failure_threshold = 3;
+        let mut tracker = ProbeTracker::new(&probe);
+
+        assert_eq!(tracker.record(true), None);
+        assert_eq!(tracker.record(true), Some(HealthStatus::Healthy));
+        assert_eq!(tracker.record(true), None);
+
+        assert_eq!(tracker.record(false), None);
+        assert_eq!(tracker.record(false), None);
+        assert_eq!(tracker.record(false), Some(HealthStatus::Unhealthy));
+    }
+
+    #[test]
+    fn test_restart_delay_doubles_up_to_the_limit() {
+        assert_eq!(restart_delay(1), Duration::from_secs(10));
+        assert_eq!(restart_delay(3), Duration::from_secs(40));
+        assert_eq!(restart_delay(6), Duration::from_secs(300));
+        assert_eq!(restart_delay(u32::MAX), Duration::from_secs(300));
+    }
+
+    #[test]
+    fn test_tracker_success_resets_failures() {
+        let mut tracker = ProbeTracker::new(&Probe::new(ProbeAction::Tcp { port: 80 }));
+
+        tracker.record(false);
+        tracker.record(false);
+        tracker.record(true);
+        assert_eq!(tracker.record(false), None);
+        assert_eq!(tracker.record(false), None);
+        assert_eq!(tracker.record(false), Some(HealthStatus::Unhealthy));
+    }
+}
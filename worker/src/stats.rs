@@ -0,0 +1,175 @@
+use std::sync::Arc;
+use std::time::Duration;
+use futures_util::future::join_all;
+use tokio::sync::watch;
+use uuid::Uuid;
+use common::{TaskUsage, Usage, UsageReport};
+use crate::DockerClient;
+
+/// Measures the node's CPU from the difference between two reads of `/proc/stat`.
+#[derive(Default)]
+pub struct NodeSampler {
+    last: Option<CpuTimes>,
+}
+
+/// Jiffies spent by all CPUs since boot.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct CpuTimes {
+    busy: u64,
+    total: u64,
+    cores: usize,
+}
+
+impl NodeSampler {
+    /// Usage of the whole host. The first call reports no CPU, there's nothing to compare to yet.
+    pub fn sample(&mut self) -> Usage {
+        let times = std::fs::read_to_string("/proc/stat").ok().and_then(|stat| parse_proc_stat(&stat));
+        let cpu = match (self.last, times) {
+            (Some(last), Some(times)) => cpu_between(&last, &times),
+            _ => 0.0,
+        };
+        self.last = times;
+
+        let memory = std::fs::read_to_string("/proc/meminfo")
+            .ok()
+            .and_then(|meminfo| parse_memory_used(&meminfo))
+            .unwrap_or(0);
+
+        Usage { cpu, memory }
+    }
+}
+
+/// Measures the node and every task container on it for the next heartbeat.
+pub async fn collect(docker: &DockerClient, sampler: &mut NodeSampler) -> UsageReport {
+    let tasks = match docker.task_containers().await {
+        Ok(tasks) => tasks,
+        Err(e) => {
+            eprintln!("Worker: {}", e);
+            Vec::new()
+        }
+    };
+
+    // each stats call waits for a second sample from Docker, so they run side by side
+    let usages = join_all(tasks.iter().map(|task_id| docker.container_usage(task_id))).await;
+    let tasks = tasks
+        .into_iter()
+        .zip(usages)
+        .filter_map(|(task_id, usage)| match usage {
+            Ok(usage) => Some(TaskUsage { task_id, usage }),
+            // stopped in the meantime
+            Err(_) => None,
+        })
+        .collect();
+
+    UsageReport {
+        timestamp: chrono::Utc::now(),
+        node: sampler.sample(),
+        tasks,
+    }
+}
+
+/// Collects a report every `interval` in the background, the receiver holds the latest one.
+///
+/// Docker can take a while to answer the stats calls, so the heartbeats send the last
+/// finished report instead of waiting for a fresh one.
+pub fn spawn_sampler(docker: Arc<DockerClient>, interval: Duration) -> watch::Receiver<Option<UsageReport>> {
+    let (sender, receiver) = watch::channel(None);
+    tokio::spawn(async move {
+        let mut sampler = NodeSampler::default();
+        loop {
+            let report = collect(&docker, &mut sampler).await;
+            if sender.send(Some(report)).is_err() {
+                return;
+            }
+            tokio::time::sleep(interval).await;
+        }
+    });
+    receiver
+}
+
+/// The task id in the name Docker reports for a task container, e.g. `/rust-orch-<uuid>`.
+pub fn task_id_of(container_name: &str) -> Option<Uuid> {
+    let name = container_name.trim_start_matches('/');
+    Uuid::parse_str(name.strip_prefix("rust-orch-")?).ok()
+}
+
+/// Cores in use from the CPU time the container and the whole host consumed between two reads.
+pub fn container_cpu(cpu_delta: u64, system_delta: u64, online_cpus: u32) -> f32 {
+    if system_delta == 0 {
+        return 0.0;
+    }
+    (cpu_delta as f64 / system_delta as f64 * online_cpus as f64) as f32
+}
+
+/// Memory of a container in MB without the page cache it could give back, like `docker stats`.
+pub fn container_memory(usage: u64, inactive_file: u64) -> i32 {
+    to_mb(usage.saturating_sub(inactive_file))
+}
+
+fn to_mb(bytes: u64) -> i32 {
+    (bytes / (1024 * 1024)).min(i32::MAX as u64) as i32
+}
+
+/// The aggregate `cpu` line of `/proc/stat`, iowait counts as idle.
+fn parse_proc_stat(content: &str) -> Option<CpuTimes> {
+    let line = content.lines().find(|l| l.starts_with("cpu "))?;
+    // user nice system idle iowait irq softirq steal, guest time is already part of user
+    let fields: Vec<u64> = line.split_whitespace().skip(1).take(8).map(|f| f.parse().ok()).collect::<Option<_>>()?;
+    if fields.len() < 5 {
+        return None;
+    }
+
+    let total: u64 = fields.iter().sum();
+    let cores = content
+        .lines()
+        .filter(|l| l.starts_with("cpu") && l.as_bytes().get(3).is_some_and(u8::is_ascii_digit))
+        .count();
+    Some(CpuTimes { busy: total - fields[3] - fields[4], total, cores: cores.max(1) })
+}
+
+fn cpu_between(last: &CpuTimes, now: &CpuTimes) -> f32 {
+    let total = now.total.saturating_sub(last.total);
+    if total == 0 {
+        return 0.0;
+    }
+    (now.busy.saturating_sub(last.busy) as f64 / total as f64 * now.cores as f64) as f32
+}
+
+/// `MemTotal - MemAvailable` of `/proc/meminfo` in MB.
+fn parse_memory_used(content: &str) -> Option<i32> {
+    let field = |name: &str| -> Option<u64> {
+        let line = content.lines().find(|l| l.starts_with(name))?;
+        line.split_whitespace().nth(1)?.parse().ok()
+    };
+    let used_kb = field("MemTotal:")?.saturating_sub(field("MemAvailable:")?);
+    Some(to_mb(used_kb * 1024))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_node_usage_from_proc() {
+        let before = "cpu  100 0 100 800 0 0 0 0 0 0\ncpu0 50 0 50 400 0 0 0 0 0 0\ncpu1 50 0 50 400 0 0 0 0 0 0\n";
+        let after = "cpu  250 0 150 1000 0 0 0 0 0 0\ncpu0 125 0 75 500 0 0 0 0 0 0\ncpu1 125 0 75 500 0 0 0 0 0 0\n";
+        let (before, after) = (parse_proc_stat(before).unwrap(), parse_proc_stat(after).unwrap());
+        assert_eq!(after.cores, 2);
+        // 200 of 400 jiffies busy on 2 cores
+        assert_eq!(cpu_between(&before, &after), 1.0);
+
+        let meminfo = "MemTotal:       8388608 kB\nMemFree:         1048576 kB\nMemAvailable:    4194304 kB\n";
+        assert_eq!(parse_memory_used(meminfo), Some(4096));
+    }
+
+    #[test]
+    fn test_container_usage() {
+        assert_eq!(container_cpu(50, 200, 4), 1.0);
+        assert_eq!(container_cpu(50, 0, 4), 0.0);
+        assert_eq!(container_memory(300 * 1024 * 1024, 100 * 1024 * 1024), 200);
+
+        let id = Uuid::new_v4();
+        assert_eq!(task_id_of(&format!("/rust-orch-{}", id)), Some(id));
+        assert_eq!(task_id_of("/postgres"), None);
+    }
+}